@@ -0,0 +1,13 @@
+#!/bin/sh
+# Grant the launcher helper CAP_NET_ADMIN so openvpn can run without pkexec.
+# If setcap is unavailable the app falls back to pkexec and the polkit policy.
+HELPER=/usr/lib/rbw-vpn-client/rbw-vpn-helper
+
+if [ -f "$HELPER" ]; then
+    chmod 0755 "$HELPER"
+    if command -v setcap >/dev/null 2>&1; then
+        setcap cap_net_admin+ep "$HELPER" || true
+    fi
+fi
+
+exit 0
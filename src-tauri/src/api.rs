@@ -1,9 +1,17 @@
+use crate::settings::Settings;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use base64::{Engine as _, engine::general_purpose};
+use rand::Rng;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::sync::RwLock;
 use std::time::Duration;
 
-const API_BASE: &str = "https://ovpn.rbwtech.io/api";
+const DEFAULT_API_BASE: &str = "https://ovpn.rbwtech.io/api";
+
+const MAX_RETRIES: u32 = 3;
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
 
 fn default_server() -> String {
     "sg".to_string()
@@ -17,7 +25,7 @@ pub struct VerifyResponse {
     pub server_location: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Server {
     pub code: String,
     pub name: String,
@@ -36,30 +44,149 @@ pub struct GenerateRequest {
     pub expiry_days: Option<i32>,
 }
 
+/// Identifies the portal account to revoke. Synced profiles have a config ID,
+/// profiles from `/generate` only the account they were created for.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "by", rename_all = "snake_case")]
+pub enum RevokeTarget {
+    Config { id: String },
+    Account { username: String, server_code: String },
+}
+
+fn default_status() -> String {
+    "active".to_string()
+}
+
+fn string_or_number<'de, D>(deserializer: D) -> std::result::Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::String(s) => Ok(s),
+        serde_json::Value::Number(n) => Ok(n.to_string()),
+        other => Err(serde::de::Error::custom(format!("invalid id: {}", other))),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteConfig {
+    #[serde(deserialize_with = "string_or_number")]
+    pub id: String,
+    pub name: String,
+    pub server_code: String,
+    pub protocol: String,
+    #[serde(default)]
+    pub content_hash: Option<String>,
+    #[serde(default = "default_status")]
+    pub status: String,
+    #[serde(default)]
+    pub expires_at: Option<String>,
+}
+
+pub enum ServerListFetch {
+    NotModified,
+    Fresh {
+        servers: Vec<Server>,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    },
+}
+
 pub struct ApiClient {
-    client: reqwest::Client,
+    client: RwLock<reqwest::Client>,
+    base_url: RwLock<String>,
 }
 
 impl ApiClient {
-    pub fn new() -> Self {
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(20)) 
-            .connect_timeout(Duration::from_secs(10)) 
-            .build()
-            .unwrap();
-        
-        Self { client }
+    pub fn new(settings: &Settings) -> Result<Self> {
+        Ok(Self {
+            client: RwLock::new(Self::build_client(settings)?),
+            base_url: RwLock::new(Self::base_url(settings)),
+        })
     }
 
-    pub async fn verify_api_key(&self, api_key: &str) -> Result<VerifyResponse> {
+    fn base_url(settings: &Settings) -> String {
+        settings
+            .api_base_url
+            .as_deref()
+            .unwrap_or(DEFAULT_API_BASE)
+            .trim_end_matches('/')
+            .to_string()
+    }
+
+    fn build_client(settings: &Settings) -> Result<reqwest::Client> {
+        let mut builder = reqwest::Client::builder()
+            .timeout(Duration::from_secs(settings.api_timeout_secs))
+            .connect_timeout(Duration::from_secs(settings.api_connect_timeout_secs))
+            .pool_idle_timeout(Duration::from_secs(90));
+
+        if let Some(proxy) = settings.proxy.as_deref().filter(|p| !p.is_empty()) {
+            builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+        }
+
+        Ok(builder.build()?)
+    }
+
+    /// Replaces the underlying client after the proxy or timeouts changed.
+    pub fn apply_settings(&self, settings: &Settings) -> Result<()> {
+        *self.client.write().unwrap() = Self::build_client(settings)?;
+        *self.base_url.write().unwrap() = Self::base_url(settings);
+        Ok(())
+    }
+
+    fn http(&self) -> reqwest::Client {
+        self.client.read().unwrap().clone()
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url.read().unwrap(), path)
+    }
+
+    fn auth_header() -> String {
         let auth = format!("{}:{}", "rbwadmin", "rbw4dm1n0vpn");
-        let auth_header = format!("Basic {}", general_purpose::STANDARD.encode(&auth));
-        
-        let response = self.client
-            .get(format!("{}/v1/app/verify", API_BASE))
-            .header("X-API-KEY", api_key)
-            .header("Authorization", auth_header)
-            .send()
+        format!("Basic {}", general_purpose::STANDARD.encode(&auth))
+    }
+
+    /// Sends an idempotent request, retrying connection failures and
+    /// 429/502/503/504 responses with jittered exponential backoff.
+    async fn send_idempotent<F>(&self, build: F) -> Result<Response>
+    where
+        F: Fn(&reqwest::Client) -> RequestBuilder,
+    {
+        let client = self.http();
+        let mut attempt = 0;
+
+        loop {
+            let result = build(&client).send().await;
+
+            let retry_after = match &result {
+                Ok(response) if is_retryable_status(response.status()) => {
+                    Some(retry_after(response))
+                }
+                Err(e) if e.is_connect() || e.is_timeout() => Some(None),
+                _ => None,
+            };
+
+            match retry_after {
+                Some(server_delay) if attempt < MAX_RETRIES => {
+                    let delay = server_delay.unwrap_or_else(|| backoff(attempt));
+                    tokio::time::sleep(delay.min(RETRY_MAX_DELAY)).await;
+                    attempt += 1;
+                }
+                _ => return Ok(result?),
+            }
+        }
+    }
+
+    pub async fn verify_api_key(&self, api_key: &str) -> Result<VerifyResponse> {
+        let url = self.url("/v1/app/verify");
+        let response = self
+            .send_idempotent(|client| {
+                client
+                    .get(&url)
+                    .header("X-API-KEY", api_key)
+                    .header("Authorization", Self::auth_header())
+            })
             .await?;
 
         if response.status().is_success() {
@@ -69,17 +196,123 @@ impl ApiClient {
         }
     }
 
-    pub async fn list_servers(&self) -> Result<Vec<Server>> {
-        let auth = format!("{}:{}", "rbwadmin", "rbw4dm1n0vpn");
-        let auth_header = format!("Basic {}", general_purpose::STANDARD.encode(&auth));
-        
-        let response = self.client
-            .get(format!("{}/servers", API_BASE))
-            .header("Authorization", auth_header)
-            .send()
+    pub async fn list_servers_conditional(
+        &self,
+        etag: Option<&str>,
+        last_modified: Option<&str>,
+    ) -> Result<ServerListFetch> {
+        use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+
+        let url = self.url("/servers");
+        let response = self
+            .send_idempotent(|client| {
+                let mut request = client
+                    .get(&url)
+                    .header("Authorization", Self::auth_header());
+
+                if let Some(etag) = etag {
+                    request = request.header(IF_NONE_MATCH, etag);
+                }
+                if let Some(last_modified) = last_modified {
+                    request = request.header(IF_MODIFIED_SINCE, last_modified);
+                }
+                request
+            })
             .await?;
 
-        Ok(response.json().await?)
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(ServerListFetch::NotModified);
+        }
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("Failed to fetch servers: {}", response.status()));
+        }
+
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v: &reqwest::header::HeaderValue| v.to_str().ok())
+                .map(|v| v.to_string())
+        };
+        let etag = header(ETAG);
+        let last_modified = header(LAST_MODIFIED);
+
+        Ok(ServerListFetch::Fresh {
+            servers: response.json().await?,
+            etag,
+            last_modified,
+        })
+    }
+
+    pub async fn list_remote_configs(&self, api_key: &str) -> Result<Vec<RemoteConfig>> {
+        let url = self.url("/v1/app/configs");
+        let response = self
+            .send_idempotent(|client| {
+                client
+                    .get(&url)
+                    .header("X-API-KEY", api_key)
+                    .header("Authorization", Self::auth_header())
+            })
+            .await?;
+
+        if response.status().is_success() {
+            Ok(response.json().await?)
+        } else {
+            Err(anyhow::anyhow!("Failed to list configs: {}", response.status()))
+        }
+    }
+
+    pub async fn download_config(&self, api_key: &str, id: &str) -> Result<String> {
+        let url = self.url(&format!("/v1/app/configs/{}/download", id));
+        let response = self
+            .send_idempotent(|client| {
+                client
+                    .get(&url)
+                    .header("X-API-KEY", api_key)
+                    .header("Authorization", Self::auth_header())
+            })
+            .await?;
+
+        if response.status().is_success() {
+            Ok(response.text().await?)
+        } else {
+            Err(anyhow::anyhow!("Failed to download config {}: {}", id, response.status()))
+        }
+    }
+
+    /// Revoking is idempotent, so it is retried, and a config the portal no
+    /// longer knows counts as revoked.
+    pub async fn revoke(&self, api_key: &str, target: &RevokeTarget) -> Result<()> {
+        let response = match target {
+            RevokeTarget::Config { id } => {
+                let url = self.url(&format!("/v1/app/configs/{}/revoke", id));
+                self.send_idempotent(|client| {
+                    client
+                        .post(&url)
+                        .header("X-API-KEY", api_key)
+                        .header("Authorization", Self::auth_header())
+                })
+                .await?
+            }
+            RevokeTarget::Account { username, server_code } => {
+                let url = self.url("/revoke");
+                let body = serde_json::json!({ "username": username, "server_code": server_code });
+                self.send_idempotent(|client| {
+                    client
+                        .post(&url)
+                        .header("X-API-KEY", api_key)
+                        .header("Authorization", Self::auth_header())
+                        .json(&body)
+                })
+                .await?
+            }
+        };
+
+        if response.status().is_success() || response.status() == StatusCode::NOT_FOUND {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("Failed to revoke config: {}", response.status()))
+        }
     }
 
     pub async fn generate_config(
@@ -87,13 +320,11 @@ impl ApiClient {
         api_key: &str,
         request: &GenerateRequest,
     ) -> Result<String> {
-        let auth = format!("{}:{}", "rbwadmin", "rbw4dm1n0vpn");
-        let auth_header = format!("Basic {}", general_purpose::STANDARD.encode(&auth));
-        
-        let response = self.client
-            .post(format!("{}/generate", API_BASE))
+        // Not retried: a lost response may still have created the account
+        let response = self.http()
+            .post(self.url("/generate"))
             .header("X-API-KEY", api_key)
-            .header("Authorization", auth_header)
+            .header("Authorization", Self::auth_header())
             .json(request)
             .send()
             .await?;
@@ -104,4 +335,37 @@ impl ApiClient {
             Err(anyhow::anyhow!("Failed to generate config"))
         }
     }
-}
\ No newline at end of file
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+fn backoff(attempt: u32) -> Duration {
+    let base = RETRY_BASE_DELAY * 2u32.pow(attempt);
+    let jitter = rand::thread_rng().gen_range(0..=base.as_millis() as u64 / 2);
+    base + Duration::from_millis(jitter)
+}
+
+/// Parses `Retry-After` as either delay-seconds or an HTTP date.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (at.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().ok()
+}
@@ -0,0 +1,271 @@
+use crate::inspect::Severity;
+use crate::profile::Profile;
+use serde::Serialize;
+
+/// Ciphers OpenVPN 2.6 negotiates by default, offered in this order.
+const MODERN_DATA_CIPHERS: &str = "AES-256-GCM:AES-128-GCM:CHACHA20-POLY1305";
+
+// 64-bit block ciphers (SWEET32) and ones OpenSSL 3 only ships in the legacy provider
+const LEGACY_CIPHERS: &[&str] = &[
+    "BF-CBC", "DES-CBC", "DES-EDE-CBC", "DES-EDE3-CBC", "DESX-CBC", "RC2-CBC", "RC2-40-CBC",
+    "RC2-64-CBC", "CAST5-CBC", "IDEA-CBC", "SEED-CBC",
+];
+
+const WEAK_DIGESTS: &[&str] = &["MD5", "SHA1", "RSA-SHA1", "SHA", "RIPEMD160"];
+
+// Removed in OpenVPN 2.5/2.6 and rejected as unknown options
+const REMOVED_DIRECTIVES: &[&str] = &[
+    "ncp-disable", "keysize", "ns-cert-type", "tls-remote", "no-replay", "no-iv",
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Finding {
+    /// Stable identifier, e.g. `deprecated-cipher`.
+    pub id: String,
+    pub severity: Severity,
+    pub message: String,
+    pub directive: Option<String>,
+    pub fixable: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditReport {
+    pub findings: Vec<Finding>,
+    /// Findings the last fix resolved, empty when only auditing.
+    pub fixed: Vec<Finding>,
+}
+
+fn finding(id: &str, severity: Severity, message: String, directive: Option<&str>, fixable: bool) -> Finding {
+    Finding {
+        id: id.to_string(),
+        severity,
+        message,
+        directive: directive.map(str::to_string),
+        fixable,
+    }
+}
+
+fn first_arg<'a>(profile: &'a Profile, name: &str) -> Option<&'a str> {
+    profile.get(name).and_then(|args| args.first()).map(String::as_str)
+}
+
+fn is_legacy_cipher(cipher: &str) -> bool {
+    LEGACY_CIPHERS.iter().any(|c| c.eq_ignore_ascii_case(cipher))
+}
+
+pub fn audit(profile: &Profile) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    let cipher = first_arg(profile, "cipher");
+    let has_data_ciphers = profile.has("data-ciphers") || profile.has("ncp-ciphers");
+
+    if let Some(cipher) = cipher.filter(|c| is_legacy_cipher(c)) {
+        findings.push(finding(
+            "deprecated-cipher",
+            Severity::Error,
+            format!("cipher {} is a legacy 64-bit block cipher that OpenVPN 2.6 with OpenSSL 3 cannot use", cipher),
+            Some("cipher"),
+            true,
+        ));
+    }
+    if let Some(list) = first_arg(profile, "data-ciphers") {
+        for cipher in list.split(':').filter(|c| is_legacy_cipher(c)) {
+            findings.push(finding(
+                "deprecated-cipher",
+                Severity::Error,
+                format!("data-ciphers offers legacy cipher {}", cipher),
+                Some("data-ciphers"),
+                true,
+            ));
+        }
+    }
+
+    if !has_data_ciphers {
+        let (severity, message) = match cipher {
+            Some(cipher) => (
+                Severity::Warning,
+                format!(
+                    "No data-ciphers: OpenVPN 2.6 ignores cipher {} for negotiation and only uses it as a fallback",
+                    cipher
+                ),
+            ),
+            None => (
+                Severity::Info,
+                "No data-ciphers, the negotiated cipher depends on the OpenVPN version".to_string(),
+            ),
+        };
+        findings.push(finding("missing-data-ciphers", severity, message, Some("data-ciphers"), true));
+    }
+
+    let comp_lzo = first_arg(profile, "comp-lzo");
+    if profile.has("comp-lzo") && comp_lzo != Some("no") {
+        findings.push(finding(
+            "compression",
+            Severity::Warning,
+            "comp-lzo enables compression, which leaks data through the VORACLE attack".to_string(),
+            Some("comp-lzo"),
+            true,
+        ));
+    }
+    if let Some(args) = profile.get("compress") {
+        let algorithm = args.first().map(String::as_str).unwrap_or("stub");
+        // "asym" still decompresses what the server sends but never compresses outgoing data
+        let allowed = first_arg(profile, "allow-compression");
+        if !algorithm.starts_with("stub") && !matches!(allowed, Some("no") | Some("asym")) {
+            findings.push(finding(
+                "compression",
+                Severity::Warning,
+                format!("compress {} enables compression, which leaks data through the VORACLE attack", algorithm),
+                Some("compress"),
+                true,
+            ));
+        }
+    }
+
+    let verifies_server = profile.has("verify-x509-name")
+        || first_arg(profile, "remote-cert-tls") == Some("server")
+        || profile.has("remote-cert-eku")
+        || profile.has("peer-fingerprint");
+    if !verifies_server {
+        findings.push(finding(
+            "no-server-verification",
+            Severity::Error,
+            "The server certificate's purpose is not checked (no remote-cert-tls server), so another client certificate from the same CA could impersonate the server".to_string(),
+            Some("remote-cert-tls"),
+            true,
+        ));
+    }
+
+    let weak_digest = first_arg(profile, "auth")
+        .filter(|d| WEAK_DIGESTS.iter().any(|w| w.eq_ignore_ascii_case(d)));
+    if let Some(digest) = weak_digest {
+        findings.push(finding(
+            "weak-digest",
+            Severity::Warning,
+            format!("auth {} is a weak HMAC digest; it has to be changed on the server too", digest),
+            Some("auth"),
+            false,
+        ));
+    }
+
+    let has_tls_crypt = ["tls-crypt", "tls-crypt-v2"]
+        .iter()
+        .any(|name| profile.has(name) || profile.block(name).is_some());
+    if !has_tls_crypt {
+        let has_tls_auth = profile.has("tls-auth") || profile.block("tls-auth").is_some();
+        findings.push(finding(
+            "no-tls-crypt",
+            if has_tls_auth { Severity::Info } else { Severity::Warning },
+            if has_tls_auth {
+                "tls-auth authenticates the control channel but does not encrypt it like tls-crypt".to_string()
+            } else {
+                "The control channel is neither authenticated nor encrypted (no tls-crypt or tls-auth)".to_string()
+            },
+            None,
+            false,
+        ));
+    }
+
+    if let Some(version) = first_arg(profile, "tls-version-min") {
+        if matches!(version, "1.0" | "1.1") {
+            findings.push(finding(
+                "old-tls-version",
+                Severity::Warning,
+                format!("tls-version-min {} allows TLS versions with known weaknesses", version),
+                Some("tls-version-min"),
+                false,
+            ));
+        }
+    }
+
+    for name in REMOVED_DIRECTIVES {
+        if profile.has(name) {
+            findings.push(finding(
+                "removed-option",
+                Severity::Error,
+                format!("{} was removed from OpenVPN and makes 2.6 refuse the profile", name),
+                Some(name),
+                true,
+            ));
+        }
+    }
+
+    findings.sort_by(|a, b| b.severity.cmp(&a.severity));
+    findings
+}
+
+/// Rewrites the profile for OpenVPN 2.6. Some fixes need the server to
+/// cooperate: legacy ciphers are dropped from the negotiation, so the server
+/// must offer one of the remaining data ciphers, and `remote-cert-tls server`
+/// is added, which fails against a server certificate without the TLS Web
+/// Server extended key usage. Findings that need server changes are left alone.
+pub fn fix(profile: &mut Profile) {
+    let cipher = first_arg(profile, "cipher").map(str::to_string);
+
+    let mut data_ciphers: Vec<String> = first_arg(profile, "data-ciphers")
+        .or_else(|| first_arg(profile, "ncp-ciphers"))
+        .unwrap_or(MODERN_DATA_CIPHERS)
+        .split(':')
+        .filter(|c| !is_legacy_cipher(c))
+        .map(str::to_string)
+        .collect();
+    if data_ciphers.is_empty() {
+        data_ciphers = MODERN_DATA_CIPHERS.split(':').map(str::to_string).collect();
+    }
+
+    // A non-AEAD cipher only the server speaks stays usable as the fallback
+    profile.remove("cipher");
+    profile.remove("ncp-ciphers");
+    match cipher.filter(|c| !is_legacy_cipher(c)) {
+        Some(cipher) if !data_ciphers.iter().any(|c| c.eq_ignore_ascii_case(&cipher)) => {
+            profile.set("data-ciphers-fallback", &[&cipher]);
+        }
+        _ => {}
+    }
+    profile.set("data-ciphers", &[&data_ciphers.join(":")]);
+
+    // Keeps the framing the server expects while never compressing outgoing data
+    if profile.has("comp-lzo") && first_arg(profile, "comp-lzo") != Some("no") {
+        profile.set("comp-lzo", &["no"]);
+    }
+    if profile.get("compress").and_then(|a| a.first()).is_some_and(|a| !a.starts_with("stub")) {
+        profile.set("allow-compression", &["asym"]);
+    }
+
+    let verifies_server = profile.has("verify-x509-name")
+        || profile.has("remote-cert-eku")
+        || profile.has("peer-fingerprint");
+    if !verifies_server && first_arg(profile, "remote-cert-tls") != Some("server") {
+        profile.set("remote-cert-tls", &["server"]);
+    }
+
+    for name in REMOVED_DIRECTIVES {
+        profile.remove(name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_profiles_have_no_fixable_findings() {
+        let mut profile = Profile::parse(
+            "client\nremote vpn.example.com 1194\ncipher BF-CBC\ncomp-lzo\ncompress lz4-v2\nns-cert-type server\n",
+        );
+        fix(&mut profile);
+        let fixable: Vec<String> = audit(&profile).into_iter().filter(|f| f.fixable).map(|f| f.id).collect();
+        assert!(fixable.is_empty(), "{:?}", fixable);
+        assert_eq!(first_arg(&profile, "allow-compression"), Some("asym"));
+    }
+
+    #[test]
+    fn compression_is_flagged_unless_outgoing_data_stays_uncompressed() {
+        let flagged = |content: &str| audit(&Profile::parse(content)).iter().any(|f| f.id == "compression");
+        assert!(flagged("compress lz4-v2\n"));
+        assert!(flagged("compress lz4-v2\nallow-compression yes\n"));
+        assert!(!flagged("compress lz4-v2\nallow-compression asym\n"));
+        assert!(!flagged("compress lz4-v2\nallow-compression no\n"));
+        assert!(!flagged("compress stub-v2\n"));
+    }
+}
@@ -0,0 +1,381 @@
+use crate::crypto::KdfParams;
+use crate::importer;
+use crate::openvpn::OpenVpnManager;
+use crate::paths;
+use crate::profile_name::ProfileName;
+use crate::settings::Settings;
+use crate::storage::{FileMode, Storage};
+use anyhow::{Context, Result};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+
+const MAGIC: &[u8; 8] = b"RBWVPNBK";
+const BACKUP_VERSION: u16 = 1;
+const MIN_PASSPHRASE_LEN: usize = 8;
+
+// Same per-file limit the archive reader enforces on restore
+const MAX_FILE_SIZE: u64 = 4 * 1024 * 1024;
+
+// Rewritten on every connection, not worth restoring
+const TRANSIENT_FILES: &[&str] = &["status.txt"];
+
+const ROLLBACK_DIR: &str = ".rollback";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Area {
+    Config,
+    Profiles,
+    State,
+    /// Profile versions, kept as `<profile>/<file>`.
+    History,
+}
+
+impl Area {
+    const ALL: [Area; 4] = [Area::Config, Area::Profiles, Area::State, Area::History];
+
+    fn dir(self) -> PathBuf {
+        match self {
+            Area::Config => paths::config_dir(),
+            Area::Profiles => paths::profiles_dir(),
+            Area::State => paths::state_dir(),
+            Area::History => paths::data_dir().join("history"),
+        }
+    }
+
+    fn key(self) -> &'static str {
+        match self {
+            Area::Config => "config",
+            Area::Profiles => "profiles",
+            Area::State => "state",
+            Area::History => "history",
+        }
+    }
+}
+
+/// Stored in the clear after the magic and version, so the key can be
+/// derived before anything is decrypted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Header {
+    created_at: String,
+    app_version: String,
+    kdf: KdfParams,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BackupManifest {
+    version: u16,
+    created_at: String,
+    files: Vec<BackupFile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BackupFile {
+    area: Area,
+    name: String,
+    sha256: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupReport {
+    pub path: String,
+    pub files: usize,
+    /// Files left out because they exceed the size limit.
+    pub skipped: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RestoreReport {
+    pub files: usize,
+    pub created_at: String,
+    pub app_version: String,
+}
+
+fn sha256(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+fn dir_files(dir: &Path) -> Vec<(String, PathBuf)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|e| e.file_type().map(|t| t.is_file()).unwrap_or(false))
+        .filter_map(|e| Some((e.file_name().to_str()?.to_string(), e.path())))
+        .filter(|(name, _)| !name.starts_with('.') && !TRANSIENT_FILES.contains(&name.as_str()))
+        .collect()
+}
+
+/// Top-level files of an area. Subdirectories belong to other areas on
+/// platforms where the directories nest, and dotfiles are our own markers.
+/// The history area holds one directory per profile instead.
+fn area_files(area: Area) -> Vec<(String, PathBuf)> {
+    let mut files = match area {
+        Area::History => history_dirs()
+            .into_iter()
+            .flat_map(|(profile, dir)| {
+                dir_files(&dir)
+                    .into_iter()
+                    .map(move |(name, path)| (format!("{}/{}", profile, name), path))
+            })
+            .collect(),
+        _ => dir_files(&area.dir()),
+    };
+    files.sort();
+    files
+}
+
+fn history_dirs() -> Vec<(String, PathBuf)> {
+    let Ok(entries) = fs::read_dir(Area::History.dir()) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|e| e.file_type().map(|t| t.is_dir()).unwrap_or(false))
+        .filter_map(|e| Some((e.file_name().to_str()?.to_string(), e.path())))
+        .filter(|(name, _)| !name.starts_with('.'))
+        .collect()
+}
+
+fn is_safe_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\']) && !name.contains("..")
+}
+
+/// History entries are the only ones one directory deep.
+fn is_safe_entry(area: Area, name: &str) -> bool {
+    match (area, name.split_once('/')) {
+        (Area::History, Some((profile, file))) => {
+            is_safe_name(profile) && is_safe_name(file) && ProfileName::parse(profile).is_ok()
+        }
+        (_, None) => area != Area::History && is_safe_name(name),
+        _ => false,
+    }
+}
+
+/// Writes the settings, API key, profiles with their metadata, credentials
+/// and version history, logs and pending operations into one encrypted file:
+///
+/// ```text
+/// "RBWVPNBK" | version (u16 BE) | header length (u32 BE) | header JSON | sealed zip
+/// ```
+///
+/// The zip holds `manifest.json` and `<area>/<file>`, or
+/// `history/<profile>/<file>` for versions; AES-GCM authenticates the whole
+/// archive and the manifest records a SHA-256 per file.
+pub fn backup(path: &Path, passphrase: &str) -> Result<BackupReport> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+        return Err(anyhow::anyhow!(
+            "The passphrase must be at least {} characters",
+            MIN_PASSPHRASE_LEN
+        ));
+    }
+
+    let created_at = Utc::now().to_rfc3339();
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    let mut files = Vec::new();
+    let mut skipped = Vec::new();
+
+    for area in Area::ALL {
+        for (name, file) in area_files(area) {
+            if fs::metadata(&file).map(|m| m.len()).unwrap_or(0) > MAX_FILE_SIZE {
+                skipped.push(format!("{}/{}", area.key(), name));
+                continue;
+            }
+            let bytes = fs::read(&file).with_context(|| format!("Failed to read {}", file.display()))?;
+            zip.start_file(format!("{}/{}", area.key(), name), options)?;
+            zip.write_all(&bytes)?;
+            files.push(BackupFile {
+                area,
+                name,
+                sha256: sha256(&bytes),
+            });
+        }
+    }
+
+    let manifest = BackupManifest {
+        version: BACKUP_VERSION,
+        created_at: created_at.clone(),
+        files,
+    };
+    zip.start_file("manifest.json", options)?;
+    zip.write_all(serde_json::to_string_pretty(&manifest)?.as_bytes())?;
+    let archive = zip.finish()?.into_inner();
+
+    let header = Header {
+        created_at,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        kdf: KdfParams::generate(),
+    };
+    let sealed = header.kdf.derive(passphrase)?.seal(&archive)?;
+    let header_json = serde_json::to_vec(&header)?;
+
+    let mut out = Vec::with_capacity(MAGIC.len() + 6 + header_json.len() + sealed.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&BACKUP_VERSION.to_be_bytes());
+    out.extend_from_slice(&(header_json.len() as u32).to_be_bytes());
+    out.extend_from_slice(&header_json);
+    out.extend_from_slice(&sealed);
+
+    Storage::write_atomic(path, &out, FileMode::Secret)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+
+    Ok(BackupReport {
+        path: path.display().to_string(),
+        files: manifest.files.len(),
+        skipped,
+    })
+}
+
+/// Decrypts a backup and checks every file in it, without touching the
+/// current data.
+fn open(path: &Path, passphrase: &str) -> Result<(Header, Vec<(BackupFile, Vec<u8>)>)> {
+    let data = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+
+    let rest = data
+        .strip_prefix(MAGIC.as_slice())
+        .ok_or_else(|| anyhow::anyhow!("Not a backup file"))?;
+    if rest.len() < 6 {
+        return Err(anyhow::anyhow!("Backup file is truncated"));
+    }
+    let version = u16::from_be_bytes([rest[0], rest[1]]);
+    if version > BACKUP_VERSION {
+        return Err(anyhow::anyhow!("Backup version {} is newer than this app supports", version));
+    }
+    let header_len = u32::from_be_bytes([rest[2], rest[3], rest[4], rest[5]]) as usize;
+    let rest = &rest[6..];
+    if rest.len() < header_len {
+        return Err(anyhow::anyhow!("Backup file is truncated"));
+    }
+    let header: Header = serde_json::from_slice(&rest[..header_len]).context("Invalid backup header")?;
+
+    let archive = header.kdf.derive(passphrase)?.open(&rest[header_len..])?;
+    let mut entries = importer::read_zip(Cursor::new(archive))?;
+    let manifest: BackupManifest = entries
+        .remove("manifest.json")
+        .ok_or_else(|| anyhow::anyhow!("Backup has no manifest"))
+        .and_then(|m| serde_json::from_slice(&m).context("Invalid backup manifest"))?;
+
+    let mut files = Vec::new();
+    for file in manifest.files {
+        let entry = format!("{}/{}", file.area.key(), file.name);
+        if !is_safe_entry(file.area, &file.name) {
+            return Err(anyhow::anyhow!("Backup contains an invalid file name: {}", entry));
+        }
+        let bytes = entries
+            .remove(&entry)
+            .ok_or_else(|| anyhow::anyhow!("Backup is missing {}", entry))?;
+        if sha256(&bytes) != file.sha256 {
+            return Err(anyhow::anyhow!("{} does not match its checksum", entry));
+        }
+
+        match (file.area, file.name.as_str()) {
+            (Area::Config, "settings.json") => {
+                let content = std::str::from_utf8(&bytes).context("settings.json is not text")?;
+                Settings::parse(content).context("Backup has invalid settings")?;
+            }
+            (Area::Profiles, name) => {
+                let stem = name
+                    .strip_suffix(".meta.json")
+                    .or_else(|| name.rsplit_once('.').map(|(stem, _)| stem))
+                    .unwrap_or(name);
+                ProfileName::parse(stem).with_context(|| format!("Invalid profile file {}", name))?;
+            }
+            _ => {}
+        }
+        files.push((file, bytes));
+    }
+
+    Ok((header, files))
+}
+
+/// Files moved aside or written so far, to undo a restore that fails.
+#[derive(Default)]
+struct Transaction {
+    moved: Vec<(PathBuf, PathBuf)>,
+    written: Vec<PathBuf>,
+}
+
+impl Transaction {
+    fn rollback(self) {
+        for path in self.written.iter().rev() {
+            fs::remove_file(path).ok();
+        }
+        for (original, aside) in self.moved.iter().rev() {
+            fs::rename(aside, original).ok();
+        }
+    }
+}
+
+fn replace(files: &[(BackupFile, Vec<u8>)], txn: &mut Transaction) -> Result<()> {
+    for area in Area::ALL {
+        let dir = area.dir();
+        let rollback = dir.join(ROLLBACK_DIR);
+        Storage::create_private_dir(&dir)?;
+        if rollback.exists() {
+            fs::remove_dir_all(&rollback)?;
+        }
+        Storage::create_private_dir(&rollback)?;
+
+        for (name, path) in area_files(area) {
+            let aside = rollback.join(&name);
+            if let Some(parent) = aside.parent() {
+                Storage::create_private_dir(parent)?;
+            }
+            fs::rename(&path, &aside).with_context(|| format!("Failed to move {}", path.display()))?;
+            txn.moved.push((path, aside));
+        }
+    }
+
+    for (file, bytes) in files {
+        let path = file.area.dir().join(&file.name);
+        let mode = if Storage::is_secret_file(&path) || matches!(file.area, Area::Profiles | Area::History) {
+            FileMode::Secret
+        } else {
+            FileMode::Public
+        };
+        if let Some(parent) = path.parent() {
+            Storage::create_private_dir(parent)?;
+        }
+        txn.written.push(path.clone());
+        Storage::write_atomic(&path, bytes, mode)?;
+    }
+
+    Ok(())
+}
+
+/// Replaces the app data with a backup. Everything is decrypted and
+/// verified first; if writing then fails, the previous files are put back.
+pub fn restore(manager: &OpenVpnManager, path: &Path, passphrase: &str) -> Result<RestoreReport> {
+    if manager.is_connected() {
+        return Err(anyhow::anyhow!("Disconnect before restoring a backup"));
+    }
+
+    let (header, files) = open(path, passphrase)?;
+
+    let mut txn = Transaction::default();
+    if let Err(e) = replace(&files, &mut txn) {
+        txn.rollback();
+        return Err(e.context("Restore failed, the previous data was kept"));
+    }
+
+    for area in Area::ALL {
+        fs::remove_dir_all(area.dir().join(ROLLBACK_DIR)).ok();
+    }
+    // Histories of profiles the backup does not have are left empty
+    for (_, dir) in history_dirs() {
+        fs::remove_dir(dir).ok();
+    }
+
+    Ok(RestoreReport {
+        files: files.len(),
+        created_at: header.created_at,
+        app_version: header.app_version,
+    })
+}
@@ -0,0 +1,532 @@
+// Minimal launcher installed with the `cap_net_admin+ep` file capability.
+// It raises CAP_NET_ADMIN into the ambient set and execs openvpn, so the
+// tunnel can be configured without running anything setuid or as root.
+// `--openvpn <path>` picks the binary, which must be root-owned.
+// `rbw-vpn-helper kill-switch on|off` installs or removes the kill switch
+// firewall rules the same way.
+
+// Options that would let a caller run arbitrary programs with our capability.
+#[cfg(target_os = "linux")]
+const FORBIDDEN: &[&str] = &[
+    "up",
+    "down",
+    "route-up",
+    "route-pre-down",
+    "ipchange",
+    "learn-address",
+    "tls-verify",
+    "auth-user-pass-verify",
+    "client-connect",
+    "client-disconnect",
+    "script-security",
+    "plugin",
+    "iproute",
+];
+
+#[cfg(target_os = "linux")]
+const MAX_CONFIG_SIZE: u64 = 1024 * 1024;
+
+#[cfg(target_os = "linux")]
+fn main() {
+    use std::os::unix::process::CommandExt;
+    use std::process::Command;
+
+    const OPENVPN_CANDIDATES: &[&str] = &["/usr/sbin/openvpn", "/usr/bin/openvpn", "/sbin/openvpn"];
+
+    let fail = |message: String| -> ! {
+        eprintln!("rbw-vpn-helper: {}", message);
+        std::process::exit(2);
+    };
+
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|a| a == "kill-switch") {
+        if let Err(e) = killswitch::run(&args[1..]) {
+            fail(e);
+        }
+        std::process::exit(0);
+    }
+
+    // The app passes the OpenVPN binary from its settings first
+    let mut openvpn = None;
+    if args.first().is_some_and(|a| a == "--openvpn") {
+        let Some(path) = args.get(1) else {
+            fail("--openvpn needs a path".to_string());
+        };
+        openvpn = Some(binary::check(path).unwrap_or_else(|e| fail(e)));
+        args.drain(..2);
+    }
+
+    if let Some(bad) = args
+        .iter()
+        .find(|a| a.strip_prefix("--").is_some_and(|name| FORBIDDEN.contains(&name)))
+    {
+        fail(format!("option {} is not allowed", bad));
+    }
+
+    // openvpn reads a lone non-option argument as the config file
+    if args.len() == 1 && !args[0].starts_with("--") {
+        fail("pass the config with --config".to_string());
+    }
+
+    let configs: Vec<usize> = args
+        .iter()
+        .enumerate()
+        .filter(|(_, a)| *a == "--config")
+        .map(|(i, _)| i + 1)
+        .collect();
+    if configs.len() > 1 {
+        fail("--config may only be given once".to_string());
+    }
+
+    // The config is checked and handed to openvpn as a sealed in-memory copy,
+    // so the file cannot be swapped or rewritten after the check.
+    let mut sealed = None;
+    if let Some(&i) = configs.first() {
+        let Some(path) = args.get(i) else {
+            fail("--config needs a file".to_string());
+        };
+        let content = config::read(path).unwrap_or_else(|e| fail(format!("cannot read {}: {}", path, e)));
+        if let Err(e) = config::check(&content) {
+            fail(e);
+        }
+        let fd = config::seal(content.as_bytes()).unwrap_or_else(|e| fail(format!("cannot copy the config: {}", e)));
+        args[i] = format!("/dev/fd/{}", fd);
+        sealed = Some(fd);
+    }
+
+    let openvpn = openvpn.or_else(|| {
+        OPENVPN_CANDIDATES
+            .iter()
+            .map(std::path::PathBuf::from)
+            .find(|p| p.is_file())
+    });
+    let Some(openvpn) = openvpn else {
+        eprintln!("rbw-vpn-helper: openvpn binary not found");
+        std::process::exit(127);
+    };
+
+    if let Err(e) = caps::raise_ambient_net_admin() {
+        eprintln!("rbw-vpn-helper: failed to raise CAP_NET_ADMIN: {}", e);
+        std::process::exit(1);
+    }
+
+    // Later options win, so scripts from the config file are disabled too.
+    let err = Command::new(&openvpn)
+        .args(&args)
+        .args(["--script-security", "1"])
+        .exec();
+
+    if let Some(fd) = sealed {
+        unsafe { libc::close(fd) };
+    }
+    eprintln!("rbw-vpn-helper: failed to exec {}: {}", openvpn.display(), err);
+    std::process::exit(126);
+}
+
+// Anyone able to replace the binary, or a directory above it, could run
+// their own code with our capability, so only root may be able to.
+#[cfg(target_os = "linux")]
+mod binary {
+    use std::os::unix::fs::MetadataExt;
+    use std::path::{Path, PathBuf};
+
+    pub fn check(path: &str) -> Result<PathBuf, String> {
+        let path = Path::new(path);
+        if !path.is_absolute() {
+            return Err(format!("{} is not an absolute path", path.display()));
+        }
+        let path = path
+            .canonicalize()
+            .map_err(|e| format!("cannot resolve {}: {}", path.display(), e))?;
+
+        let meta = path
+            .metadata()
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        if !meta.is_file() {
+            return Err(format!("{} is not a file", path.display()));
+        }
+        for entry in path.ancestors() {
+            let meta = entry
+                .metadata()
+                .map_err(|e| format!("cannot read {}: {}", entry.display(), e))?;
+            if meta.uid() != 0 || meta.mode() & 0o022 != 0 {
+                return Err(format!(
+                    "{} must be owned by root and writable by no one else",
+                    entry.display()
+                ));
+            }
+        }
+        Ok(path)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn accepts_only_binaries_only_root_can_change() {
+            assert!(check("/bin/sh").is_ok());
+            assert!(check("sh").is_err());
+            assert!(check("/bin").is_err());
+
+            // The temp dir is writable by everyone
+            let file = std::env::temp_dir().join(format!("rbw-openvpn-{:08x}", std::process::id()));
+            std::fs::write(&file, b"").unwrap();
+            let result = check(file.to_str().unwrap());
+            std::fs::remove_file(&file).ok();
+            assert!(result.is_err());
+        }
+    }
+}
+
+// The app's config parser, for its tokenizer
+#[cfg(target_os = "linux")]
+#[allow(dead_code)]
+#[path = "../profile.rs"]
+mod profile;
+
+#[cfg(target_os = "linux")]
+mod config {
+    use super::profile::split_words;
+    use super::{FORBIDDEN, MAX_CONFIG_SIZE};
+    use std::io::{self, Read};
+    use std::os::fd::RawFd;
+
+    pub fn read(path: &str) -> io::Result<String> {
+        let mut content = String::new();
+        std::fs::File::open(path)?
+            .take(MAX_CONFIG_SIZE + 1)
+            .read_to_string(&mut content)?;
+        if content.len() as u64 > MAX_CONFIG_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "config is too large"));
+        }
+        Ok(content)
+    }
+
+    /// Refuses configs that load code, run programs or pull in other files.
+    /// Every line is checked, including those inside inline blocks.
+    pub fn check(content: &str) -> Result<(), String> {
+        for line in content.lines() {
+            let Some(first) = split_words(line).into_iter().next() else {
+                continue;
+            };
+            // Config files may spell options with the leading dashes too
+            let name = first.strip_prefix("--").unwrap_or(&first);
+            if name == "config" {
+                return Err("configs including other configs are not allowed".to_string());
+            }
+            if FORBIDDEN.contains(&name) {
+                return Err(format!("configs using {} are not allowed", name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies the config into a sealed memfd that stays open across exec.
+    pub fn seal(content: &[u8]) -> io::Result<RawFd> {
+        let fd = unsafe { libc::memfd_create(c"rbw-vpn-config".as_ptr(), libc::MFD_ALLOW_SEALING) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut written = 0;
+        while written < content.len() {
+            let n = unsafe {
+                libc::write(fd, content[written..].as_ptr().cast(), content.len() - written)
+            };
+            if n < 0 {
+                let err = io::Error::last_os_error();
+                unsafe { libc::close(fd) };
+                return Err(err);
+            }
+            written += n as usize;
+        }
+
+        let seals = libc::F_SEAL_SEAL | libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_WRITE;
+        if unsafe { libc::fcntl(fd, libc::F_ADD_SEALS, seals) } != 0 {
+            let err = io::Error::last_os_error();
+            unsafe { libc::close(fd) };
+            return Err(err);
+        }
+        Ok(fd)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn splits_like_openvpn() {
+            assert_eq!(split_words("remote  host 1194 # comment"), ["remote", "host", "1194"]);
+            assert_eq!(split_words(r#"auth-user-pass "a b\"c" 'd\e'"#), ["auth-user-pass", "a b\"c", "d\\e"]);
+            assert_eq!(split_words(r"plu\gin x.so"), ["plugin", "x.so"]);
+            assert!(split_words("  ; plugin x.so").is_empty());
+        }
+
+        #[test]
+        fn refuses_plugins_scripts_and_includes() {
+            assert!(check("client\nremote host 1194\n").is_ok());
+            assert!(check("plugin /tmp/x.so\n").is_err());
+            assert!(check("  --plugin /tmp/x.so\n").is_err());
+            assert!(check("\"plugin\" /tmp/x.so\n").is_err());
+            assert!(check("p\\lugin /tmp/x.so\n").is_err());
+            assert!(check("config /tmp/other.conf\n").is_err());
+            assert!(check("<connection>\nup /bin/sh\n</connection>\n").is_err());
+            assert!(check("# plugin /tmp/x.so\n").is_ok());
+        }
+
+        #[test]
+        fn sealed_copy_cannot_be_changed() {
+            let fd = seal(b"client\n").unwrap();
+            let n = unsafe { libc::write(fd, b"x".as_ptr().cast(), 1) };
+            assert!(n < 0);
+            let read = std::fs::read_to_string(format!("/dev/fd/{}", fd)).unwrap();
+            assert_eq!(read, "client\n");
+            unsafe { libc::close(fd) };
+        }
+    }
+}
+
+/// nftables rules that drop outgoing traffic except through the tunnel and
+/// to the VPN servers. The helper builds the ruleset itself from checked
+/// addresses, so callers cannot load rules of their own.
+#[cfg(target_os = "linux")]
+mod killswitch {
+    use std::io::Write;
+    use std::net::SocketAddr;
+    use std::process::{Command, Stdio};
+
+    const TABLE: &str = "inet rbw_vpn_kill_switch";
+    const NFT_CANDIDATES: &[&str] = &["/usr/sbin/nft", "/sbin/nft", "/usr/bin/nft"];
+    const USAGE: &str = "usage: kill-switch on [--dev NAME] PROTO:ADDRESS:PORT... | kill-switch off";
+
+    #[derive(Debug, PartialEq)]
+    pub struct Endpoint {
+        pub tcp: bool,
+        pub addr: SocketAddr,
+    }
+
+    /// Parses `udp:203.0.113.5:1194` or `tcp:[2001:db8::1]:443`.
+    pub fn parse_endpoint(arg: &str) -> Result<Endpoint, String> {
+        let (proto, addr) = arg.split_once(':').ok_or_else(|| format!("invalid server {}", arg))?;
+        let tcp = match proto {
+            "udp" => false,
+            "tcp" => true,
+            _ => return Err(format!("unknown protocol in {}", arg)),
+        };
+        let addr = addr.parse().map_err(|_| format!("invalid server address {}", arg))?;
+        Ok(Endpoint { tcp, addr })
+    }
+
+    /// Device names end up in the ruleset, so only plain interface names pass.
+    pub fn check_device(name: &str) -> Result<(), String> {
+        let valid = !name.is_empty()
+            && name.len() <= 15
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid {
+            return Err(format!("invalid device name {:?}", name));
+        }
+        Ok(())
+    }
+
+    /// Replaces the table in one transaction, so there is no moment without rules
+    /// while switching servers.
+    pub fn ruleset(endpoints: &[Endpoint], devices: &[String]) -> String {
+        let mut rules = vec![
+            "oifname \"lo\" accept".to_string(),
+            "oifname \"tun*\" accept".to_string(),
+            "oifname \"tap*\" accept".to_string(),
+        ];
+        rules.extend(devices.iter().map(|dev| format!("oifname \"{}\" accept", dev)));
+        // Keeps the local address leases and IPv6 neighbours working
+        rules.push("udp sport 68 udp dport 67 accept".to_string());
+        rules.push("udp sport 546 udp dport 547 accept".to_string());
+        rules.push(
+            "icmpv6 type { nd-router-solicit, nd-neighbor-solicit, nd-neighbor-advert } accept".to_string(),
+        );
+        for endpoint in endpoints {
+            let family = if endpoint.addr.is_ipv4() { "ip" } else { "ip6" };
+            let proto = if endpoint.tcp { "tcp" } else { "udp" };
+            rules.push(format!(
+                "{} daddr {} {} dport {} accept",
+                family,
+                endpoint.addr.ip(),
+                proto,
+                endpoint.addr.port()
+            ));
+        }
+
+        let mut script = remove_script();
+        script.push_str(&format!(
+            "table {} {{\n\tchain output {{\n\t\ttype filter hook output priority 0; policy drop;\n",
+            TABLE
+        ));
+        for rule in rules {
+            script.push_str(&format!("\t\t{}\n", rule));
+        }
+        script.push_str("\t}\n}\n");
+        script
+    }
+
+    /// Declaring the table first makes deleting it succeed when it is absent.
+    pub fn remove_script() -> String {
+        format!("table {}\ndelete table {}\n", TABLE, TABLE)
+    }
+
+    pub fn run(args: &[String]) -> Result<(), String> {
+        let script = match args.split_first() {
+            Some((action, rest)) if action == "on" => {
+                let mut endpoints = Vec::new();
+                let mut devices = Vec::new();
+                let mut rest = rest.iter();
+                while let Some(arg) = rest.next() {
+                    if arg == "--dev" {
+                        let dev = rest.next().ok_or("--dev needs a device name")?;
+                        check_device(dev)?;
+                        devices.push(dev.clone());
+                    } else {
+                        endpoints.push(parse_endpoint(arg)?);
+                    }
+                }
+                if endpoints.is_empty() {
+                    return Err("the kill switch needs at least one VPN server".to_string());
+                }
+                ruleset(&endpoints, &devices)
+            }
+            Some((action, [])) if action == "off" => remove_script(),
+            _ => return Err(USAGE.to_string()),
+        };
+
+        let nft = NFT_CANDIDATES
+            .iter()
+            .find(|p| std::path::Path::new(p).is_file())
+            .ok_or("nft not found, install nftables")?;
+        super::caps::raise_ambient_net_admin().map_err(|e| format!("failed to raise CAP_NET_ADMIN: {}", e))?;
+
+        let mut child = Command::new(nft)
+            .args(["-f", "-"])
+            .env_clear()
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("failed to run {}: {}", nft, e))?;
+        child
+            .stdin
+            .take()
+            .ok_or("failed to open nft input")?
+            .write_all(script.as_bytes())
+            .map_err(|e| format!("failed to pass the rules to nft: {}", e))?;
+        let status = child.wait().map_err(|e| e.to_string())?;
+        if !status.success() {
+            return Err(format!("nft failed ({})", status));
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn parses_only_plain_endpoints() {
+            assert_eq!(
+                parse_endpoint("tcp:[2001:db8::1]:443").unwrap(),
+                Endpoint {
+                    tcp: true,
+                    addr: "[2001:db8::1]:443".parse().unwrap()
+                }
+            );
+            assert!(parse_endpoint("udp:203.0.113.5:1194").is_ok());
+            assert!(parse_endpoint("udp:vpn.example.com:1194").is_err());
+            assert!(parse_endpoint("icmp:203.0.113.5:1").is_err());
+            assert!(parse_endpoint("udp:203.0.113.5").is_err());
+            assert!(check_device("vpn0").is_ok());
+            assert!(check_device("x\" accept").is_err());
+        }
+
+        #[test]
+        fn allows_only_the_tunnel_and_servers() {
+            let endpoints = [
+                parse_endpoint("udp:203.0.113.5:1194").unwrap(),
+                parse_endpoint("tcp:[2001:db8::1]:443").unwrap(),
+            ];
+            let script = ruleset(&endpoints, &["vpn0".to_string()]);
+
+            assert!(script.starts_with(&remove_script()));
+            assert!(script.contains("policy drop;"));
+            assert!(script.contains("oifname \"vpn0\" accept"));
+            assert!(script.contains("ip daddr 203.0.113.5 udp dport 1194 accept"));
+            assert!(script.contains("ip6 daddr 2001:db8::1 tcp dport 443 accept"));
+            assert!(!script.contains("dport 53"));
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod caps {
+    use std::io;
+
+    const LINUX_CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+    const CAP_NET_ADMIN: u32 = 12;
+
+    #[repr(C)]
+    struct CapHeader {
+        version: u32,
+        pid: i32,
+    }
+
+    #[repr(C)]
+    #[derive(Default, Clone, Copy)]
+    struct CapData {
+        effective: u32,
+        permitted: u32,
+        inheritable: u32,
+    }
+
+    pub fn raise_ambient_net_admin() -> io::Result<()> {
+        let mut header = CapHeader {
+            version: LINUX_CAPABILITY_VERSION_3,
+            pid: 0,
+        };
+        let mut data = [CapData::default(); 2];
+
+        if unsafe { libc::syscall(libc::SYS_capget, &mut header, data.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let bit = 1 << CAP_NET_ADMIN;
+        if data[0].permitted & bit == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "CAP_NET_ADMIN is not in the permitted set (was setcap applied?)",
+            ));
+        }
+
+        // A capability must be both permitted and inheritable to become ambient.
+        data[0].effective |= bit;
+        data[0].inheritable |= bit;
+        if unsafe { libc::syscall(libc::SYS_capset, &mut header, data.as_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let raised = unsafe {
+            libc::prctl(
+                libc::PR_CAP_AMBIENT,
+                libc::PR_CAP_AMBIENT_RAISE as libc::c_ulong,
+                CAP_NET_ADMIN as libc::c_ulong,
+                0 as libc::c_ulong,
+                0 as libc::c_ulong,
+            )
+        };
+        if raised != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("rbw-vpn-helper is only supported on Linux");
+    std::process::exit(1);
+}
@@ -0,0 +1,405 @@
+use crate::api::Server;
+use crate::crypto::{KdfParams, SecretKey};
+use crate::editor;
+use crate::history::{self, ChangeReason};
+use crate::importer::{self, ImportReport};
+use crate::metadata::{content_hash, ProfileMeta, ProfileSource};
+use crate::openvpn::OpenVpnManager;
+use crate::profile::Profile;
+use crate::profile_name::ProfileName;
+use crate::storage::{FileMode, Storage};
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose, Engine as _};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+
+pub const BUNDLE_FORMAT: &str = "rbw-vpn-bundle";
+const BUNDLE_VERSION: u32 = 1;
+
+// Sealed into the manifest so a wrong passphrase is caught before importing
+const PASSPHRASE_CHECK: &[u8] = b"rbw-vpn-bundle";
+
+/// `manifest.json` at the root of a bundle. A bundle is a zip archive:
+///
+/// ```text
+/// manifest.json
+/// profiles/<name>.ovpn
+/// profiles/<name>.meta.json
+/// profiles/<name>.creds.enc    only when exported with a passphrase
+/// ```
+///
+/// `.creds.enc` files are AES-256-GCM sealed (12-byte nonce, then the
+/// ciphertext and tag) under a key derived from the passphrase with `kdf`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: String,
+    pub version: u32,
+    pub created_at: String,
+    pub app_version: String,
+    pub kdf: Option<KdfParams>,
+    /// Base64 of the sealed `PASSPHRASE_CHECK`.
+    pub check: Option<String>,
+    pub profiles: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub name: String,
+    /// SHA-256 of the `.ovpn` file.
+    pub sha256: String,
+    pub has_credentials: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BundleExport {
+    pub path: String,
+    pub profiles: Vec<String>,
+    pub credentials_included: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BundleEntry {
+    pub name: String,
+    pub has_credentials: bool,
+    /// A profile with this name (ignoring case) is already stored.
+    pub exists: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BundlePreview {
+    pub created_at: String,
+    pub encrypted: bool,
+    pub profiles: Vec<BundleEntry>,
+}
+
+/// What to do when a bundled profile's name is already taken.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Collision {
+    Skip,
+    Overwrite,
+    Rename { name: ProfileName },
+}
+
+fn entry_path(name: &str, extension: &str) -> String {
+    format!("profiles/{}.{}", name, extension)
+}
+
+/// Writes the selected profiles, or all of them, into a bundle. Credentials
+/// are only included when a passphrase is given.
+pub fn export(
+    manager: &OpenVpnManager,
+    names: Option<&[ProfileName]>,
+    path: &Path,
+    passphrase: Option<&str>,
+) -> Result<BundleExport> {
+    let names: Vec<ProfileName> = match names {
+        Some(names) => names.to_vec(),
+        None => manager
+            .list_configs()?
+            .iter()
+            .filter_map(|c| ProfileName::parse(&c.name).ok())
+            .collect(),
+    };
+    if names.is_empty() {
+        return Err(anyhow::anyhow!("No profiles to export"));
+    }
+
+    let kdf = passphrase.filter(|p| !p.is_empty()).map(|_| KdfParams::generate());
+    let key = match (&kdf, passphrase) {
+        (Some(kdf), Some(passphrase)) => Some(kdf.derive(passphrase)?),
+        _ => None,
+    };
+
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    let mut entries = Vec::new();
+
+    for name in &names {
+        let content = manager.load_config(name)?;
+        let meta = manager.load_meta(name);
+
+        zip.start_file(entry_path(name.as_str(), "ovpn"), options)?;
+        zip.write_all(content.as_bytes())?;
+        zip.start_file(entry_path(name.as_str(), "meta.json"), options)?;
+        zip.write_all(serde_json::to_string_pretty(&meta)?.as_bytes())?;
+
+        let credentials = match &key {
+            Some(key) => manager
+                .load_credentials(name)
+                .ok()
+                .map(|c| key.seal(c.as_bytes()))
+                .transpose()?,
+            None => None,
+        };
+        if let Some(sealed) = &credentials {
+            zip.start_file(entry_path(name.as_str(), "creds.enc"), options)?;
+            zip.write_all(sealed)?;
+        }
+
+        entries.push(ManifestEntry {
+            name: name.to_string(),
+            sha256: content_hash(&content),
+            has_credentials: credentials.is_some(),
+        });
+    }
+
+    let manifest = Manifest {
+        format: BUNDLE_FORMAT.to_string(),
+        version: BUNDLE_VERSION,
+        created_at: Utc::now().to_rfc3339(),
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        check: key
+            .as_ref()
+            .map(|key| key.seal(PASSPHRASE_CHECK).map(|c| general_purpose::STANDARD.encode(c)))
+            .transpose()?,
+        kdf,
+        profiles: entries,
+    };
+    zip.start_file("manifest.json", options)?;
+    zip.write_all(serde_json::to_string_pretty(&manifest)?.as_bytes())?;
+
+    let bytes = zip.finish()?.into_inner();
+    Storage::write_atomic(path, &bytes, FileMode::Secret)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+
+    Ok(BundleExport {
+        path: path.display().to_string(),
+        profiles: names.iter().map(|n| n.to_string()).collect(),
+        credentials_included: manifest.kdf.is_some(),
+    })
+}
+
+fn read(path: &Path) -> Result<(Manifest, HashMap<String, Vec<u8>>)> {
+    let file = fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let files = importer::read_zip(file)?;
+
+    let manifest: Manifest = files
+        .get("manifest.json")
+        .ok_or_else(|| anyhow::anyhow!("Not a profile bundle: manifest.json is missing"))
+        .and_then(|m| serde_json::from_slice(m).context("Invalid bundle manifest"))?;
+    if manifest.format != BUNDLE_FORMAT {
+        return Err(anyhow::anyhow!("Not a profile bundle"));
+    }
+    if manifest.version > BUNDLE_VERSION {
+        return Err(anyhow::anyhow!(
+            "Bundle format version {} is newer than this app supports",
+            manifest.version
+        ));
+    }
+
+    Ok((manifest, files))
+}
+
+/// Lists what a bundle contains and which names are already taken.
+pub fn preview(manager: &OpenVpnManager, path: &Path) -> Result<BundlePreview> {
+    let (manifest, _) = read(path)?;
+
+    Ok(BundlePreview {
+        created_at: manifest.created_at,
+        encrypted: manifest.kdf.is_some(),
+        profiles: manifest
+            .profiles
+            .into_iter()
+            .map(|entry| BundleEntry {
+                exists: ProfileName::parse(&entry.name)
+                    .ok()
+                    .and_then(|n| manager.existing_name(&n))
+                    .is_some(),
+                name: entry.name,
+                has_credentials: entry.has_credentials,
+            })
+            .collect(),
+    })
+}
+
+/// Imports every profile of a bundle. A name that is already taken is
+/// resolved by `collisions`, keyed by the bundled name, and reported as
+/// an error when no resolution was given.
+pub fn import(
+    manager: &OpenVpnManager,
+    path: &Path,
+    passphrase: Option<&str>,
+    collisions: &HashMap<String, Collision>,
+    servers: &[Server],
+) -> Result<Vec<ImportReport>> {
+    let (manifest, files) = read(path)?;
+
+    let key = match (&manifest.kdf, passphrase.filter(|p| !p.is_empty())) {
+        (Some(kdf), Some(passphrase)) => {
+            let key = kdf.derive(passphrase)?;
+            let check = manifest
+                .check
+                .as_deref()
+                .map(|c| general_purpose::STANDARD.decode(c))
+                .transpose()?
+                .unwrap_or_default();
+            if key.open(&check).ok().as_deref() != Some(PASSPHRASE_CHECK) {
+                return Err(anyhow::anyhow!("Wrong passphrase"));
+            }
+            Some(key)
+        }
+        _ => None,
+    };
+
+    let mut reports = Vec::new();
+    for entry in &manifest.profiles {
+        let mut report = ImportReport {
+            source: format!("{}:{}", path.display(), entry.name),
+            ..Default::default()
+        };
+        if let Err(e) = import_entry(manager, entry, &files, key.as_ref(), collisions, servers, &mut report) {
+            report.error = Some(e.to_string());
+        }
+        reports.push(report);
+    }
+
+    Ok(reports)
+}
+
+fn import_entry(
+    manager: &OpenVpnManager,
+    entry: &ManifestEntry,
+    files: &HashMap<String, Vec<u8>>,
+    key: Option<&SecretKey>,
+    collisions: &HashMap<String, Collision>,
+    servers: &[Server],
+    report: &mut ImportReport,
+) -> Result<()> {
+    let bundled = ProfileName::parse(&entry.name)?;
+    let content = files
+        .get(&entry_path(&entry.name, "ovpn"))
+        .ok_or_else(|| anyhow::anyhow!("Profile is missing from the bundle"))?;
+    let content = String::from_utf8(content.clone()).context("Profile is not a text file")?;
+    if content_hash(&content) != entry.sha256 {
+        return Err(anyhow::anyhow!("Profile does not match its checksum"));
+    }
+
+    let (name, replaces) = match (manager.existing_name(&bundled), collisions.get(&entry.name)) {
+        (None, _) => (bundled, None),
+        (Some(_), Some(Collision::Skip)) => {
+            report.name = Some(bundled.to_string());
+            report.skipped = true;
+            return Ok(());
+        }
+        (Some(existing), Some(Collision::Overwrite)) => (bundled, Some(existing)),
+        (Some(_), Some(Collision::Rename { name })) => {
+            if let Some(existing) = manager.existing_name(name) {
+                return Err(anyhow::anyhow!("A profile named {} already exists", existing));
+            }
+            (name.clone(), None)
+        }
+        (Some(existing), None) => {
+            return Err(anyhow::anyhow!("A profile named {} already exists", existing));
+        }
+    };
+    report.name = Some(name.to_string());
+
+    // An overwrite is written under a temporary name first, so a failure
+    // part way leaves the existing profile, credentials and history intact
+    let target = match replaces {
+        Some(_) => ProfileName::parse(&format!("import-{:08x}", rand::random::<u32>()))?,
+        None => name.clone(),
+    };
+    let meta = bundled_meta(entry, files, &content, servers);
+    let written = write_entry(manager, entry, files, key, &target, &content, report).and_then(|_| {
+        let Some(mut meta) = meta else {
+            return Ok(());
+        };
+        meta.expires_at = meta.expires_at.or(manager.load_meta(&target).expires_at);
+        manager.save_meta(&target, &meta)
+    });
+    if let Err(e) = written {
+        if replaces.is_some() {
+            manager.delete_config(&target).ok();
+        }
+        return Err(e);
+    }
+
+    if let Some(existing) = replaces {
+        // The history moves to the staged profile so deleting the old one
+        // keeps it, and the replaced content becomes a version
+        let previous = manager.load_config(&existing).ok();
+        history::rename(&existing, &target)?;
+        if let Err(e) = manager.delete_config(&existing) {
+            if manager.config_exists(&existing) {
+                history::rename(&target, &existing).ok();
+                manager.delete_config(&target).ok();
+                return Err(e);
+            }
+        }
+        if let Some(previous) = previous.filter(|p| *p != content) {
+            history::record(&target, &previous, ChangeReason::Imported).ok();
+        }
+        manager.rename_config(&target, &name)?;
+    }
+
+    report.imported = true;
+    Ok(())
+}
+
+fn write_entry(
+    manager: &OpenVpnManager,
+    entry: &ManifestEntry,
+    files: &HashMap<String, Vec<u8>>,
+    key: Option<&SecretKey>,
+    name: &ProfileName,
+    content: &str,
+    report: &mut ImportReport,
+) -> Result<()> {
+    let credentials = match (entry.has_credentials, key) {
+        (true, Some(key)) => {
+            let sealed = files
+                .get(&entry_path(&entry.name, "creds.enc"))
+                .ok_or_else(|| anyhow::anyhow!("Credentials are missing from the bundle"))?;
+            Some(String::from_utf8(key.open(sealed)?).context("Credentials are not text")?)
+        }
+        (true, None) => {
+            report.unresolved.push("credentials: no passphrase given".to_string());
+            None
+        }
+        (false, _) => None,
+    };
+
+    importer::save_profile(manager, name, content, credentials.as_deref())
+}
+
+/// The bundled metadata, kept only as far as it can be trusted: the profile
+/// counts as imported, is not linked to a portal config and keeps its server
+/// code only when its remotes are that server's address.
+fn bundled_meta(
+    entry: &ManifestEntry,
+    files: &HashMap<String, Vec<u8>>,
+    content: &str,
+    servers: &[Server],
+) -> Option<ProfileMeta> {
+    let meta: ProfileMeta = files
+        .get(&entry_path(&entry.name, "meta.json"))
+        .and_then(|m| serde_json::from_slice(m).ok())?;
+
+    let remotes: Vec<String> = editor::settings(&Profile::parse(content))
+        .remotes
+        .into_iter()
+        .map(|r| r.host)
+        .collect();
+    let serves = |code: &String| {
+        servers
+            .iter()
+            .find(|s| s.code.eq_ignore_ascii_case(code))
+            .is_some_and(|server| !remotes.is_empty() && remotes.iter().all(|r| *r == server.ip))
+    };
+
+    Some(ProfileMeta {
+        source: ProfileSource::Imported,
+        status: meta.status,
+        server_code: meta.server_code.filter(serves),
+        username: meta.username,
+        expires_at: meta.expires_at,
+        ..Default::default()
+    })
+}
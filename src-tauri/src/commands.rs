@@ -1,8 +1,31 @@
-use crate::api::{ApiClient, GenerateRequest};
+use crate::api::{ApiClient, GenerateRequest, RevokeTarget};
+use crate::audit::{self, AuditReport};
+use crate::backup::{self, BackupReport, RestoreReport};
+use crate::bundle::{self, BundleExport, BundlePreview, Collision};
+use crate::editor::{self, ProfileEdit, ProfileSettings};
+use crate::expiry::{self, ExpiringProfile};
+use crate::fallback::{self, Transport};
+use crate::history::{self, ChangeReason, DiffLine, ProfileVersion};
+use crate::importer::{self, ImportReport};
+use crate::inspect::{self, ProfileInspection};
+use crate::killswitch;
+use crate::metadata::{content_hash, ProfileMeta, ProfileSource, ProfileStatus};
+use crate::networkmanager::{self, NmExport};
 use crate::openvpn::OpenVpnManager;
+use crate::pending::{self, PendingAction, PendingOperation};
+use crate::policy::PolicyStatus;
+use crate::profile::Profile;
+use crate::profile_name::ProfileName;
+use crate::remotes::{self, RemoteStatus};
+use crate::probe::{self, ProbeResult, ServerRanking};
+use crate::server_cache::{self, ServerCache};
+use crate::settings::{Settings, SettingsStore};
 use crate::state::{AppState, VpnConnection};
+use crate::subnets::{self, SubnetConflict};
+use crate::sync::SyncReport;
 use serde::{Deserialize, Serialize};
-use tauri::{State, Emitter};
+use std::collections::HashMap;
+use tauri::{Emitter, Manager, State};
 
 #[derive(Debug, Serialize)]
 pub struct VerifyResponse {
@@ -11,7 +34,7 @@ pub struct VerifyResponse {
     pub server_location: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Server {
     pub code: String,
     pub name: String,
@@ -20,6 +43,37 @@ pub struct Server {
     pub tcp_port: u16,
 }
 
+impl From<crate::api::Server> for Server {
+    fn from(s: crate::api::Server) -> Self {
+        Self {
+            code: s.code,
+            name: s.name,
+            ip: s.ip,
+            udp_port: s.udp_port,
+            tcp_port: s.tcp_port,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerList {
+    pub servers: Vec<Server>,
+    pub cached: bool,
+    pub stale: bool,
+    pub fetched_at: String,
+}
+
+impl ServerList {
+    fn from_cache(cache: ServerCache, cached: bool, stale: bool) -> Self {
+        Self {
+            servers: cache.servers.into_iter().map(Server::from).collect(),
+            cached,
+            stale,
+            fetched_at: cache.fetched_at.to_rfc3339(),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct VpnConfig {
     pub name: String,
@@ -30,10 +84,11 @@ pub struct VpnConfig {
 
 #[tauri::command]
 pub async fn verify_api_key(
+    app: tauri::AppHandle,
     state: State<'_, AppState>,
+    client: State<'_, ApiClient>,
     api_key: String,
 ) -> Result<VerifyResponse, String> {
-    let client = ApiClient::new();
     let response = client
         .verify_api_key(&api_key)
         .await
@@ -43,6 +98,10 @@ pub async fn verify_api_key(
     
     state.set_api_key(api_key);
 
+    tauri::async_runtime::spawn(async move {
+        crate::sync::run_and_notify(&app).await;
+    });
+
     Ok(VerifyResponse {
         valid: response.valid,
         username: response.username,
@@ -69,25 +128,124 @@ pub async fn delete_api_key_from_disk() -> Result<(), String> {
 }
 
 #[tauri::command]
-pub async fn list_servers() -> Result<Vec<Server>, String> {
-    let client = ApiClient::new();
-    let servers = client.list_servers().await.map_err(|e| e.to_string())?;
+pub async fn get_settings(settings: State<'_, SettingsStore>) -> Result<Settings, String> {
+    Ok(settings.get())
+}
+
+#[tauri::command]
+pub async fn update_settings(
+    app: tauri::AppHandle,
+    settings: State<'_, SettingsStore>,
+    patch: serde_json::Value,
+) -> Result<Settings, String> {
+    let updated = settings.update(patch).map_err(|e| e.to_string())?;
+    app.emit("settings-changed", &updated).ok();
+    Ok(updated)
+}
+
+#[tauri::command]
+pub async fn get_policy_status(settings: State<'_, SettingsStore>) -> Result<PolicyStatus, String> {
+    Ok(settings.policy().status())
+}
+
+#[tauri::command]
+pub async fn list_servers(
+    app: tauri::AppHandle,
+    client: State<'_, ApiClient>,
+    settings: State<'_, SettingsStore>,
+) -> Result<ServerList, String> {
+    let ttl = settings.get().server_cache_ttl_secs;
+
+    match ServerCache::load() {
+        Some(cache) if cache.is_fresh(ttl) => Ok(ServerList::from_cache(cache, true, false)),
+        Some(cache) => {
+            // Serve the stale copy right away and refresh it in the background
+            let stale = ServerList::from_cache(cache.clone(), true, true);
+            tauri::async_runtime::spawn(async move {
+                let client = app.state::<ApiClient>();
+                if let Ok(updated) = server_cache::revalidate(&client, Some(cache)).await {
+                    app.emit("servers-updated", ServerList::from_cache(updated, false, false))
+                        .ok();
+                }
+            });
+            Ok(stale)
+        }
+        None => {
+            let cache = server_cache::revalidate(&client, None)
+                .await
+                .map_err(|e| e.to_string())?;
+            Ok(ServerList::from_cache(cache, false, false))
+        }
+    }
+}
+
+pub(crate) async fn probe_with_cache(
+    state: &AppState,
+    servers: &[crate::api::Server],
+    timeout: std::time::Duration,
+    force: bool,
+) -> Vec<ProbeResult> {
+    let mut results = Vec::new();
+    let mut stale = Vec::new();
+
+    for server in servers {
+        match state.get_cached_probe(&server.code, probe::PROBE_CACHE_TTL) {
+            Some(cached) if !force => results.push(cached),
+            _ => stale.push(server.clone()),
+        }
+    }
+
+    for result in probe::probe_servers(&stale, timeout).await {
+        state.cache_probe(result.clone());
+        results.push(result);
+    }
+
+    results
+}
+
+#[tauri::command]
+pub async fn probe_servers(
+    state: State<'_, AppState>,
+    client: State<'_, ApiClient>,
+    settings: State<'_, SettingsStore>,
+    force: Option<bool>,
+) -> Result<Vec<ProbeResult>, String> {
+    let settings = settings.get();
+    let servers = server_cache::current_servers(&client, settings.server_cache_ttl_secs)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let timeout = std::time::Duration::from_millis(settings.probe_timeout_ms);
+    Ok(probe_with_cache(&state, &servers, timeout, force.unwrap_or(false)).await)
+}
+
+#[tauri::command]
+pub async fn pick_best_server(
+    state: State<'_, AppState>,
+    client: State<'_, ApiClient>,
+    settings: State<'_, SettingsStore>,
+    region: Option<String>,
+) -> Result<Vec<ServerRanking>, String> {
+    let settings = settings.get();
+    let servers = server_cache::current_servers(&client, settings.server_cache_ttl_secs)
+        .await
+        .map_err(|e| e.to_string())?;
 
-    Ok(servers
+    let timeout = std::time::Duration::from_millis(settings.probe_timeout_ms);
+    let probes = probe_with_cache(&state, &servers, timeout, false)
+        .await
         .into_iter()
-        .map(|s| Server {
-            code: s.code,
-            name: s.name,
-            ip: s.ip,
-            udp_port: s.udp_port,
-            tcp_port: s.tcp_port,
-        })
-        .collect())
+        .map(|p| (p.code.clone(), p))
+        .collect();
+
+    Ok(probe::rank_servers(&servers, &probes, region.or(settings.preferred_region).as_deref()))
 }
 
 #[tauri::command]
 pub async fn generate_config(
     state: State<'_, AppState>,
+    client: State<'_, ApiClient>,
+    settings: State<'_, SettingsStore>,
     username: String,
     password: String,
     email: Option<String>,
@@ -96,7 +254,10 @@ pub async fn generate_config(
     expiry_days: Option<i32>,
 ) -> Result<VpnConfig, String> {
     let api_key = state.get_api_key().ok_or("API key not set")?;
-    let client = ApiClient::new();
+    settings
+        .policy()
+        .check_server(&server_code)
+        .map_err(|e| e.to_string())?;
 
     let request = GenerateRequest {
         username: username.clone(),
@@ -111,15 +272,30 @@ pub async fn generate_config(
         .generate_config(&api_key, &request)
         .await
         .map_err(|e| e.to_string())?;
+    let expires_at = expiry::profile_expiry(&config_content, expiry::expiry_from_days(expiry_days));
 
     let manager = OpenVpnManager::new();
-    let config_name = format!("{}-{}-{}", username, server_code, protocol);
+    let config_name = ProfileName::sanitize(&format!("{}-{}-{}", username, server_code, protocol))
+        .map_err(|e| e.to_string())?;
+    manager
+        .save_config(&config_name, &config_content, ChangeReason::Generated)
+        .map_err(|e| e.to_string())?;
     manager
-        .save_config(&config_name, &config_content)
+        .save_meta(
+            &config_name,
+            &ProfileMeta {
+                source: ProfileSource::Generated,
+                server_code: Some(server_code.clone()),
+                username: Some(username),
+                synced_hash: Some(content_hash(&config_content)),
+                expires_at: expires_at.map(|e| e.to_rfc3339()),
+                ..Default::default()
+            },
+        )
         .map_err(|e| e.to_string())?;
 
     Ok(VpnConfig {
-        name: config_name,
+        name: config_name.to_string(),
         server: server_code,
         protocol,
         created_at: chrono::Utc::now().to_rfc3339(),
@@ -127,20 +303,392 @@ pub async fn generate_config(
 }
 
 #[tauri::command]
-pub async fn import_config(name: String, content: String) -> Result<VpnConfig, String> {
+pub async fn inspect_profile(name: ProfileName) -> Result<ProfileInspection, String> {
+    let manager = OpenVpnManager::new();
+    let content = manager.load_config(&name).map_err(|e| e.to_string())?;
+    Ok(inspect::inspect(&content))
+}
+
+#[tauri::command]
+pub async fn audit_profile(name: ProfileName) -> Result<AuditReport, String> {
+    let manager = OpenVpnManager::new();
+    let content = manager.load_config(&name).map_err(|e| e.to_string())?;
+    Ok(AuditReport {
+        findings: audit::audit(&Profile::parse(&content)),
+        fixed: Vec::new(),
+    })
+}
+
+/// Applies the audit's automatic fixes and saves the profile if anything changed.
+#[tauri::command]
+pub async fn fix_profile(name: ProfileName) -> Result<AuditReport, String> {
+    let manager = OpenVpnManager::new();
+    let content = manager.load_config(&name).map_err(|e| e.to_string())?;
+
+    let mut profile = Profile::parse(&content);
+    let before = audit::audit(&profile);
+    audit::fix(&mut profile);
+    let findings = audit::audit(&profile);
+
+    let fixed_content = profile.to_string();
+    if fixed_content != content {
+        manager
+            .save_config(&name, &fixed_content, ChangeReason::Fixed)
+            .map_err(|e| e.to_string())?;
+    }
+
+    Ok(AuditReport {
+        fixed: before.into_iter().filter(|f| !findings.contains(f)).collect(),
+        findings,
+    })
+}
+
+#[tauri::command]
+pub async fn get_profile_settings(name: ProfileName) -> Result<ProfileSettings, String> {
+    let manager = OpenVpnManager::new();
+    let content = manager.load_config(&name).map_err(|e| e.to_string())?;
+    Ok(editor::settings(&Profile::parse(&content)))
+}
+
+#[tauri::command]
+pub async fn edit_profile(
+    client: State<'_, ApiClient>,
+    store: State<'_, SettingsStore>,
+    name: ProfileName,
+    edit: ProfileEdit,
+) -> Result<ProfileSettings, String> {
+    let manager = OpenVpnManager::new();
+    let content = manager.load_config(&name).map_err(|e| e.to_string())?;
+
+    let mut profile = Profile::parse(&content);
+    editor::apply(&mut profile, &edit).map_err(|e| e.to_string())?;
+
+    // New remotes must point at servers the policy allows
+    let policy = store.policy();
+    if edit.remotes.is_some() && policy.allowed_servers.is_some() {
+        let remotes: Vec<String> = editor::settings(&profile)
+            .remotes
+            .into_iter()
+            .map(|r| r.host)
+            .collect();
+        let servers = server_cache::current_servers(&client, store.get().server_cache_ttl_secs)
+            .await
+            .unwrap_or_default();
+        policy
+            .check_profile(name.as_str(), &manager.load_meta(&name), &remotes, &servers)
+            .map_err(|e| e.to_string())?;
+    }
+
+    let edited = profile.to_string();
+    if edited != content {
+        manager
+            .save_config(&name, &edited, ChangeReason::Edited)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(editor::settings(&profile))
+}
+
+#[tauri::command]
+pub async fn list_profile_versions(name: ProfileName) -> Result<Vec<ProfileVersion>, String> {
+    Ok(history::list(&name))
+}
+
+/// Line diff between two versions; a missing id stands for the current content.
+#[tauri::command]
+pub async fn diff_profile_versions(
+    name: ProfileName,
+    from: Option<u64>,
+    to: Option<u64>,
+) -> Result<Vec<DiffLine>, String> {
+    let manager = OpenVpnManager::new();
+    let content = |id: Option<u64>| match id {
+        Some(id) => history::load(&name, id),
+        None => manager.load_config(&name),
+    };
+    let old = content(from).map_err(|e| e.to_string())?;
+    let new = content(to).map_err(|e| e.to_string())?;
+    Ok(history::diff(&old, &new))
+}
+
+/// Puts an earlier version back; the content it replaces becomes a version too.
+#[tauri::command]
+pub async fn restore_profile_version(name: ProfileName, id: u64) -> Result<(), String> {
+    let manager = OpenVpnManager::new();
+    let content = history::load(&name, id).map_err(|e| e.to_string())?;
+    manager
+        .save_config(&name, &content, ChangeReason::Restored)
+        .map_err(|e| e.to_string())?;
+
+    if let Some(expiry) = expiry::cert_not_after(&content) {
+        let mut meta = manager.load_meta(&name);
+        meta.expires_at = Some(expiry.to_rfc3339());
+        manager.save_meta(&name, &meta).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn rename_config(
+    state: State<'_, AppState>,
+    name: ProfileName,
+    new_name: ProfileName,
+) -> Result<(), String> {
+    if state.get_connection().is_some_and(|c| c.config_name == name.as_str()) {
+        return Err("Disconnect before renaming this profile".to_string());
+    }
+    let manager = OpenVpnManager::new();
+    manager.rename_config(&name, &new_name).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn duplicate_config(name: ProfileName, new_name: ProfileName) -> Result<(), String> {
+    let manager = OpenVpnManager::new();
+    manager.duplicate_config(&name, &new_name).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn expiring_profiles(
+    settings: State<'_, SettingsStore>,
+    within_days: Option<i64>,
+) -> Result<Vec<ExpiringProfile>, String> {
+    let within_days = within_days.unwrap_or(settings.get().expiry_warning_days as i64);
+    Ok(expiry::expiring_profiles(&OpenVpnManager::new(), within_days))
+}
+
+/// Regenerates a generated profile through the portal and replaces it under
+/// the same name. Username and password default to the saved credentials.
+#[tauri::command]
+pub async fn renew_config(
+    state: State<'_, AppState>,
+    client: State<'_, ApiClient>,
+    settings: State<'_, SettingsStore>,
+    name: ProfileName,
+    username: Option<String>,
+    password: Option<String>,
+    expiry_days: Option<i32>,
+) -> Result<VpnConfig, String> {
+    let api_key = state.get_api_key().ok_or("API key not set")?;
     let manager = OpenVpnManager::new();
+
+    let mut meta = manager.load_meta(&name);
+    let server_code = meta
+        .server_code
+        .clone()
+        .filter(|_| meta.source != ProfileSource::Imported)
+        .ok_or_else(|| format!("{} was not generated from the portal and cannot be renewed", name))?;
+    settings
+        .policy()
+        .check_server(&server_code)
+        .map_err(|e| e.to_string())?;
+
+    let content = manager.load_config(&name).map_err(|e| e.to_string())?;
+    let protocol = content
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("proto "))
+        .map(|proto| proto.trim().trim_end_matches("-client").to_string())
+        .unwrap_or_else(|| "udp".to_string());
+
+    let saved = manager.load_credentials(&name).unwrap_or_default();
+    let mut saved = saved.lines();
+    let username = username
+        .or_else(|| saved.next().map(str::to_string))
+        .filter(|u| !u.is_empty())
+        .ok_or_else(|| format!("Save credentials for {} before renewing it", name))?;
+    let password = password
+        .or_else(|| saved.next().map(str::to_string))
+        .ok_or_else(|| format!("Save credentials for {} before renewing it", name))?;
+
+    let request = GenerateRequest {
+        username: username.clone(),
+        password,
+        email: None,
+        server_code: server_code.clone(),
+        protocol: protocol.clone(),
+        expiry_days,
+    };
+
+    let config_content = client
+        .generate_config(&api_key, &request)
+        .await
+        .map_err(|e| e.to_string())?;
+
     manager
-        .save_config(&name, &content)
+        .save_config(&name, &config_content, ChangeReason::Renewed)
         .map_err(|e| e.to_string())?;
 
+    // The renewal is a new config on the portal; staying linked to the old
+    // one would make the next sync pull it back over the renewed profile
+    let regenerated =
+        crate::sync::find_regenerated(&client, &manager, &api_key, meta.remote_id.as_deref(), &config_content)
+            .await
+            .unwrap_or(None);
+
+    meta.status = ProfileStatus::Active;
+    meta.username = Some(username);
+    meta.remote_hash = None;
+    meta.synced_at = regenerated.is_some().then(|| chrono::Utc::now().to_rfc3339());
+    meta.remote_id = regenerated;
+    meta.synced_hash = Some(content_hash(&config_content));
+    meta.expires_at = expiry::profile_expiry(&config_content, expiry::expiry_from_days(expiry_days))
+        .map(|e| e.to_rfc3339());
+    manager.save_meta(&name, &meta).map_err(|e| e.to_string())?;
+
+    Ok(VpnConfig {
+        name: name.to_string(),
+        server: server_code,
+        protocol,
+        created_at: chrono::Utc::now().to_rfc3339(),
+    })
+}
+
+#[tauri::command]
+pub async fn sync_configs(
+    state: State<'_, AppState>,
+    client: State<'_, ApiClient>,
+) -> Result<SyncReport, String> {
+    let api_key = state.get_api_key().ok_or("API key not set")?;
+    let manager = OpenVpnManager::new();
+    crate::sync::sync_configs(&client, &manager, &api_key)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn resolve_sync_conflict(
+    state: State<'_, AppState>,
+    client: State<'_, ApiClient>,
+    name: ProfileName,
+    keep_remote: bool,
+) -> Result<(), String> {
+    let api_key = state.get_api_key().ok_or("API key not set")?;
+    let manager = OpenVpnManager::new();
+    crate::sync::resolve_conflict(&client, &manager, &api_key, &name, keep_remote)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn import_config(
+    settings: State<'_, SettingsStore>,
+    name: ProfileName,
+    content: String,
+) -> Result<VpnConfig, String> {
+    settings.policy().check_import().map_err(|e| e.to_string())?;
+    let manager = OpenVpnManager::new();
+    importer::save_profile(&manager, &name, &content, None).map_err(|e| e.to_string())?;
+
     Ok(VpnConfig {
-        name,
+        name: name.to_string(),
         server: "imported".to_string(),
         protocol: "unknown".to_string(),
         created_at: chrono::Utc::now().to_rfc3339(),
     })
 }
 
+#[tauri::command]
+pub async fn import_config_from_path(
+    settings: State<'_, SettingsStore>,
+    path: String,
+    name: Option<ProfileName>,
+) -> Result<Vec<ImportReport>, String> {
+    settings.policy().check_import().map_err(|e| e.to_string())?;
+    let manager = OpenVpnManager::new();
+    importer::import_path(&manager, std::path::Path::new(&path), name).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn import_networkmanager(
+    settings: State<'_, SettingsStore>,
+    path: Option<String>,
+) -> Result<Vec<ImportReport>, String> {
+    settings.policy().check_import().map_err(|e| e.to_string())?;
+    let manager = OpenVpnManager::new();
+    networkmanager::import(&manager, path.as_deref().map(std::path::Path::new)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn import_openvpn_gui(
+    settings: State<'_, SettingsStore>,
+    dir: Option<String>,
+) -> Result<Vec<ImportReport>, String> {
+    settings.policy().check_import().map_err(|e| e.to_string())?;
+    let manager = OpenVpnManager::new();
+    importer::import_openvpn_gui(&manager, dir.as_deref().map(std::path::Path::new)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn export_networkmanager(
+    name: ProfileName,
+    dir: String,
+    include_credentials: Option<bool>,
+) -> Result<NmExport, String> {
+    let manager = OpenVpnManager::new();
+    networkmanager::export(&manager, &name, std::path::Path::new(&dir), include_credentials.unwrap_or(false))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn export_bundle(
+    names: Option<Vec<ProfileName>>,
+    path: String,
+    passphrase: Option<String>,
+) -> Result<BundleExport, String> {
+    let manager = OpenVpnManager::new();
+    bundle::export(&manager, names.as_deref(), std::path::Path::new(&path), passphrase.as_deref())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn preview_bundle(path: String) -> Result<BundlePreview, String> {
+    let manager = OpenVpnManager::new();
+    bundle::preview(&manager, std::path::Path::new(&path)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn import_bundle(
+    client: State<'_, ApiClient>,
+    settings: State<'_, SettingsStore>,
+    path: String,
+    passphrase: Option<String>,
+    collisions: Option<HashMap<String, Collision>>,
+) -> Result<Vec<ImportReport>, String> {
+    settings.policy().check_import().map_err(|e| e.to_string())?;
+    let manager = OpenVpnManager::new();
+    let servers = server_cache::current_servers(&client, settings.get().server_cache_ttl_secs)
+        .await
+        .unwrap_or_default();
+    bundle::import(
+        &manager,
+        std::path::Path::new(&path),
+        passphrase.as_deref(),
+        &collisions.unwrap_or_default(),
+        &servers,
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn backup_app_data(path: String, passphrase: String) -> Result<BackupReport, String> {
+    backup::backup(std::path::Path::new(&path), &passphrase).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn restore_app_data(
+    app: tauri::AppHandle,
+    settings: State<'_, SettingsStore>,
+    path: String,
+    passphrase: String,
+) -> Result<RestoreReport, String> {
+    // A backup can carry profiles from anywhere, like an import
+    settings.policy().check_import().map_err(|e| e.to_string())?;
+    let manager = OpenVpnManager::new();
+    let report = backup::restore(&manager, std::path::Path::new(&path), &passphrase).map_err(|e| e.to_string())?;
+
+    let updated = settings.reload().map_err(|e| e.to_string())?;
+    app.emit("settings-changed", &updated).ok();
+    Ok(report)
+}
+
 #[cfg(target_os = "windows")]
 fn get_system_architecture() -> String {
     use std::process::Command;
@@ -223,48 +771,339 @@ pub async fn list_configs() -> Result<Vec<VpnConfig>, String> {
     manager.list_configs().map_err(|e| e.to_string())
 }
 
+#[derive(Debug, Serialize)]
+pub struct DeleteReport {
+    pub removed: bool,
+    pub revoked: bool,
+    /// Set when the local files are gone but revoking on the portal failed.
+    pub pending: Option<PendingOperation>,
+    pub error: Option<String>,
+}
+
+fn revoke_target(manager: &OpenVpnManager, name: &ProfileName) -> Option<RevokeTarget> {
+    let meta = manager.load_meta(name);
+    if let Some(id) = meta.remote_id {
+        return Some(RevokeTarget::Config { id });
+    }
+    if meta.source != ProfileSource::Generated {
+        return None;
+    }
+
+    let username = meta.username.or_else(|| {
+        manager
+            .load_credentials(name)
+            .ok()
+            .and_then(|c| c.lines().next().map(str::to_string))
+    })?;
+    Some(RevokeTarget::Account {
+        username,
+        server_code: meta.server_code?,
+    })
+}
+
 #[tauri::command]
-pub async fn delete_config(name: String) -> Result<(), String> {
+pub async fn delete_config(
+    state: State<'_, AppState>,
+    client: State<'_, ApiClient>,
+    name: ProfileName,
+    revoke: Option<bool>,
+) -> Result<DeleteReport, String> {
     let manager = OpenVpnManager::new();
-    manager.delete_config(&name).map_err(|e| e.to_string())
+    let target = revoke_target(&manager, &name);
+
+    manager.delete_config(&name).map_err(|e| e.to_string())?;
+
+    let mut report = DeleteReport {
+        removed: true,
+        revoked: false,
+        pending: None,
+        error: None,
+    };
+    if !revoke.unwrap_or(false) {
+        return Ok(report);
+    }
+    let Some(target) = target else {
+        report.error = Some(format!("{} is not linked to a portal account", name));
+        return Ok(report);
+    };
+
+    let result = match state.get_api_key() {
+        Some(api_key) => client.revoke(&api_key, &target).await,
+        None => Err(anyhow::anyhow!("API key not set")),
+    };
+
+    match result {
+        Ok(()) => report.revoked = true,
+        Err(e) => {
+            report.error = Some(e.to_string());
+            report.pending = Some(
+                pending::enqueue(name.as_str(), PendingAction::Revoke { target }, e.to_string())
+                    .await
+                    .map_err(|e| e.to_string())?,
+            );
+        }
+    }
+
+    Ok(report)
+}
+
+#[tauri::command]
+pub async fn list_pending_operations() -> Result<Vec<PendingOperation>, String> {
+    Ok(pending::load())
+}
+
+#[tauri::command]
+pub async fn retry_pending_operations(
+    state: State<'_, AppState>,
+    client: State<'_, ApiClient>,
+) -> Result<Vec<PendingOperation>, String> {
+    let api_key = state.get_api_key().ok_or("API key not set")?;
+    pending::retry_all(&client, &api_key)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn discard_pending_operation(id: String) -> Result<(), String> {
+    pending::discard(&id).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn save_credentials(config_name: String, credentials: String) -> Result<(), String> {
+pub async fn save_credentials(config_name: ProfileName, credentials: String) -> Result<(), String> {
     let manager = OpenVpnManager::new();
     manager.save_credentials(&config_name, &credentials).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn load_credentials(config_name: String) -> Result<String, String> {
+pub async fn load_credentials(config_name: ProfileName) -> Result<String, String> {
     let manager = OpenVpnManager::new();
     manager.load_credentials(&config_name).map_err(|e| e.to_string())
 }
 
+/// The profile rewritten to reach its server over TCP, when it starts over
+/// UDP and a TCP endpoint is known.
+async fn fallback_config(
+    client: &ApiClient,
+    manager: &OpenVpnManager,
+    config_name: &ProfileName,
+    content: &str,
+    settings: &Settings,
+) -> Result<Option<String>, String> {
+    let profile = Profile::parse(content);
+    if !fallback::starts_with_udp(&profile) {
+        return Ok(None);
+    }
+
+    let servers = server_cache::current_servers(client, settings.server_cache_ttl_secs)
+        .await
+        .unwrap_or_default();
+    match fallback::tcp_remote(&profile, &manager.load_meta(config_name), &servers) {
+        Some(remote) => fallback::tcp_config(content, remote).map(Some).map_err(|e| e.to_string()),
+        None => Ok(None),
+    }
+}
+
+/// Waits until the tunnel is up, OpenVPN exits or the timeout passes.
+async fn wait_for_handshake(manager: &OpenVpnManager, timeout_secs: u64) -> bool {
+    let deadline = tokio::time::Instant::now() + tokio::time::Duration::from_secs(timeout_secs);
+    while tokio::time::Instant::now() < deadline {
+        if manager.handshake_completed() {
+            return true;
+        }
+        if !manager.is_connected() {
+            return false;
+        }
+        tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+    }
+    manager.handshake_completed()
+}
+
+/// Starts OpenVPN, first over the profile's own transport and then over the
+/// TCP fallback when one is given. Returns whether the fallback is in use.
+async fn start_openvpn(
+    manager: &OpenVpnManager,
+    config_name: &ProfileName,
+    settings: &Settings,
+    ordered: Option<String>,
+    tcp_config: Option<String>,
+) -> Result<bool, String> {
+    let mut over_tcp_fallback = false;
+    match tcp_config {
+        Some(tcp_config) => {
+            let network = fallback::current_network();
+            let mut attempts = vec![
+                (Transport::Udp, ordered.as_deref()),
+                (Transport::Tcp, Some(tcp_config.as_str())),
+            ];
+            if network.as_deref().and_then(fallback::remembered) == Some(Transport::Tcp) {
+                attempts.reverse();
+            }
+
+            let last = attempts.len() - 1;
+            for (i, (transport, derived)) in attempts.into_iter().enumerate() {
+                manager
+                    .connect(config_name, settings, derived)
+                    .map_err(|e| e.to_string())?;
+                over_tcp_fallback = transport == Transport::Tcp;
+                if wait_for_handshake(manager, settings.tcp_fallback_timeout_secs).await {
+                    if let Some(network) = &network {
+                        fallback::remember(network, transport).ok();
+                    }
+                    break;
+                }
+                // The last attempt keeps running, OpenVPN retries on its own
+                if i < last {
+                    manager.disconnect().ok();
+                }
+            }
+        }
+        None => {
+            manager
+                .connect(config_name, settings, ordered.as_deref())
+                .map_err(|e| e.to_string())?;
+
+            tokio::time::sleep(tokio::time::Duration::from_secs(settings.connect_wait_secs)).await;
+        }
+    }
+
+    Ok(over_tcp_fallback)
+}
+
 #[tauri::command]
 pub async fn connect_vpn(
     state: State<'_, AppState>,
-    config_name: String,
+    client: State<'_, ApiClient>,
+    store: State<'_, SettingsStore>,
+    config_name: ProfileName,
+    tcp_fallback: Option<bool>,
+) -> Result<(), String> {
+    state.next_generation();
+    connect_profile(state, client, store, config_name, tcp_fallback).await
+}
+
+/// Connects like [`connect_vpn`] without counting as a change the user made.
+pub async fn connect_profile(
+    state: State<'_, AppState>,
+    client: State<'_, ApiClient>,
+    store: State<'_, SettingsStore>,
+    config_name: ProfileName,
+    tcp_fallback: Option<bool>,
 ) -> Result<(), String> {
+    let settings = store.get();
     let manager = OpenVpnManager::new();
-    manager.connect(&config_name).map_err(|e| e.to_string())?;
 
-    tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+    let policy = store.policy();
+    let remotes = manager.remote_hosts(&config_name).map_err(|e| e.to_string())?;
+    let servers = if policy.allowed_servers.is_some() {
+        server_cache::current_servers(&client, settings.server_cache_ttl_secs)
+            .await
+            .unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+    policy
+        .check_profile(
+            config_name.as_str(),
+            &manager.load_meta(&config_name),
+            &remotes,
+            &servers,
+        )
+        .map_err(|e| e.to_string())?;
+
+    let content = manager.load_config(&config_name).map_err(|e| e.to_string())?;
+    let ordered = remotes::reordered(&config_name, &content).map_err(|e| e.to_string())?;
+
+    let tcp_config = if tcp_fallback.unwrap_or(settings.tcp_fallback) {
+        fallback_config(&client, &manager, &config_name, &content, &settings).await?
+    } else {
+        None
+    };
+
+    let (ordered, tcp_config) = if settings.kill_switch {
+        let primary = ordered.unwrap_or_else(|| content.clone());
+        let rules_settings = settings.clone();
+        let (primary, tcp_config) = tokio::task::spawn_blocking(move || -> anyhow::Result<_> {
+            let (primary, mut endpoints) = killswitch::pin_remotes(&primary)?;
+            let tcp_config = match tcp_config {
+                Some(tcp_config) => {
+                    let (tcp_config, tcp_endpoints) = killswitch::pin_remotes(&tcp_config)?;
+                    endpoints.extend(tcp_endpoints);
+                    Some(tcp_config)
+                }
+                None => None,
+            };
+            killswitch::enable(&endpoints, &killswitch::devices(&primary), &rules_settings)?;
+            Ok((primary, tcp_config))
+        })
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())?;
+        (Some(primary), tcp_config)
+    } else {
+        // Rules kept for a reconnect before the setting was turned off
+        killswitch::disable().map_err(|e| e.to_string())?;
+        (ordered, tcp_config)
+    };
+
+    let started = start_openvpn(&manager, &config_name, &settings, ordered, tcp_config).await;
+    let over_tcp_fallback = match started {
+        Ok(over_tcp_fallback) => over_tcp_fallback,
+        Err(e) => {
+            killswitch::disable().ok();
+            return Err(e);
+        }
+    };
+
+    // The TCP fallback connects to a remote of its own, which says nothing
+    // about the profile's remotes
+    let in_use = if over_tcp_fallback {
+        None
+    } else if manager.handshake_completed() {
+        let (_, _, srv_ip, srv_port, _) = manager.get_connection_details(&config_name).unwrap_or_default();
+        let in_use = remotes::identify_blocking(&content, &srv_ip, srv_port).await;
+        if in_use.is_some() {
+            remotes::record(&config_name, &content, in_use).ok();
+        }
+        in_use
+    } else {
+        if !manager.is_connected() && remotes::unreachable(&manager.session_log()) {
+            remotes::record(&config_name, &content, None).ok();
+        }
+        None
+    };
     
     if manager.is_connected() {
         let (ipv4, ipv6, srv_ip, srv_port, proto) = manager
             .get_connection_details(&config_name)
             .unwrap_or_default();
+
+        let mut meta = manager.load_meta(&config_name);
+        let remembered = (meta.pushed_networks.clone(), meta.tunnel_network.clone());
+        let subnet_conflicts = subnets::check_after(&content, &manager.session_log(), &mut meta);
+        if (&meta.pushed_networks, &meta.tunnel_network) != (&remembered.0, &remembered.1) {
+            manager.save_meta(&config_name, &meta).ok();
+        }
+
+        if settings.kill_switch && !ipv4.is_empty() {
+            if let Err(e) = killswitch::allow_tunnel(&ipv4) {
+                manager.disconnect().ok();
+                killswitch::disable().ok();
+                return Err(e.to_string());
+            }
+        }
         
         state.set_connection(Some(VpnConnection {
-            config_name,
+            config_name: config_name.to_string(),
             server: "unknown".to_string(),
+            remote: in_use.and_then(|i| remotes::label(&content, i)),
             server_ip: srv_ip,
             server_port: srv_port,
             protocol: proto,
             private_ipv4: ipv4,
             private_ipv6: ipv6,
             connected_at: chrono::Utc::now().to_rfc3339(),
+            quality: None,
+            subnet_conflicts,
             bytes_sent: 0,
             bytes_received: 0,
             speed_up: 0,
@@ -272,15 +1111,21 @@ pub async fn connect_vpn(
         }));
         Ok(())
     } else {
+        killswitch::disable().ok();
         Err("Failed to connect - OpenVPN process not running".to_string())
     }
 }
 
 #[tauri::command]
-pub async fn check_openvpn() -> Result<(), String> {
+#[cfg_attr(not(target_os = "windows"), allow(unused_variables))]
+pub async fn check_openvpn(settings: State<'_, SettingsStore>) -> Result<(), String> {
     #[cfg(target_os = "windows")]
     {
-        let openvpn_path = r"C:\Program Files\OpenVPN\bin\openvpn.exe";
+        let settings = settings.get();
+        let openvpn_path = settings
+            .openvpn_path
+            .as_deref()
+            .unwrap_or(r"C:\Program Files\OpenVPN\bin\openvpn.exe");
         if !std::path::Path::new(openvpn_path).exists() {
             return Err("OpenVPN not installed".to_string());
         }
@@ -290,23 +1135,57 @@ pub async fn check_openvpn() -> Result<(), String> {
 
 #[tauri::command]
 pub async fn disconnect_vpn(state: State<'_, AppState>) -> Result<(), String> {
+    state.next_generation();
     let manager = OpenVpnManager::new();
     manager.disconnect().map_err(|e| e.to_string())?;
 
     state.set_connection(None);
 
-    Ok(())
+    killswitch::disable().map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn get_config_ip(config_name: String) -> Result<String, String> {
+pub async fn get_config_ip(config_name: ProfileName) -> Result<String, String> {
     let manager = OpenVpnManager::new();
     manager.get_config_ip(&config_name).map_err(|e| e.to_string())
 }
 
+/// Overlaps between the profile's networks, including the ones its server
+/// pushed last time, and the local network. Meant to be shown before
+/// connecting.
+#[tauri::command]
+pub async fn check_subnet_conflicts(name: ProfileName) -> Result<Vec<SubnetConflict>, String> {
+    let manager = OpenVpnManager::new();
+    let content = manager.load_config(&name).map_err(|e| e.to_string())?;
+    Ok(subnets::check_before(&content, &manager.load_meta(&name)))
+}
+
+/// The profile's remotes with their connection history, marking the one in
+/// use when this profile is connected.
+#[tauri::command]
+pub async fn get_profile_remotes(
+    state: State<'_, AppState>,
+    name: ProfileName,
+) -> Result<Vec<RemoteStatus>, String> {
+    let manager = OpenVpnManager::new();
+    let content = manager.load_config(&name).map_err(|e| e.to_string())?;
+    let in_use = match state
+        .get_connection()
+        .filter(|conn| conn.config_name == name.as_str() && manager.is_connected())
+    {
+        Some(conn) => remotes::identify_blocking(&content, &conn.server_ip, conn.server_port).await,
+        None => None,
+    };
+    Ok(remotes::status(&name, &content, in_use))
+}
+
 #[tauri::command]
-pub async fn get_vpn_status(state: State<'_, AppState>) -> Result<Option<VpnConnection>, String> {
+pub async fn get_vpn_status(
+    state: State<'_, AppState>,
+    settings: State<'_, SettingsStore>,
+) -> Result<Option<VpnConnection>, String> {
     let manager = OpenVpnManager::new();
+    let interval = settings.get().status_poll_interval_secs;
     
     if !manager.is_connected() {
         state.set_connection(None);
@@ -319,18 +1198,21 @@ pub async fn get_vpn_status(state: State<'_, AppState>) -> Result<Option<VpnConn
             let prev_recv = conn.bytes_received;
             
             conn.speed_up = if sent > prev_sent { 
-                (sent - prev_sent) / 3 
+                (sent - prev_sent) / interval 
             } else { 0 };
             
             conn.speed_down = if recv > prev_recv {
-                (recv - prev_recv) / 3
+                (recv - prev_recv) / interval
             } else { 0 };
             
             conn.bytes_sent = sent;
             conn.bytes_received = recv;
         }
         
-        if let Ok((ipv4, ipv6, srv_ip, srv_port, proto)) = manager.get_connection_details(&conn.config_name) {
+        let details = ProfileName::parse(&conn.config_name)
+            .map_err(anyhow::Error::from)
+            .and_then(|name| manager.get_connection_details(&name));
+        if let Ok((ipv4, ipv6, srv_ip, srv_port, proto)) = details {
             if !ipv4.is_empty() {
                 conn.private_ipv4 = ipv4;
             }
@@ -338,6 +1220,18 @@ pub async fn get_vpn_status(state: State<'_, AppState>) -> Result<Option<VpnConn
                 conn.private_ipv6 = ipv6;
             }
             if !srv_ip.is_empty() {
+                // OpenVPN moved on to another remote since the last poll
+                if srv_ip != conn.server_ip || srv_port != conn.server_port {
+                    let content = ProfileName::parse(&conn.config_name)
+                        .ok()
+                        .and_then(|name| manager.load_config(&name).ok());
+                    conn.remote = match content {
+                        Some(content) => remotes::identify_blocking(&content, &srv_ip, srv_port)
+                            .await
+                            .and_then(|index| remotes::label(&content, index)),
+                        None => None,
+                    };
+                }
                 conn.server_ip = srv_ip;
                 conn.server_port = srv_port;
                 conn.protocol = proto;
@@ -357,7 +1251,7 @@ pub async fn get_speed_history() -> Result<Vec<(u64, u64)>, String> {
 }
 
 #[tauri::command]
-pub async fn get_vpn_logs() -> Result<String, String> {
+pub async fn get_vpn_logs(settings: State<'_, SettingsStore>) -> Result<String, String> {
     let manager = OpenVpnManager::new();
-    manager.get_logs().map_err(|e| e.to_string())
+    manager.get_logs(settings.get().log_lines).map_err(|e| e.to_string())
 }
\ No newline at end of file
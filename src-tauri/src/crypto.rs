@@ -0,0 +1,85 @@
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use anyhow::Result;
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose, Engine as _};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+const NONCE_LEN: usize = 12;
+
+// Upper bounds for parameters read from files, which could otherwise make
+// opening one take unbounded memory and time
+const MAX_MEMORY_KIB: u32 = 1024 * 1024;
+const MAX_ITERATIONS: u32 = 16;
+const MAX_PARALLELISM: u32 = 16;
+
+/// Argon2id parameters stored alongside passphrase-encrypted data, so they
+/// can be raised later without breaking older files.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub algorithm: String,
+    pub salt: String,
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl KdfParams {
+    pub fn generate() -> Self {
+        let mut salt = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut salt);
+        Self {
+            algorithm: "argon2id".to_string(),
+            salt: general_purpose::STANDARD.encode(salt),
+            memory_kib: 64 * 1024,
+            iterations: 3,
+            parallelism: 1,
+        }
+    }
+
+    pub fn derive(&self, passphrase: &str) -> Result<SecretKey> {
+        if self.algorithm != "argon2id" {
+            return Err(anyhow::anyhow!("Unsupported key derivation {}", self.algorithm));
+        }
+        let salt = general_purpose::STANDARD.decode(&self.salt)?;
+        let params = Params::new(
+            self.memory_kib.min(MAX_MEMORY_KIB),
+            self.iterations.clamp(1, MAX_ITERATIONS),
+            self.parallelism.clamp(1, MAX_PARALLELISM),
+            Some(32),
+        )
+            .map_err(|e| anyhow::anyhow!("Invalid key derivation parameters: {}", e))?;
+
+        let mut key = [0u8; 32];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|e| anyhow::anyhow!("Key derivation failed: {}", e))?;
+        Ok(SecretKey(key))
+    }
+}
+
+/// AES-256-GCM key derived from a passphrase.
+pub struct SecretKey([u8; 32]);
+
+impl SecretKey {
+    /// Encrypts with a random nonce, returned in front of the ciphertext.
+    pub fn seal(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = Aes256Gcm::new(&self.0.into())
+            .encrypt(Nonce::from_slice(&nonce), plaintext)
+            .map_err(|_| anyhow::anyhow!("Encryption failed"))?;
+        Ok([nonce.as_slice(), &ciphertext].concat())
+    }
+
+    pub fn open(&self, sealed: &[u8]) -> Result<Vec<u8>> {
+        if sealed.len() < NONCE_LEN {
+            return Err(anyhow::anyhow!("Encrypted data is truncated"));
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        Aes256Gcm::new(&self.0.into())
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow::anyhow!("Wrong passphrase or corrupted data"))
+    }
+}
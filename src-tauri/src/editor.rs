@@ -0,0 +1,262 @@
+use crate::profile::{Line, Profile};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+const PROTOCOLS: &[&str] = &[
+    "udp", "udp4", "udp6", "tcp", "tcp4", "tcp6", "tcp-client", "tcp4-client", "tcp6-client",
+];
+
+// Options that run programs or load code; the privilege helper refuses them too
+const FORBIDDEN_DIRECTIVES: &[&str] = &[
+    "up", "down", "route-up", "route-pre-down", "ipchange", "learn-address", "tls-verify",
+    "auth-user-pass-verify", "client-connect", "client-disconnect", "script-security", "plugin",
+    "iproute", "config",
+];
+
+// Edited through their own fields, which keep them consistent
+const STRUCTURED_DIRECTIVES: &[&str] = &["remote", "proto", "verb"];
+
+const MAX_VERB: u8 = 11;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Remote {
+    pub host: String,
+    pub port: Option<u16>,
+    pub proto: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Directive {
+    pub name: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileSettings {
+    pub proto: String,
+    pub remotes: Vec<Remote>,
+    pub verb: Option<u8>,
+    /// Every other directive, in file order.
+    pub directives: Vec<Directive>,
+}
+
+/// Changes to make to a profile. Fields left out are not touched.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ProfileEdit {
+    pub proto: Option<String>,
+    pub remotes: Option<Vec<Remote>>,
+    pub verb: Option<u8>,
+    /// Directives to add, or replace when already present.
+    pub set: Vec<Directive>,
+    /// Directives to remove entirely.
+    pub remove: Vec<String>,
+}
+
+fn is_tcp(proto: &str) -> bool {
+    proto.starts_with("tcp")
+}
+
+fn check_proto(proto: &str) -> Result<()> {
+    if PROTOCOLS.contains(&proto) {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("Unknown protocol {}", proto))
+    }
+}
+
+// A line break in an argument would start a new, unchecked directive
+fn check_arg(arg: &str) -> Result<()> {
+    if arg.contains(['\n', '\r', '\0']) {
+        return Err(anyhow::anyhow!("Invalid argument {:?}", arg));
+    }
+    Ok(())
+}
+
+fn check_directive(name: &str) -> Result<()> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-') {
+        return Err(anyhow::anyhow!("Invalid directive name {:?}", name));
+    }
+    if FORBIDDEN_DIRECTIVES.contains(&name) {
+        return Err(anyhow::anyhow!("{} is not allowed in profiles", name));
+    }
+    if STRUCTURED_DIRECTIVES.contains(&name) {
+        return Err(anyhow::anyhow!("{} is edited through its own field", name));
+    }
+    Ok(())
+}
+
+pub fn settings(profile: &Profile) -> ProfileSettings {
+    let proto = profile
+        .get("proto")
+        .and_then(|a| a.first())
+        .cloned()
+        .unwrap_or_else(|| "udp".to_string());
+
+    let remotes = profile
+        .get_all("remote")
+        .into_iter()
+        .filter_map(|args| {
+            Some(Remote {
+                host: args.first()?.clone(),
+                port: args.get(1).and_then(|p| p.parse().ok()),
+                proto: args.get(2).cloned(),
+            })
+        })
+        .collect();
+
+    let directives = profile
+        .lines
+        .iter()
+        .filter_map(|line| match line {
+            Line::Directive { name, args, .. } if !STRUCTURED_DIRECTIVES.contains(&name.as_str()) => {
+                Some(Directive {
+                    name: name.clone(),
+                    args: args.clone(),
+                })
+            }
+            _ => None,
+        })
+        .collect();
+
+    ProfileSettings {
+        proto,
+        remotes,
+        verb: profile.get("verb").and_then(|a| a.first()).and_then(|v| v.parse().ok()),
+        directives,
+    }
+}
+
+fn remote_line(remote: &Remote) -> Line {
+    let port = remote.port.map(|p| p.to_string());
+    let mut args = vec![remote.host.as_str()];
+    match (&port, &remote.proto) {
+        (Some(port), Some(proto)) => args.extend([port.as_str(), proto.as_str()]),
+        (Some(port), None) => args.push(port),
+        // A per-remote protocol needs the port before it
+        (None, Some(proto)) => args.extend(["1194", proto.as_str()]),
+        (None, None) => {}
+    }
+    Line::directive("remote", &args)
+}
+
+/// Applies an edit to the parsed profile. Lines it does not change, including
+/// comments and inline blocks, are written back exactly as they were.
+pub fn apply(profile: &mut Profile, edit: &ProfileEdit) -> Result<()> {
+    for name in edit.remove.iter().chain(edit.set.iter().map(|d| &d.name)) {
+        check_directive(name)?;
+    }
+    for arg in edit.set.iter().flat_map(|d| &d.args) {
+        check_arg(arg)?;
+    }
+    if let Some(proto) = &edit.proto {
+        check_proto(proto)?;
+    }
+    if let Some(remotes) = &edit.remotes {
+        if remotes.is_empty() {
+            return Err(anyhow::anyhow!("A profile needs at least one remote"));
+        }
+        for remote in remotes {
+            if remote.host.is_empty() || remote.host.contains(char::is_whitespace) {
+                return Err(anyhow::anyhow!("Invalid remote host {:?}", remote.host));
+            }
+            if remote.port == Some(0) {
+                return Err(anyhow::anyhow!("Invalid port for {}", remote.host));
+            }
+            if let Some(proto) = &remote.proto {
+                check_arg(proto)?;
+                check_proto(proto)?;
+            }
+        }
+    }
+    if edit.verb.is_some_and(|v| v > MAX_VERB) {
+        return Err(anyhow::anyhow!("verb must be between 0 and {}", MAX_VERB));
+    }
+
+    if let Some(remotes) = &edit.remotes {
+        profile.replace_all("remote", remotes.iter().map(remote_line).collect());
+    }
+
+    if let Some(proto) = &edit.proto {
+        profile.set("proto", &[proto]);
+
+        // A protocol on a remote line overrides `proto`, so switch those too
+        for line in profile.lines.iter_mut() {
+            if let Line::Directive { name, args, .. } = line {
+                if name == "remote" && args.len() >= 3 && args[2] != *proto {
+                    let args: Vec<&str> = [args[0].as_str(), args[1].as_str(), proto.as_str()]
+                        .into_iter()
+                        .chain(args[3..].iter().map(String::as_str))
+                        .collect();
+                    *line = Line::directive("remote", &args);
+                }
+            }
+        }
+
+        // UDP only; OpenVPN refuses to start with it over TCP
+        if is_tcp(proto) {
+            profile.remove("explicit-exit-notify");
+        }
+    }
+
+    if let Some(verb) = edit.verb {
+        profile.set("verb", &[&verb.to_string()]);
+    }
+
+    for name in &edit.remove {
+        profile.remove(name);
+    }
+    for directive in &edit.set {
+        let args: Vec<&str> = directive.args.iter().map(String::as_str).collect();
+        profile.set(&directive.name, &args);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(set: Vec<Directive>) -> ProfileEdit {
+        ProfileEdit {
+            set,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn refuses_line_breaks_in_arguments() {
+        let mut profile = Profile::parse("client\nremote host 1194\n");
+        for arg in ["\nplugin /tmp/x.so\n#", "a\rup /bin/sh", "a\0b"] {
+            let directive = Directive {
+                name: "auth-nocache".to_string(),
+                args: vec![arg.to_string()],
+            };
+            assert!(apply(&mut profile, &edit(vec![directive])).is_err());
+        }
+
+        let remotes = ProfileEdit {
+            remotes: Some(vec![Remote {
+                host: "host".to_string(),
+                port: Some(1194),
+                proto: Some("udp\nplugin /tmp/x.so".to_string()),
+            }]),
+            ..Default::default()
+        };
+        assert!(apply(&mut profile, &remotes).is_err());
+        assert_eq!(profile.to_string(), "client\nremote host 1194\n");
+    }
+
+    #[test]
+    fn sets_plain_arguments() {
+        let mut profile = Profile::parse("client\n");
+        let directive = Directive {
+            name: "verify-x509-name".to_string(),
+            args: vec!["vpn server".to_string(), "name".to_string()],
+        };
+        apply(&mut profile, &edit(vec![directive])).unwrap();
+        assert!(profile.to_string().contains("verify-x509-name \"vpn server\" name"));
+    }
+}
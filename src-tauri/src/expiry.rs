@@ -0,0 +1,134 @@
+use crate::inspect::inline_block;
+use crate::metadata::{ProfileMeta, ProfileStatus};
+use crate::openvpn::OpenVpnManager;
+use crate::profile_name::ProfileName;
+use crate::settings::SettingsStore;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::HashSet;
+use tauri::{AppHandle, Emitter, Manager};
+
+const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExpiringProfile {
+    pub name: String,
+    pub expires_at: String,
+    pub days_left: i64,
+    pub expired: bool,
+}
+
+/// `notAfter` of the client certificate embedded in the profile.
+pub fn cert_not_after(config: &str) -> Option<DateTime<Utc>> {
+    let pem = inline_block(config, "cert")?;
+    let (_, pem) = x509_parser::pem::parse_x509_pem(pem.as_bytes()).ok()?;
+    let cert = pem.parse_x509().ok()?;
+    DateTime::from_timestamp(cert.validity().not_after.timestamp(), 0)
+}
+
+/// The profile stops working at whichever comes first: the account expiry
+/// requested from the portal or the end of the certificate's validity.
+pub fn profile_expiry(config: &str, account_expiry: Option<DateTime<Utc>>) -> Option<DateTime<Utc>> {
+    match (account_expiry, cert_not_after(config)) {
+        (Some(a), Some(c)) => Some(a.min(c)),
+        (a, c) => a.or(c),
+    }
+}
+
+pub fn expiry_from_days(days: Option<i32>) -> Option<DateTime<Utc>> {
+    days.filter(|d| *d > 0).map(|d| Utc::now() + Duration::days(d as i64))
+}
+
+/// When the profile expires: the recorded expiry, or else the end of its
+/// certificate's validity.
+fn expires_at(manager: &OpenVpnManager, name: &ProfileName, meta: &ProfileMeta) -> Option<DateTime<Utc>> {
+    match meta
+        .expires_at
+        .as_deref()
+        .and_then(|e| DateTime::parse_from_rfc3339(e).ok())
+    {
+        Some(e) => Some(e.with_timezone(&Utc)),
+        None => manager.load_config(name).ok().and_then(|c| cert_not_after(&c)),
+    }
+}
+
+/// Records the certificate expiry of profiles without one and marks active
+/// profiles past their expiry as expired.
+pub fn refresh_statuses(manager: &OpenVpnManager) {
+    let now = Utc::now();
+    for config in manager.list_configs().unwrap_or_default() {
+        let Ok(name) = ProfileName::parse(&config.name) else {
+            continue;
+        };
+        let mut meta = manager.load_meta(&name);
+        let Some(expires_at) = expires_at(manager, &name, &meta) else {
+            continue;
+        };
+
+        let recorded = meta.expires_at.is_some();
+        meta.expires_at.get_or_insert_with(|| expires_at.to_rfc3339());
+        let expired = expires_at <= now && meta.status == ProfileStatus::Active;
+        if expired {
+            meta.status = ProfileStatus::Expired;
+        }
+        if !recorded || expired {
+            manager.save_meta(&name, &meta).ok();
+        }
+    }
+}
+
+/// Profiles expiring within `within_days`, including ones already expired.
+/// Profiles without a recorded expiry go by their certificate.
+pub fn expiring_profiles(manager: &OpenVpnManager, within_days: i64) -> Vec<ExpiringProfile> {
+    let now = Utc::now();
+    let mut profiles = Vec::new();
+
+    for config in manager.list_configs().unwrap_or_default() {
+        let Ok(name) = ProfileName::parse(&config.name) else {
+            continue;
+        };
+        let Some(expires_at) = expires_at(manager, &name, &manager.load_meta(&name)) else {
+            continue;
+        };
+
+        let expired = expires_at <= now;
+        let days_left = (expires_at - now).num_days();
+        if expired || days_left < within_days {
+            profiles.push(ExpiringProfile {
+                name: name.to_string(),
+                expires_at: expires_at.to_rfc3339(),
+                days_left,
+                expired,
+            });
+        }
+    }
+
+    profiles.sort_by(|a, b| a.expires_at.cmp(&b.expires_at));
+    profiles
+}
+
+/// Checks hourly, marking expired profiles, and emits "profiles-expiring"
+/// once per profile and expiry date when it enters the warning window from
+/// settings.
+pub fn spawn_monitor(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut warned: HashSet<(String, String)> = HashSet::new();
+
+        loop {
+            let manager = OpenVpnManager::new();
+            refresh_statuses(&manager);
+
+            let within_days = app.state::<SettingsStore>().get().expiry_warning_days as i64;
+            let fresh: Vec<ExpiringProfile> = expiring_profiles(&manager, within_days)
+                .into_iter()
+                .filter(|p| warned.insert((p.name.clone(), p.expires_at.clone())))
+                .collect();
+
+            if !fresh.is_empty() {
+                app.emit("profiles-expiring", fresh).ok();
+            }
+
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    });
+}
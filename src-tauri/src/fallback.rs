@@ -0,0 +1,240 @@
+use crate::api::Server;
+use crate::editor::{self, ProfileEdit, Remote};
+use crate::metadata::ProfileMeta;
+use crate::profile::Profile;
+use crate::storage::{FileMode, Storage};
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::net::UdpSocket;
+use std::path::PathBuf;
+
+// Networks change their filtering; a remembered protocol is retried after this
+const MEMORY_DAYS: i64 = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Transport {
+    Udp,
+    Tcp,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Remembered {
+    transport: Transport,
+    updated_at: String,
+}
+
+fn path() -> PathBuf {
+    crate::paths::state_dir().join("protocols.json")
+}
+
+fn load() -> HashMap<String, Remembered> {
+    fs::read_to_string(path())
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// The hardware address `/proc/net/arp` lists for `ip`. Incomplete entries
+/// show as all zeros.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn arp_entry(arp: &str, ip: &str) -> Option<String> {
+    arp.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let mac = *fields.get(3)?;
+        (fields.first() == Some(&ip) && mac != "00:00:00:00:00:00").then(|| mac.to_lowercase())
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn default_gateway() -> Option<String> {
+    let routes = fs::read_to_string("/proc/net/route").ok()?;
+    let gateway = routes.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.get(1) != Some(&"00000000") {
+            return None;
+        }
+        let gateway = u32::from_str_radix(fields.get(2)?, 16).ok()?;
+        Some(std::net::Ipv4Addr::from(gateway.to_le_bytes()).to_string())
+    })?;
+    // Home routers share addresses like 192.168.1.1; their MACs tell them apart
+    let mac = fs::read_to_string("/proc/net/arp").ok().and_then(|arp| arp_entry(&arp, &gateway));
+    Some(match mac {
+        Some(mac) => format!("{} ({})", gateway, mac),
+        None => gateway,
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn default_gateway() -> Option<String> {
+    None
+}
+
+/// Identifies the network the machine is on by the local address used for
+/// outbound traffic and, where available, the default gateway and its MAC
+/// address. Connecting a UDP socket only picks a route, nothing is sent.
+pub fn current_network() -> Option<String> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("192.0.2.1:9").ok()?;
+    let local = socket.local_addr().ok()?.ip();
+    Some(match default_gateway() {
+        Some(gateway) => format!("{} via {}", local, gateway),
+        None => local.to_string(),
+    })
+}
+
+/// The transport that last worked on this network, if it is recent.
+pub fn remembered(network: &str) -> Option<Transport> {
+    let entry = load().remove(network)?;
+    let updated_at = DateTime::parse_from_rfc3339(&entry.updated_at).ok()?;
+    (Utc::now() - updated_at.with_timezone(&Utc) < Duration::days(MEMORY_DAYS)).then_some(entry.transport)
+}
+
+pub fn remember(network: &str, transport: Transport) -> Result<()> {
+    let mut entries = load();
+    entries.insert(
+        network.to_string(),
+        Remembered {
+            transport,
+            updated_at: Utc::now().to_rfc3339(),
+        },
+    );
+    Storage::write_atomic(&path(), serde_json::to_string_pretty(&entries)?.as_bytes(), FileMode::Public)
+}
+
+fn is_tcp(proto: &str) -> bool {
+    proto.starts_with("tcp")
+}
+
+/// Whether OpenVPN would start with a UDP remote, the only case a TCP retry
+/// can help with.
+pub fn starts_with_udp(profile: &Profile) -> bool {
+    let settings = editor::settings(profile);
+    let first = settings.remotes.first().and_then(|r| r.proto.as_deref());
+    !is_tcp(first.unwrap_or(&settings.proto))
+}
+
+/// A TCP remote for the profile: the server's TCP port when the profile was
+/// generated for a known server, otherwise a TCP remote already in the profile.
+pub fn tcp_remote(profile: &Profile, meta: &ProfileMeta, servers: &[Server]) -> Option<Remote> {
+    let settings = editor::settings(profile);
+
+    let server = servers.iter().find(|s| {
+        meta.server_code.as_deref() == Some(s.code.as_str())
+            || settings.remotes.iter().any(|r| r.host == s.ip)
+    });
+    if let Some(server) = server {
+        return Some(Remote {
+            host: server.ip.clone(),
+            port: Some(server.tcp_port),
+            proto: None,
+        });
+    }
+
+    settings
+        .remotes
+        .into_iter()
+        .find(|r| is_tcp(r.proto.as_deref().unwrap_or(&settings.proto)))
+        .map(|r| Remote { proto: None, ..r })
+}
+
+/// The profile's content with `remote` as its only remote, over TCP.
+pub fn tcp_config(content: &str, remote: Remote) -> Result<String> {
+    let mut profile = Profile::parse(content);
+    editor::apply(
+        &mut profile,
+        &ProfileEdit {
+            proto: Some("tcp-client".to_string()),
+            remotes: Some(vec![remote]),
+            ..Default::default()
+        },
+    )?;
+    Ok(profile.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server(code: &str, ip: &str) -> Server {
+        Server {
+            code: code.to_string(),
+            name: code.to_uppercase(),
+            ip: ip.to_string(),
+            udp_port: 1194,
+            tcp_port: 443,
+        }
+    }
+
+    #[test]
+    fn finds_the_gateway_mac() {
+        let arp = "\
+IP address       HW type     Flags       HW address            Mask     Device
+192.168.1.20     0x1         0x0         00:00:00:00:00:00     *        wlan0
+192.168.1.1      0x1         0x2         A4:2B:B0:11:22:33     *        wlan0
+";
+        assert_eq!(arp_entry(arp, "192.168.1.1").as_deref(), Some("a4:2b:b0:11:22:33"));
+        assert_eq!(arp_entry(arp, "192.168.1.20"), None);
+        assert_eq!(arp_entry(arp, "192.168.1.2"), None);
+    }
+
+    #[test]
+    fn detects_udp_first_profiles() {
+        let cases = [
+            ("client\nremote a.example\n", true),
+            ("client\nproto tcp-client\nremote a.example\n", false),
+            ("client\nproto tcp\nremote a.example 443 udp\n", true),
+            ("client\nproto udp\nremote a.example 443 tcp-client\nremote b.example\n", false),
+            ("client\nproto udp4\nremote a.example\n", true),
+        ];
+        for (content, expected) in cases {
+            assert_eq!(starts_with_udp(&Profile::parse(content)), expected, "{}", content);
+        }
+    }
+
+    #[test]
+    fn prefers_the_known_server_for_tcp() {
+        let servers = [server("de1", "203.0.113.5"), server("nl1", "203.0.113.9")];
+        let profile = Profile::parse("client\nremote 203.0.113.9 1194\nremote 198.51.100.7 8443 tcp-client\n");
+
+        // The server the profile was generated for comes first
+        let meta = ProfileMeta {
+            server_code: Some("de1".to_string()),
+            ..Default::default()
+        };
+        let remote = tcp_remote(&profile, &meta, &servers).unwrap();
+        assert_eq!((remote.host.as_str(), remote.port), ("203.0.113.5", Some(443)));
+
+        // Then a known server among the remotes
+        let remote = tcp_remote(&profile, &ProfileMeta::default(), &servers).unwrap();
+        assert_eq!((remote.host.as_str(), remote.port), ("203.0.113.9", Some(443)));
+
+        // Then a TCP remote of the profile
+        let remote = tcp_remote(&profile, &ProfileMeta::default(), &[]).unwrap();
+        assert_eq!((remote.host.as_str(), remote.port), ("198.51.100.7", Some(8443)));
+        assert!(remote.proto.is_none());
+
+        let udp_only = Profile::parse("client\nremote 198.51.100.7 1194\n");
+        assert!(tcp_remote(&udp_only, &ProfileMeta::default(), &[]).is_none());
+    }
+
+    #[test]
+    fn rewrites_the_profile_for_tcp() {
+        let content = "client\nproto udp\nremote a.example 1194\nremote b.example 1194\nverb 3\n";
+        let remote = Remote {
+            host: "203.0.113.5".to_string(),
+            port: Some(443),
+            proto: None,
+        };
+        let config = tcp_config(content, remote).unwrap();
+
+        let profile = Profile::parse(&config);
+        assert_eq!(profile.get("proto").unwrap(), ["tcp-client"]);
+        assert_eq!(profile.get_all("remote"), [vec!["203.0.113.5".to_string(), "443".to_string()]]);
+        assert!(config.contains("verb 3\n"));
+        assert!(!starts_with_udp(&profile));
+    }
+}
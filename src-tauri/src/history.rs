@@ -0,0 +1,182 @@
+use crate::metadata::content_hash;
+use crate::profile_name::ProfileName;
+use crate::storage::{FileMode, Storage};
+use anyhow::{Context, Result};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+const MAX_VERSIONS: usize = 20;
+
+// Above this many line pairs the diff falls back to replacing everything
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// Why a profile's content was replaced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeReason {
+    Generated,
+    Imported,
+    Edited,
+    Fixed,
+    Renewed,
+    Synced,
+    Restored,
+    Duplicated,
+}
+
+/// A previous content of a profile, kept when a write replaced it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileVersion {
+    pub id: u64,
+    /// When this content was replaced.
+    pub replaced_at: String,
+    /// The change that replaced it.
+    pub reason: ChangeReason,
+    pub hash: String,
+    pub size: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffKind {
+    Same,
+    Added,
+    Removed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffLine {
+    pub kind: DiffKind,
+    pub text: String,
+}
+
+fn dir(name: &ProfileName) -> PathBuf {
+    crate::paths::data_dir().join("history").join(name.as_str())
+}
+
+fn index_path(name: &ProfileName) -> PathBuf {
+    dir(name).join("versions.json")
+}
+
+fn version_path(name: &ProfileName, id: u64) -> PathBuf {
+    dir(name).join(format!("{}.ovpn", id))
+}
+
+/// Versions of a profile, newest first.
+pub fn list(name: &ProfileName) -> Vec<ProfileVersion> {
+    let mut versions: Vec<ProfileVersion> = fs::read_to_string(index_path(name))
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default();
+    versions.sort_by(|a, b| b.id.cmp(&a.id));
+    versions
+}
+
+fn save_index(name: &ProfileName, versions: &[ProfileVersion]) -> Result<()> {
+    Storage::write_atomic(&index_path(name), serde_json::to_string_pretty(versions)?.as_bytes(), FileMode::Secret)
+        .with_context(|| format!("Failed to save version history of {}", name))
+}
+
+/// Keeps `previous` as a version of the profile, dropping the oldest
+/// versions beyond the limit.
+pub fn record(name: &ProfileName, previous: &str, reason: ChangeReason) -> Result<()> {
+    Storage::create_private_dir(&dir(name))?;
+
+    let mut versions = list(name);
+    let id = versions.first().map(|v| v.id + 1).unwrap_or(1);
+    Storage::write_atomic(&version_path(name, id), previous.as_bytes(), FileMode::Secret)?;
+
+    versions.insert(
+        0,
+        ProfileVersion {
+            id,
+            replaced_at: Utc::now().to_rfc3339(),
+            reason,
+            hash: content_hash(previous),
+            size: previous.len(),
+        },
+    );
+    for old in versions.split_off(MAX_VERSIONS.min(versions.len())) {
+        fs::remove_file(version_path(name, old.id)).ok();
+    }
+
+    save_index(name, &versions)
+}
+
+pub fn load(name: &ProfileName, id: u64) -> Result<String> {
+    if !list(name).iter().any(|v| v.id == id) {
+        return Err(anyhow::anyhow!("Version {} of {} not found", id, name));
+    }
+    fs::read_to_string(version_path(name, id)).with_context(|| format!("Failed to read version {} of {}", id, name))
+}
+
+pub fn rename(from: &ProfileName, to: &ProfileName) -> Result<()> {
+    let source = dir(from);
+    if !source.exists() {
+        return Ok(());
+    }
+    let target = dir(to);
+    // On case-insensitive filesystems a case-only rename sees itself as the target
+    if target.exists() && from.key() != to.key() {
+        fs::remove_dir_all(&target)?;
+    }
+    fs::rename(&source, &target).with_context(|| format!("Failed to move version history of {}", from))
+}
+
+pub fn remove(name: &ProfileName) -> Result<()> {
+    match fs::remove_dir_all(dir(name)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Line diff from the longest common subsequence of the two texts.
+pub fn diff(old: &str, new: &str) -> Vec<DiffLine> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let line = |kind, text: &str| DiffLine {
+        kind,
+        text: text.to_string(),
+    };
+
+    if old.len().saturating_mul(new.len()) > MAX_DIFF_CELLS {
+        return old
+            .iter()
+            .map(|l| line(DiffKind::Removed, l))
+            .chain(new.iter().map(|l| line(DiffKind::Added, l)))
+            .collect();
+    }
+
+    // lcs[i][j] is the common length of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            lines.push(line(DiffKind::Same, old[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            lines.push(line(DiffKind::Removed, old[i]));
+            i += 1;
+        } else {
+            lines.push(line(DiffKind::Added, new[j]));
+            j += 1;
+        }
+    }
+    lines.extend(old[i..].iter().map(|l| line(DiffKind::Removed, l)));
+    lines.extend(new[j..].iter().map(|l| line(DiffKind::Added, l)));
+    lines
+}
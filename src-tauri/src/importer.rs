@@ -0,0 +1,537 @@
+use crate::expiry;
+use crate::history::ChangeReason;
+use crate::metadata::ProfileMeta;
+use crate::openvpn::OpenVpnManager;
+use crate::paths;
+use crate::profile::{Line, Profile};
+use crate::profile_name::ProfileName;
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose, Engine as _};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Seek};
+use std::path::{Component, Path, PathBuf};
+
+const MAX_REFERENCED_FILE: u64 = 1024 * 1024;
+const MAX_BUNDLE_ENTRY: u64 = 4 * 1024 * 1024;
+const MAX_BUNDLE_SIZE: u64 = 32 * 1024 * 1024;
+
+/// Directives whose file argument OpenVPN also accepts as an inline block.
+const FILE_DIRECTIVES: &[&str] = &[
+    "ca", "cert", "key", "extra-certs", "tls-auth", "tls-crypt", "tls-crypt-v2", "pkcs12",
+    "crl-verify", "secret",
+];
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImportReport {
+    pub source: String,
+    pub name: Option<String>,
+    pub imported: bool,
+    /// References that were inlined, as `directive file`.
+    pub inlined: Vec<String>,
+    /// References left as they were because the file could not be used.
+    pub unresolved: Vec<String>,
+    /// Left out on request, e.g. a name collision resolved by skipping.
+    pub skipped: bool,
+    pub error: Option<String>,
+}
+
+/// Where the files a profile refers to are looked up.
+enum Source<'a> {
+    Dir(PathBuf),
+    Bundle {
+        files: &'a HashMap<String, Vec<u8>>,
+        base: String,
+    },
+}
+
+impl Source<'_> {
+    fn read(&self, reference: &str) -> Result<Vec<u8>> {
+        match self {
+            Source::Dir(dir) => {
+                let path = dir_path(dir, reference)?;
+                let size = fs::metadata(&path)
+                    .with_context(|| format!("{} not found", path.display()))?
+                    .len();
+                if size > MAX_REFERENCED_FILE {
+                    return Err(anyhow::anyhow!("{} is too large", path.display()));
+                }
+                Ok(fs::read(&path)?)
+            }
+            Source::Bundle { files, base } => {
+                let path = bundle_path(base, reference)
+                    .ok_or_else(|| anyhow::anyhow!("{} points outside the bundle", reference))?;
+                files
+                    .get(&path)
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("{} is not in the bundle", path))
+            }
+        }
+    }
+}
+
+/// Joins a reference onto a directory inside a bundle, refusing to leave it.
+fn bundle_path(base: &str, reference: &str) -> Option<String> {
+    let reference = reference.replace('\\', "/");
+    if reference.starts_with('/') {
+        return None;
+    }
+
+    let mut parts: Vec<&str> = base.split('/').filter(|p| !p.is_empty()).collect();
+    for part in reference.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            part => parts.push(part),
+        }
+    }
+    Some(parts.join("/"))
+}
+
+/// Joins a reference onto the profile's directory, refusing absolute paths
+/// and references that lead out of it, symlinks included.
+fn dir_path(dir: &Path, reference: &str) -> Result<PathBuf> {
+    let is_absolute = reference.starts_with(['/', '\\'])
+        || Path::new(reference)
+            .components()
+            .any(|c| matches!(c, Component::Prefix(_) | Component::RootDir));
+    if is_absolute {
+        return Err(anyhow::anyhow!("{} is an absolute path", reference));
+    }
+
+    let base = dir
+        .canonicalize()
+        .with_context(|| format!("{} not found", dir.display()))?;
+    let path = base
+        .join(reference)
+        .canonicalize()
+        .with_context(|| format!("{} not found", reference))?;
+    if !path.starts_with(&base) {
+        return Err(anyhow::anyhow!("{} points outside {}", reference, dir.display()));
+    }
+    Ok(path)
+}
+
+struct Resolved {
+    content: String,
+    credentials: Option<String>,
+    inlined: Vec<String>,
+    unresolved: Vec<String>,
+}
+
+/// Replaces file references with inline blocks. `auth-user-pass <file>` is
+/// turned into saved credentials, since the app supplies its own file.
+fn resolve(content: &str, source: &Source) -> Resolved {
+    let mut profile = Profile::parse(content);
+    let has_key_direction = profile.has("key-direction");
+    let mut resolved = Resolved {
+        content: String::new(),
+        credentials: None,
+        inlined: Vec::new(),
+        unresolved: Vec::new(),
+    };
+
+    let mut lines = Vec::new();
+    for line in std::mem::take(&mut profile.lines) {
+        let Line::Directive { name, args, .. } = &line else {
+            lines.push(line);
+            continue;
+        };
+        let Some(file) = args.first().filter(|a| *a != "[inline]") else {
+            lines.push(line);
+            continue;
+        };
+
+        if name == "auth-user-pass" {
+            match source.read(file).map(String::from_utf8) {
+                Ok(Ok(credentials)) => {
+                    resolved.credentials = Some(credentials);
+                    resolved.inlined.push(format!("{} {}", name, file));
+                    lines.push(Line::directive("auth-user-pass", &[]));
+                }
+                Ok(Err(_)) => {
+                    resolved.unresolved.push(format!("{} {}: not a text file", name, file));
+                    lines.push(line);
+                }
+                Err(e) => {
+                    resolved.unresolved.push(format!("{} {}: {}", name, file, e));
+                    lines.push(line);
+                }
+            }
+            continue;
+        }
+
+        if !FILE_DIRECTIVES.contains(&name.as_str()) {
+            lines.push(line);
+            continue;
+        }
+        if name == "crl-verify" && args.get(1).is_some_and(|a| a == "dir") {
+            resolved.unresolved.push(format!("{} {}: CRL directories cannot be inlined", name, file));
+            lines.push(line);
+            continue;
+        }
+
+        let body = source.read(file).and_then(|bytes| {
+            if name == "pkcs12" {
+                Ok(general_purpose::STANDARD.encode(bytes))
+            } else {
+                String::from_utf8(bytes).map_err(|_| anyhow::anyhow!("not a PEM text file"))
+            }
+        });
+
+        match body {
+            Ok(body) => {
+                resolved.inlined.push(format!("{} {}", name, file));
+                lines.push(Line::block(name, &body));
+                if name == "tls-auth" && !has_key_direction {
+                    if let Some(direction) = args.get(1) {
+                        lines.push(Line::directive("key-direction", &[direction]));
+                    }
+                }
+            }
+            Err(e) => {
+                resolved.unresolved.push(format!("{} {}: {}", name, file, e));
+                lines.push(line);
+            }
+        }
+    }
+
+    profile.lines = lines;
+    resolved.content = profile.to_string();
+    resolved
+}
+
+/// Saves an imported profile with its metadata and, when present, credentials.
+pub fn save_profile(
+    manager: &OpenVpnManager,
+    name: &ProfileName,
+    content: &str,
+    credentials: Option<&str>,
+) -> Result<()> {
+    // Never overwrites, not even a profile of exactly the same name
+    if let Some(existing) = manager.existing_name(name) {
+        return Err(anyhow::anyhow!("A profile named {} already exists", existing));
+    }
+
+    manager.save_config(name, content, ChangeReason::Imported)?;
+    manager.save_meta(
+        name,
+        &ProfileMeta {
+            expires_at: expiry::cert_not_after(content).map(|e| e.to_rfc3339()),
+            ..Default::default()
+        },
+    )?;
+    if let Some(credentials) = credentials {
+        manager.save_credentials(name, credentials)?;
+    }
+    Ok(())
+}
+
+fn is_bundle(path: &Path) -> bool {
+    let name = path.to_string_lossy().to_lowercase();
+    name.ends_with(".zip") || name.ends_with(".tar.gz") || name.ends_with(".tgz")
+}
+
+fn is_profile(path: &str) -> bool {
+    let lower = path.to_lowercase();
+    lower.ends_with(".ovpn") || lower.ends_with(".conf")
+}
+
+fn read_limited(reader: impl Read, size: u64, name: &str, total: &mut u64) -> Result<Vec<u8>> {
+    *total += size;
+    if size > MAX_BUNDLE_ENTRY || *total > MAX_BUNDLE_SIZE {
+        return Err(anyhow::anyhow!("Bundle is too large ({})", name));
+    }
+
+    let mut bytes = Vec::new();
+    reader.take(MAX_BUNDLE_ENTRY + 1).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 > MAX_BUNDLE_ENTRY {
+        return Err(anyhow::anyhow!("Bundle is too large ({})", name));
+    }
+    Ok(bytes)
+}
+
+/// Reads every regular file of a zip archive into memory, keyed by its
+/// normalised path inside the archive.
+pub fn read_zip(reader: impl Read + Seek) -> Result<HashMap<String, Vec<u8>>> {
+    let mut archive = zip::ZipArchive::new(reader).context("Not a valid zip file")?;
+    let mut files = HashMap::new();
+    let mut total = 0;
+
+    for i in 0..archive.len() {
+        let entry = archive.by_index(i)?;
+        if !entry.is_file() {
+            continue;
+        }
+        // Skips entries with absolute or `..` paths
+        let Some(name) = entry.enclosed_name().map(|p| p.to_string_lossy().replace('\\', "/")) else {
+            continue;
+        };
+        let size = entry.size();
+        files.insert(name.clone(), read_limited(entry, size, &name, &mut total)?);
+    }
+
+    Ok(files)
+}
+
+/// Reads every regular file of a `.zip` or `.tar.gz` bundle into memory,
+/// keyed by its normalised path inside the bundle.
+fn read_bundle(path: &Path) -> Result<HashMap<String, Vec<u8>>> {
+    let file = fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+
+    if path.to_string_lossy().to_lowercase().ends_with(".zip") {
+        return read_zip(file);
+    }
+
+    let mut files = HashMap::new();
+    let mut total = 0;
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+    for entry in archive.entries().context("Not a valid tar.gz file")? {
+        let entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let Some(name) = bundle_path("", &entry.path()?.to_string_lossy()) else {
+            continue;
+        };
+        let size = entry.size();
+        files.insert(name.clone(), read_limited(entry, size, &name, &mut total)?);
+    }
+
+    Ok(files)
+}
+
+fn import_one(
+    manager: &OpenVpnManager,
+    source_name: &str,
+    content: &[u8],
+    name: Result<ProfileName, String>,
+    source: &Source,
+) -> ImportReport {
+    let mut report = ImportReport {
+        source: source_name.to_string(),
+        ..Default::default()
+    };
+
+    let name = match name {
+        Ok(name) => name,
+        Err(e) => {
+            report.error = Some(e);
+            return report;
+        }
+    };
+    report.name = Some(name.to_string());
+
+    let Ok(content) = std::str::from_utf8(content) else {
+        report.error = Some("Profile is not a text file".to_string());
+        return report;
+    };
+
+    let resolved = resolve(content, source);
+    report.inlined = resolved.inlined;
+    report.unresolved = resolved.unresolved;
+
+    match save_profile(manager, &name, &resolved.content, resolved.credentials.as_deref()) {
+        Ok(()) => report.imported = true,
+        Err(e) => report.error = Some(e.to_string()),
+    }
+    report
+}
+
+/// Imports a profile whose relative file references point into `dir`.
+pub fn import_from_dir(
+    manager: &OpenVpnManager,
+    source_name: &str,
+    content: &[u8],
+    name: Result<ProfileName, String>,
+    dir: &Path,
+) -> ImportReport {
+    import_one(manager, source_name, content, name, &Source::Dir(dir.to_path_buf()))
+}
+
+fn name_from_path(path: &str) -> Result<ProfileName, String> {
+    let file = path.rsplit(['/', '\\']).next().unwrap_or(path);
+    let stem = file.rsplit_once('.').map(|(stem, _)| stem).filter(|s| !s.is_empty()).unwrap_or(file);
+    ProfileName::sanitize(stem).map_err(|e| e.to_string())
+}
+
+/// Imports a profile file, inlining the files it references from its own
+/// directory, or every profile inside a `.zip`/`.tar.gz` bundle. `name`
+/// only applies when a single profile is imported.
+pub fn import_path(manager: &OpenVpnManager, path: &Path, name: Option<ProfileName>) -> Result<Vec<ImportReport>> {
+    if !is_bundle(path) {
+        let content = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let name = name.map(Ok).unwrap_or_else(|| {
+            name_from_path(&path.file_name().unwrap_or_default().to_string_lossy())
+        });
+        return Ok(vec![import_one(
+            manager,
+            &path.display().to_string(),
+            &content,
+            name,
+            &Source::Dir(dir),
+        )]);
+    }
+
+    let files = read_bundle(path)?;
+    let mut profiles: Vec<&String> = files.keys().filter(|p| is_profile(p)).collect();
+    profiles.sort();
+
+    if profiles.is_empty() {
+        return Err(anyhow::anyhow!("No .ovpn or .conf profile found in {}", path.display()));
+    }
+
+    let single = profiles.len() == 1;
+    let mut name = name;
+    Ok(profiles
+        .into_iter()
+        .map(|profile| {
+            let base = profile.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("");
+            let profile_name = match name.take().filter(|_| single) {
+                Some(name) => Ok(name),
+                None => name_from_path(profile),
+            };
+            import_one(
+                manager,
+                &format!("{}:{}", path.display(), profile),
+                &files[profile],
+                profile_name,
+                &Source::Bundle {
+                    files: &files,
+                    base: base.to_string(),
+                },
+            )
+        })
+        .collect())
+}
+
+fn find_profiles(dir: &Path, depth: usize, found: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() && depth > 0 {
+            find_profiles(&path, depth - 1, found);
+        } else if path.is_file() && is_profile(&path.to_string_lossy()) && is_client_config(&path) {
+            found.push(path);
+        }
+    }
+}
+
+/// Server configs sit next to client profiles in some folders and must not
+/// be imported as profiles.
+fn is_client_config(path: &Path) -> bool {
+    let mut content = String::new();
+    let read = fs::File::open(path).and_then(|f| f.take(MAX_REFERENCED_FILE).read_to_string(&mut content));
+    if read.is_err() {
+        // Kept, so the import reports why it could not be read
+        return true;
+    }
+    let profile = Profile::parse(&content);
+    profile.has("client") || profile.has("tls-client")
+}
+
+/// Imports the profiles of an OpenVPN GUI config folder, where each one may
+/// sit in its own subfolder next to the files it references. Without `dir`
+/// the platform's usual folders are searched.
+pub fn import_openvpn_gui(manager: &OpenVpnManager, dir: Option<&Path>) -> Result<Vec<ImportReport>> {
+    let dirs = match dir {
+        Some(dir) => vec![(dir.to_path_buf(), 3)],
+        None => paths::openvpn_gui_dirs(),
+    };
+
+    let mut found = Vec::new();
+    for (dir, depth) in dirs.iter().filter(|(d, _)| d.is_dir()) {
+        find_profiles(dir, *depth, &mut found);
+    }
+    found.sort();
+    found.dedup();
+
+    if found.is_empty() {
+        return Err(anyhow::anyhow!("No OpenVPN profiles found"));
+    }
+
+    Ok(found
+        .iter()
+        .map(|path| {
+            let source = path.display().to_string();
+            let content = match fs::read(path) {
+                Ok(content) => content,
+                Err(e) => {
+                    return ImportReport {
+                        source,
+                        error: Some(e.to_string()),
+                        ..Default::default()
+                    }
+                }
+            };
+
+            // Tunnelblick keeps every profile as Name.tblk/Contents/Resources/config.ovpn
+            let tblk = path.ancestors().find_map(|p| {
+                p.file_name()?.to_str()?.strip_suffix(".tblk").map(str::to_string)
+            });
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+            let name = match tblk {
+                Some(name) => ProfileName::sanitize(&name).map_err(|e| e.to_string()),
+                None => name_from_path(&file_name),
+            };
+
+            let dir = path.parent().unwrap_or(Path::new(""));
+            import_from_dir(manager, &source, &content, name, dir)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dir_references_stay_inside_the_directory() {
+        let root = std::env::temp_dir().join(format!("rbw-import-{:08x}", rand::random::<u32>()));
+        let dir = root.join("profiles");
+        fs::create_dir_all(dir.join("keys")).unwrap();
+        fs::write(dir.join("keys").join("ca.crt"), "ca").unwrap();
+        fs::write(root.join("secret.key"), "secret").unwrap();
+
+        assert!(dir_path(&dir, "keys/ca.crt").is_ok());
+        assert!(dir_path(&dir, "keys/../keys/ca.crt").is_ok());
+        assert!(dir_path(&dir, "../secret.key").is_err());
+        assert!(dir_path(&dir, &root.join("secret.key").display().to_string()).is_err());
+        assert!(dir_path(&dir, "\\secret.key").is_err());
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(root.join("secret.key"), dir.join("link.key")).unwrap();
+            assert!(dir_path(&dir, "link.key").is_err());
+        }
+
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn finds_client_profiles_only() {
+        let root = std::env::temp_dir().join(format!("rbw-import-{:08x}", rand::random::<u32>()));
+        fs::create_dir_all(root.join("server")).unwrap();
+        fs::create_dir_all(root.join("client")).unwrap();
+        fs::write(root.join("server").join("office.conf"), "mode server\ntls-server\n").unwrap();
+        fs::write(root.join("client").join("office.conf"), "client\nremote vpn.example.com\n").unwrap();
+        fs::write(root.join("client").join("manual.ovpn"), "tls-client\npull\n").unwrap();
+
+        let mut found = Vec::new();
+        find_profiles(&root, 3, &mut found);
+        found.sort();
+        assert_eq!(found, [root.join("client").join("manual.ovpn"), root.join("client").join("office.conf")]);
+
+        let mut found = Vec::new();
+        find_profiles(&root, 0, &mut found);
+        assert!(found.is_empty());
+
+        fs::remove_dir_all(&root).ok();
+    }
+}
@@ -0,0 +1,387 @@
+use crate::profile::Profile;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use x509_parser::extensions::GeneralName;
+use x509_parser::objects::{oid2sn, oid_registry};
+use x509_parser::pem::Pem;
+use x509_parser::public_key::PublicKey;
+
+// Certificates closer to expiry than this are reported as a warning
+const EXPIRY_WARNING_DAYS: i64 = 30;
+
+const MIN_RSA_BITS: usize = 2048;
+const MIN_EC_BITS: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Problem {
+    pub severity: Severity,
+    pub block: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CertificateInfo {
+    pub subject: String,
+    pub issuer: String,
+    pub subject_alt_names: Vec<String>,
+    pub serial: String,
+    pub not_before: String,
+    pub not_after: String,
+    pub key_type: String,
+    pub key_bits: Option<usize>,
+    pub signature_algorithm: String,
+    pub fingerprint_sha256: String,
+    pub is_ca: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyInfo {
+    pub format: String,
+    pub encrypted: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StaticKeyInfo {
+    /// `tls-auth`, `tls-crypt` or `tls-crypt-v2`.
+    pub kind: String,
+    pub valid: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ProfileInspection {
+    pub ca: Vec<CertificateInfo>,
+    pub cert: Option<CertificateInfo>,
+    pub key: Option<KeyInfo>,
+    pub tls_key: Option<StaticKeyInfo>,
+    pub problems: Vec<Problem>,
+}
+
+/// Returns the body of an inline block such as `<cert>...</cert>`.
+pub fn inline_block<'a>(config: &'a str, tag: &str) -> Option<&'a str> {
+    let start = config.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let end = start + config[start..].find(&format!("</{}>", tag))?;
+    Some(config[start..end].trim())
+}
+
+/// Parses every certificate in a PEM block, reporting the ones that fail.
+fn certificates(block: &str, name: &str, problems: &mut Vec<Problem>) -> Vec<CertificateInfo> {
+    let mut certs = Vec::new();
+
+    for pem in Pem::iter_from_buffer(block.as_bytes()) {
+        let parsed = pem
+            .map_err(|e| e.to_string())
+            .and_then(|pem| certificate_info(&pem));
+        match parsed {
+            Ok(info) => certs.push(info),
+            Err(e) => problems.push(Problem {
+                severity: Severity::Error,
+                block: name.to_string(),
+                message: format!("Unreadable certificate: {}", e),
+            }),
+        }
+    }
+
+    certs
+}
+
+/// Formats a SAN entry the way `openssl x509 -text` does.
+fn general_name(name: &GeneralName) -> String {
+    match name {
+        GeneralName::DNSName(dns) => format!("DNS:{}", dns),
+        GeneralName::RFC822Name(email) => format!("email:{}", email),
+        GeneralName::URI(uri) => format!("URI:{}", uri),
+        GeneralName::IPAddress(bytes) => match bytes.len() {
+            4 => format!("IP:{}", std::net::Ipv4Addr::from(<[u8; 4]>::try_from(*bytes).unwrap())),
+            16 => format!("IP:{}", std::net::Ipv6Addr::from(<[u8; 16]>::try_from(*bytes).unwrap())),
+            _ => name.to_string(),
+        },
+        other => other.to_string(),
+    }
+}
+
+fn certificate_info(pem: &Pem) -> Result<CertificateInfo, String> {
+    let cert = pem.parse_x509().map_err(|e| e.to_string())?;
+    let registry = oid_registry();
+
+    let subject_alt_names = cert
+        .subject_alternative_name()
+        .ok()
+        .flatten()
+        .map(|san| san.value.general_names.iter().map(general_name).collect())
+        .unwrap_or_default();
+
+    let spki = cert.public_key();
+    let (key_type, key_bits) = match spki.parsed() {
+        Ok(PublicKey::RSA(rsa)) => ("RSA".to_string(), Some(rsa.key_size())),
+        Ok(PublicKey::EC(ec)) => ("EC".to_string(), Some(ec.key_size())),
+        _ => (
+            oid2sn(&spki.algorithm.algorithm, registry)
+                .map(str::to_string)
+                .unwrap_or_else(|_| spki.algorithm.algorithm.to_id_string()),
+            None,
+        ),
+    };
+
+    let signature_algorithm = oid2sn(&cert.signature_algorithm.algorithm, registry)
+        .map(str::to_string)
+        .unwrap_or_else(|_| cert.signature_algorithm.algorithm.to_id_string());
+
+    let fingerprint_sha256 = Sha256::digest(&pem.contents)
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":");
+
+    let time = |t: i64| {
+        DateTime::<Utc>::from_timestamp(t, 0)
+            .map(|t| t.to_rfc3339())
+            .unwrap_or_default()
+    };
+
+    Ok(CertificateInfo {
+        subject: cert.subject().to_string(),
+        issuer: cert.issuer().to_string(),
+        subject_alt_names,
+        serial: cert.raw_serial_as_string(),
+        not_before: time(cert.validity().not_before.timestamp()),
+        not_after: time(cert.validity().not_after.timestamp()),
+        key_type,
+        key_bits,
+        signature_algorithm,
+        fingerprint_sha256,
+        is_ca: cert.is_ca(),
+    })
+}
+
+fn check_certificate(cert: &CertificateInfo, block: &str, problems: &mut Vec<Problem>) {
+    let mut report = |severity, message: String| {
+        problems.push(Problem {
+            severity,
+            block: block.to_string(),
+            message,
+        })
+    };
+
+    let now = Utc::now();
+    let parse = |t: &str| DateTime::parse_from_rfc3339(t).ok().map(|t| t.with_timezone(&Utc));
+
+    if let Some(not_after) = parse(&cert.not_after) {
+        if not_after <= now {
+            report(Severity::Error, format!("{} expired on {}", cert.subject, cert.not_after));
+        } else if not_after - now < Duration::days(EXPIRY_WARNING_DAYS) {
+            report(
+                Severity::Warning,
+                format!("{} expires in {} days", cert.subject, (not_after - now).num_days()),
+            );
+        }
+    }
+    if parse(&cert.not_before).is_some_and(|not_before| not_before > now) {
+        report(Severity::Error, format!("{} is not valid before {}", cert.subject, cert.not_before));
+    }
+
+    match (cert.key_type.as_str(), cert.key_bits) {
+        ("RSA", Some(bits)) if bits < MIN_RSA_BITS => report(
+            if bits < 1024 { Severity::Error } else { Severity::Warning },
+            format!("{} uses a weak {}-bit RSA key", cert.subject, bits),
+        ),
+        ("EC", Some(bits)) if bits < MIN_EC_BITS => report(
+            Severity::Warning,
+            format!("{} uses a weak {}-bit EC key", cert.subject, bits),
+        ),
+        _ => {}
+    }
+
+    let signature = cert.signature_algorithm.to_lowercase();
+    if signature.contains("md5") || signature.contains("sha1") {
+        report(
+            Severity::Warning,
+            format!("{} is signed with {}, which OpenSSL 3 rejects by default", cert.subject, cert.signature_algorithm),
+        );
+    }
+}
+
+fn key_info(block: &str) -> KeyInfo {
+    let label = block
+        .lines()
+        .find_map(|l| l.trim().strip_prefix("-----BEGIN "))
+        .and_then(|l| l.strip_suffix("-----"))
+        .unwrap_or_default();
+
+    let legacy_encrypted = block.contains("Proc-Type: 4,ENCRYPTED");
+    let format = match label {
+        "ENCRYPTED PRIVATE KEY" | "PRIVATE KEY" => "PKCS#8",
+        "RSA PRIVATE KEY" => "PKCS#1 (RSA)",
+        "EC PRIVATE KEY" => "SEC1 (EC)",
+        _ => "unknown",
+    };
+
+    KeyInfo {
+        format: format.to_string(),
+        encrypted: label == "ENCRYPTED PRIVATE KEY" || legacy_encrypted,
+    }
+}
+
+fn static_key_info(config: &str) -> Option<StaticKeyInfo> {
+    let (kind, block, header) = ["tls-crypt-v2", "tls-crypt", "tls-auth"]
+        .into_iter()
+        .find_map(|kind| {
+            let header = if kind == "tls-crypt-v2" {
+                "-----BEGIN OpenVPN tls-crypt-v2 client key-----"
+            } else {
+                "-----BEGIN OpenVPN Static key V1-----"
+            };
+            inline_block(config, kind).map(|block| (kind, block, header))
+        })?;
+
+    // A V1 static key is 2048 bits written as 16 lines of 32 hex digits
+    let valid = block.contains(header)
+        && (kind == "tls-crypt-v2"
+            || block
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty() && !l.starts_with('#') && !l.starts_with("-----"))
+                .map(|l| l.len() == 32 && l.chars().all(|c| c.is_ascii_hexdigit()))
+                .fold((0, true), |(n, ok), line_ok| (n + 1, ok && line_ok))
+                == (16, true));
+
+    Some(StaticKeyInfo {
+        kind: kind.to_string(),
+        valid,
+    })
+}
+
+pub fn inspect(config: &str) -> ProfileInspection {
+    let mut inspection = ProfileInspection::default();
+    let mut problems = Vec::new();
+
+    if let Some(block) = inline_block(config, "ca") {
+        inspection.ca = certificates(block, "ca", &mut problems);
+    }
+    if let Some(block) = inline_block(config, "cert") {
+        inspection.cert = certificates(block, "cert", &mut problems).into_iter().next();
+    }
+    inspection.key = inline_block(config, "key").map(key_info);
+    inspection.tls_key = static_key_info(config);
+
+    for ca in &inspection.ca {
+        check_certificate(ca, "ca", &mut problems);
+    }
+    if let Some(cert) = &inspection.cert {
+        check_certificate(cert, "cert", &mut problems);
+    }
+
+    let mut report = |severity, block: &str, message: &str| {
+        problems.push(Problem {
+            severity,
+            block: block.to_string(),
+            message: message.to_string(),
+        })
+    };
+
+    // Each may come inline or from a file; a PKCS#12 file holds all three
+    let profile = Profile::parse(config);
+    let has = |name: &str| profile.has(name) || inline_block(config, name).is_some();
+    let pkcs12 = has("pkcs12");
+    match (has("key"), has("cert")) {
+        (true, false) if !pkcs12 => report(Severity::Error, "key", "Private key without a client certificate"),
+        (false, true) if !pkcs12 => report(Severity::Error, "cert", "Client certificate without a private key"),
+        _ => {}
+    }
+    if let Some(key) = &inspection.key {
+        if key.encrypted {
+            report(Severity::Info, "key", "Private key is encrypted, OpenVPN will ask for its passphrase");
+        }
+        if key.format == "unknown" {
+            report(Severity::Error, "key", "Private key is not in a recognised PEM format");
+        }
+    }
+    if let Some(tls_key) = &inspection.tls_key {
+        if !tls_key.valid {
+            report(Severity::Error, &tls_key.kind, "Malformed OpenVPN static key");
+        }
+    }
+    if !has("ca") && !pkcs12 && !profile.has("capath") {
+        report(Severity::Error, "ca", "No CA certificate, the server cannot be verified");
+    }
+
+    problems.sort_by(|a, b| b.severity.cmp(&a.severity));
+    inspection.problems = problems;
+    inspection
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CA: &str = include_str!("testdata/inspect/ca.crt");
+    const CERT: &str = include_str!("testdata/inspect/client.crt");
+    const KEY: &str = include_str!("testdata/inspect/client.key");
+    const EXPIRED: &str = include_str!("testdata/inspect/expired.crt");
+
+    fn messages(config: &str) -> Vec<String> {
+        inspect(config).problems.into_iter().map(|p| p.message).collect()
+    }
+
+    #[test]
+    fn inline_and_file_credentials_are_complete() {
+        let inline = format!("client\n<ca>\n{}</ca>\n<cert>\n{}</cert>\n<key>\n{}</key>\n", CA, CERT, KEY);
+        let inspection = inspect(&inline);
+        assert!(inspection.problems.is_empty(), "{:?}", inspection.problems);
+        assert_eq!(inspection.ca.len(), 1);
+        assert_eq!(inspection.cert.unwrap().subject, "CN=client");
+        assert_eq!(inspection.key.unwrap().format, "PKCS#8");
+
+        assert!(messages("client\nca ca.crt\ncert client.crt\nkey client.key\n").is_empty());
+        assert!(messages(&format!("client\nca ca.crt\ncert client.crt\n<key>\n{}</key>\n", KEY)).is_empty());
+        assert!(messages(&format!("client\n<ca>\n{}</ca>\n<cert>\n{}</cert>\nkey client.key\n", CA, CERT)).is_empty());
+    }
+
+    #[test]
+    fn reports_missing_halves() {
+        assert_eq!(
+            messages(&format!("client\nca ca.crt\n<key>\n{}</key>\n", KEY)),
+            ["Private key without a client certificate"]
+        );
+        assert_eq!(
+            messages("client\nca ca.crt\ncert client.crt\n"),
+            ["Client certificate without a private key"]
+        );
+        assert_eq!(
+            messages("client\nauth-user-pass\n"),
+            ["No CA certificate, the server cannot be verified"]
+        );
+    }
+
+    #[test]
+    fn pkcs12_holds_ca_cert_and_key() {
+        assert!(messages("client\npkcs12 client.p12\n").is_empty());
+        assert!(messages("client\n<pkcs12>\nMIIJ\n</pkcs12>\n").is_empty());
+    }
+
+    #[test]
+    fn reports_expired_certificates() {
+        let problems = inspect(&format!("client\n<ca>\n{}</ca>\nauth-user-pass\n", EXPIRED)).problems;
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].severity, Severity::Error);
+        assert!(problems[0].message.contains("expired"), "{}", problems[0].message);
+    }
+
+    #[test]
+    fn reports_malformed_pem() {
+        let broken = CERT.replace(&CERT.lines().nth(3).unwrap()[..8], "!!!!!!!!");
+        let problems = inspect(&format!("client\n<ca>\n{}</ca>\nauth-user-pass\n", broken)).problems;
+        assert!(problems.iter().any(|p| p.block == "ca" && p.message.starts_with("Unreadable certificate")));
+
+        let key = inspect("client\nca ca.crt\ncert client.crt\n<key>\nnot a key\n</key>\n");
+        assert_eq!(key.problems[0].message, "Private key is not in a recognised PEM format");
+    }
+}
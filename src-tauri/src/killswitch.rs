@@ -0,0 +1,357 @@
+use crate::editor::{self, ProfileEdit, Remote};
+use crate::profile::Profile;
+use crate::settings::Settings;
+use anyhow::{Context, Result};
+use std::fs;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::PathBuf;
+
+const DEFAULT_PORT: u16 = 1194;
+
+/// A server the firewall rules let OpenVPN reach.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    pub addr: SocketAddr,
+    pub tcp: bool,
+}
+
+impl Endpoint {
+    /// The `proto:address:port` form the helper takes.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    fn arg(&self) -> String {
+        format!("{}:{}", if self.tcp { "tcp" } else { "udp" }, self.addr)
+    }
+}
+
+/// Present while rules are installed. Kept in the state directory because
+/// the rules outlive the session on Windows and until reboot on Linux.
+fn marker() -> PathBuf {
+    crate::paths::state_dir().join("kill-switch")
+}
+
+pub fn is_active() -> bool {
+    marker().exists()
+}
+
+/// Resolves the profile's remotes and writes the addresses into it, since
+/// name lookups are blocked once the rules are in place. Returns the
+/// rewritten profile and the servers the rules must let through.
+pub fn pin_remotes(content: &str) -> Result<(String, Vec<Endpoint>)> {
+    let mut profile = Profile::parse(content);
+    let settings = editor::settings(&profile);
+    let default_port = ["rport", "port"]
+        .iter()
+        .find_map(|name| profile.get(name)?.first()?.parse().ok())
+        .unwrap_or(DEFAULT_PORT);
+
+    let mut remotes = Vec::new();
+    let mut endpoints = Vec::new();
+    for remote in &settings.remotes {
+        let port = remote.port.unwrap_or(default_port);
+        let proto = remote.proto.as_deref().unwrap_or(&settings.proto);
+        let addrs = (remote.host.as_str(), port)
+            .to_socket_addrs()
+            .with_context(|| {
+                if is_active() {
+                    format!(
+                        "Cannot resolve {} while the kill switch blocks traffic",
+                        remote.host
+                    )
+                } else {
+                    format!("Failed to resolve {}", remote.host)
+                }
+            })?;
+
+        for addr in addrs {
+            // udp4, tcp6-client and the like only use one address family
+            if (proto.ends_with('4') && addr.is_ipv6()) || (proto.contains('6') && addr.is_ipv4()) {
+                continue;
+            }
+            let endpoint = Endpoint {
+                addr,
+                tcp: proto.starts_with("tcp"),
+            };
+            if !endpoints.contains(&endpoint) {
+                endpoints.push(endpoint);
+                remotes.push(Remote {
+                    host: addr.ip().to_string(),
+                    port: Some(port),
+                    proto: remote.proto.clone(),
+                });
+            }
+        }
+    }
+
+    if endpoints.is_empty() {
+        return Err(anyhow::anyhow!(
+            "The profile has no server the kill switch could allow"
+        ));
+    }
+
+    editor::apply(
+        &mut profile,
+        &ProfileEdit {
+            remotes: Some(remotes),
+            ..Default::default()
+        },
+    )?;
+    Ok((profile.to_string(), endpoints))
+}
+
+/// Tunnel devices the profile names beyond the usual `tun*` and `tap*`.
+pub fn devices(content: &str) -> Vec<String> {
+    Profile::parse(content)
+        .get("dev")
+        .and_then(|args| args.first().cloned())
+        .filter(|dev| !dev.starts_with("tun") && !dev.starts_with("tap"))
+        .into_iter()
+        .collect()
+}
+
+/// Blocks all traffic except through the tunnel and to `endpoints`.
+/// Replaces rules installed for an earlier connection.
+#[cfg_attr(not(target_os = "windows"), allow(unused_variables))]
+pub fn enable(endpoints: &[Endpoint], devices: &[String], settings: &Settings) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        let mut args = vec!["on".to_string()];
+        for dev in devices {
+            args.push("--dev".to_string());
+            args.push(dev.clone());
+        }
+        args.extend(endpoints.iter().map(Endpoint::arg));
+        run_helper(&args)?;
+        crate::storage::Storage::write_atomic(&marker(), b"", crate::storage::FileMode::Public)?;
+    }
+
+    #[cfg(target_os = "windows")]
+    windows::enable(endpoints, settings)?;
+
+    #[cfg(target_os = "macos")]
+    return Err(anyhow::anyhow!("The kill switch is not supported on macOS"));
+
+    #[cfg(not(target_os = "macos"))]
+    Ok(())
+}
+
+/// Lets traffic from the tunnel address out once the tunnel is up. Only
+/// Windows needs this; on Linux the rules match the tunnel device.
+#[cfg_attr(not(target_os = "windows"), allow(unused_variables))]
+pub fn allow_tunnel(local_ip: &str) -> Result<()> {
+    #[cfg(target_os = "windows")]
+    windows::allow_tunnel(local_ip)?;
+
+    Ok(())
+}
+
+/// Removes the rules, if any are installed.
+pub fn disable() -> Result<()> {
+    if !is_active() {
+        return Ok(());
+    }
+
+    #[cfg(target_os = "linux")]
+    run_helper(&["off".to_string()])?;
+
+    #[cfg(target_os = "windows")]
+    windows::disable()?;
+
+    fs::remove_file(marker()).context("Failed to clear the kill switch state")
+}
+
+#[cfg(target_os = "linux")]
+fn run_helper(args: &[String]) -> Result<()> {
+    let output = crate::privilege::Strategy::detect()?
+        .helper_command()?
+        .arg("kill-switch")
+        .args(args)
+        .output()
+        .context("Failed to run the privilege helper")?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "Failed to change the kill switch rules: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// Windows Firewall: outbound traffic is blocked by default and allowed only
+/// for OpenVPN itself, the servers and the tunnel address. The previous
+/// default policies are kept in the marker and restored on disable.
+#[cfg(target_os = "windows")]
+mod windows {
+    use super::{marker, Endpoint};
+    use crate::settings::Settings;
+    use crate::storage::{FileMode, Storage};
+    use anyhow::{Context, Result};
+    use regex::Regex;
+    use std::fs;
+    use std::process::Command;
+
+    const RULE_NAME: &str = "RBW-Tech OVPN kill switch";
+    const PROFILES: [&str; 3] = ["domainprofile", "privateprofile", "publicprofile"];
+
+    fn netsh(args: &[&str]) -> Result<String> {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NO_WINDOW: u32 = 0x08000000;
+
+        let output = Command::new("netsh")
+            .args(args)
+            .creation_flags(CREATE_NO_WINDOW)
+            .output()
+            .context("Failed to run netsh")?;
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "netsh {} failed: {}",
+                args.join(" "),
+                stdout.trim()
+            ));
+        }
+        Ok(stdout)
+    }
+
+    fn add_rule(extra: &[&str]) -> Result<()> {
+        let name = format!("name={}", RULE_NAME);
+        let mut args = vec![
+            "advfirewall",
+            "firewall",
+            "add",
+            "rule",
+            name.as_str(),
+            "dir=out",
+            "action=allow",
+        ];
+        args.extend_from_slice(extra);
+        netsh(&args).map(|_| ())
+    }
+
+    /// The `BlockInbound,AllowOutbound` values of the domain, private and
+    /// public profiles, in that order. The values are not localized.
+    fn current_policies() -> Result<Vec<String>> {
+        let output = netsh(&["advfirewall", "show", "allprofiles", "firewallpolicy"])?;
+        let policy = Regex::new(r"(\w+Inbound\w*),(\w+Outbound)").unwrap();
+        let policies: Vec<String> = policy
+            .find_iter(&output)
+            .map(|m| m.as_str().to_string())
+            .collect();
+        if policies.len() != PROFILES.len() {
+            return Err(anyhow::anyhow!(
+                "Could not read the current firewall policy"
+            ));
+        }
+        Ok(policies)
+    }
+
+    pub fn enable(endpoints: &[Endpoint], settings: &Settings) -> Result<()> {
+        let openvpn = settings
+            .openvpn_path
+            .as_deref()
+            .unwrap_or(r"C:\Program Files\OpenVPN\bin\openvpn.exe");
+
+        // Only the first enable sees the user's own policy
+        let policies = match fs::read_to_string(marker()) {
+            Ok(saved) => saved.lines().map(str::to_string).collect(),
+            Err(_) => {
+                let policies = current_policies()?;
+                Storage::write_atomic(&marker(), policies.join("\n").as_bytes(), FileMode::Public)?;
+                policies
+            }
+        };
+
+        netsh(&[
+            "advfirewall",
+            "firewall",
+            "delete",
+            "rule",
+            &format!("name={}", RULE_NAME),
+        ])
+        .ok();
+        add_rule(&[&format!("program={}", openvpn)])?;
+        add_rule(&["protocol=udp", "localport=68", "remoteport=67"])?;
+        for endpoint in endpoints {
+            add_rule(&[
+                &format!("remoteip={}", endpoint.addr.ip()),
+                &format!("protocol={}", if endpoint.tcp { "tcp" } else { "udp" }),
+                &format!("remoteport={}", endpoint.addr.port()),
+            ])?;
+        }
+
+        for (profile, policy) in PROFILES.iter().zip(&policies) {
+            let inbound = policy.split(',').next().unwrap_or("BlockInbound");
+            netsh(&[
+                "advfirewall",
+                "set",
+                profile,
+                "firewallpolicy",
+                &format!("{},blockoutbound", inbound),
+            ])?;
+        }
+        Ok(())
+    }
+
+    pub fn allow_tunnel(local_ip: &str) -> Result<()> {
+        add_rule(&[&format!("localip={}", local_ip)])
+    }
+
+    pub fn disable() -> Result<()> {
+        let saved =
+            fs::read_to_string(marker()).context("Failed to read the saved firewall policy")?;
+        for (profile, policy) in PROFILES.iter().zip(saved.lines()) {
+            netsh(&["advfirewall", "set", profile, "firewallpolicy", policy])?;
+        }
+        netsh(&[
+            "advfirewall",
+            "firewall",
+            "delete",
+            "rule",
+            &format!("name={}", RULE_NAME),
+        ])
+        .ok();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pins_remotes_to_addresses() {
+        let content =
+            "client\nproto udp\nport 1195\nremote 203.0.113.5\nremote 2001:db8::1 443 tcp-client\n";
+        let (pinned, endpoints) = pin_remotes(content).unwrap();
+
+        assert_eq!(
+            endpoints,
+            [
+                Endpoint {
+                    addr: "203.0.113.5:1195".parse().unwrap(),
+                    tcp: false
+                },
+                Endpoint {
+                    addr: "[2001:db8::1]:443".parse().unwrap(),
+                    tcp: true
+                },
+            ]
+        );
+        assert!(pinned.contains("remote 203.0.113.5 1195\n"));
+        assert!(pinned.contains("remote 2001:db8::1 443 tcp-client\n"));
+        assert_eq!(endpoints[1].arg(), "tcp:[2001:db8::1]:443");
+    }
+
+    #[test]
+    fn skips_addresses_of_the_other_family() {
+        let content = "client\nproto udp4\nremote 2001:db8::1\nremote 203.0.113.5\n";
+        let (_, endpoints) = pin_remotes(content).unwrap();
+        assert_eq!(endpoints.len(), 1);
+        assert!(pin_remotes("client\nproto udp4\nremote 2001:db8::1\n").is_err());
+    }
+
+    #[test]
+    fn lists_custom_devices_only() {
+        assert!(devices("client\ndev tun\n").is_empty());
+        assert_eq!(devices("client\ndev vpn0\ndev-type tun\n"), ["vpn0"]);
+    }
+}
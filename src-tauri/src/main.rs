@@ -1,11 +1,40 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod api;
+mod audit;
+mod backup;
+mod bundle;
 mod openvpn;
+mod paths;
+mod pending;
 mod commands;
+mod crypto;
+mod editor;
+mod expiry;
+mod fallback;
+mod history;
+mod importer;
+mod inspect;
+mod killswitch;
+mod metadata;
+mod networkmanager;
+mod policy;
+#[cfg(target_os = "linux")]
+mod privilege;
+mod probe;
+mod profile;
+mod profile_name;
+mod quality;
+mod remotes;
+mod server_cache;
+mod settings;
 mod state;
 mod storage;
+mod subnets;
+mod sync;
 
+use api::ApiClient;
+use settings::{Settings, SettingsStore};
 use state::AppState;
 use tauri::Manager;
 
@@ -55,6 +84,11 @@ fn show_admin_error() {
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("export-nm") {
+        std::process::exit(networkmanager::run_cli(&args[1..]));
+    }
+
     #[cfg(windows)]
     {
         if !is_elevated() {
@@ -69,11 +103,50 @@ fn main() {
         .plugin(tauri_plugin_opener::init())
         .manage(AppState::new())
         .setup(|app| {
+            paths::migrate_legacy().ok();
+            storage::Storage::repair_permissions(&paths::config_dir()).ok();
+            openvpn::OpenVpnManager::new().repair_permissions().ok();
+            openvpn::OpenVpnManager::new().migrate_legacy_names().ok();
+
+            // A damaged settings file should not keep the app from starting
+            let settings = Settings::load().unwrap_or_default();
+            // Nor should a broken policy, which loads as one refusing connections
+            let store = SettingsStore::new(settings, policy::Policy::load())?;
+            // Nor should a proxy the HTTP client rejects
+            let client = ApiClient::new(&store.get()).or_else(|e| {
+                eprintln!("Ignoring the configured proxy: {}", e);
+                ApiClient::new(&Settings {
+                    proxy: None,
+                    ..store.get()
+                })
+            })?;
+            app.manage(client);
+            app.manage(store);
+
+            let handle = app.handle().clone();
+            let mut changes = app.state::<SettingsStore>().subscribe();
+            tauri::async_runtime::spawn(async move {
+                while changes.changed().await.is_ok() {
+                    let settings = changes.borrow_and_update().clone();
+                    handle.state::<ApiClient>().apply_settings(&settings).ok();
+                }
+            });
+
+            // Rules left behind by a crash would keep blocking the network
+            if let Err(e) = killswitch::disable() {
+                eprintln!("Failed to remove the kill switch rules: {}", e);
+            }
+
+            sync::spawn_periodic(app.handle().clone());
+            expiry::spawn_monitor(app.handle().clone());
+            quality::spawn_monitor(app.handle().clone());
+
             if let Some(window) = app.get_webview_window("main") {
                 window.on_window_event(move |event| {
                     if let tauri::WindowEvent::CloseRequested { .. } = event {
                         let manager = crate::openvpn::OpenVpnManager::new();
                         let _ = manager.disconnect();
+                        let _ = crate::killswitch::disable();
                     }
                 });
             }
@@ -82,13 +155,41 @@ fn main() {
         .invoke_handler(tauri::generate_handler![
             commands::verify_api_key,
             commands::list_servers,
+            commands::probe_servers,
+            commands::pick_best_server,
             commands::generate_config,
+            commands::inspect_profile,
+            commands::audit_profile,
+            commands::fix_profile,
+            commands::get_profile_settings,
+            commands::edit_profile,
+            commands::rename_config,
+            commands::duplicate_config,
+            commands::list_profile_versions,
+            commands::diff_profile_versions,
+            commands::restore_profile_version,
+            commands::expiring_profiles,
+            commands::renew_config,
+            commands::sync_configs,
+            commands::resolve_sync_conflict,
             commands::save_credentials,
             commands::load_credentials,
             commands::install_openvpn,
             commands::import_config,
+            commands::import_config_from_path,
+            commands::import_networkmanager,
+            commands::import_openvpn_gui,
+            commands::export_networkmanager,
+            commands::export_bundle,
+            commands::preview_bundle,
+            commands::import_bundle,
+            commands::backup_app_data,
+            commands::restore_app_data,
             commands::list_configs,
             commands::delete_config,
+            commands::list_pending_operations,
+            commands::retry_pending_operations,
+            commands::discard_pending_operation,
             commands::connect_vpn,
             commands::disconnect_vpn,
             commands::get_vpn_status,
@@ -96,9 +197,14 @@ fn main() {
             commands::check_openvpn,
             commands::get_speed_history,
             commands::get_config_ip,
+            commands::get_profile_remotes,
+            commands::check_subnet_conflicts,
             commands::save_api_key_to_disk,      
             commands::load_api_key_from_disk,    
-            commands::delete_api_key_from_disk
+            commands::delete_api_key_from_disk,
+            commands::get_settings,
+            commands::update_settings,
+            commands::get_policy_status
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
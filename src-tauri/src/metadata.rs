@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProfileSource {
+    #[default]
+    Imported,
+    Generated,
+    Synced,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProfileStatus {
+    #[default]
+    Active,
+    Revoked,
+    Expired,
+    Conflict,
+}
+
+/// Sidecar stored next to each profile as `<name>.meta.json`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProfileMeta {
+    pub source: ProfileSource,
+    pub status: ProfileStatus,
+    /// ID of the config on the portal, for generated and synced profiles.
+    pub remote_id: Option<String>,
+    /// Portal server the profile was generated for.
+    pub server_code: Option<String>,
+    /// Portal account the profile was generated for.
+    pub username: Option<String>,
+    /// Hash of the content as last downloaded, used to detect local edits.
+    pub synced_hash: Option<String>,
+    /// Hash of the newer portal version while a sync conflict is pending.
+    pub remote_hash: Option<String>,
+    pub synced_at: Option<String>,
+    /// When the account or certificate expires, whichever is first.
+    pub expires_at: Option<String>,
+    /// IPv4 routes the server pushed on the last connection, in CIDR form.
+    pub pushed_networks: Vec<String>,
+    /// The tunnel's address range on the last connection.
+    pub tunnel_network: Option<String>,
+}
+
+pub fn content_hash(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
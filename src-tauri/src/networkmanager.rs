@@ -0,0 +1,771 @@
+use crate::importer::{self, ImportReport};
+use crate::openvpn::OpenVpnManager;
+use crate::profile_name::ProfileName;
+use crate::profile::{Line, Profile};
+use crate::storage::{FileMode, Storage};
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose, Engine as _};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const SYSTEM_CONNECTIONS: &str = "/etc/NetworkManager/system-connections";
+
+const OPENVPN_SERVICE: &str = "org.freedesktop.NetworkManager.openvpn";
+
+/// A parsed keyfile: section name to its keys.
+pub type Keyfile = HashMap<String, HashMap<String, String>>;
+
+/// Undoes GLib keyfile escaping (`\s`, `\n`, `\t`, `\r`, `\\`).
+fn unescape(value: &str) -> String {
+    let mut out = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => out.push(' '),
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+pub fn parse_keyfile(content: &str) -> Keyfile {
+    let mut keyfile = Keyfile::new();
+    let mut section = String::new();
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = name.to_string();
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            keyfile
+                .entry(section.clone())
+                .or_default()
+                .insert(key.trim().to_string(), unescape(value.trim()));
+        }
+    }
+
+    keyfile
+}
+
+/// Splits NetworkManager's `remote` value, `host[:port[:proto]]` entries
+/// separated by commas or spaces, with IPv6 hosts in brackets.
+fn remotes(value: &str, default_port: &str, default_proto: &str) -> Vec<(String, String, String)> {
+    value
+        .split([',', ' '])
+        .filter(|r| !r.is_empty())
+        .map(|remote| {
+            let (host, rest) = match remote.strip_prefix('[').and_then(|r| r.split_once(']')) {
+                Some((host, rest)) => (host.to_string(), rest.trim_start_matches(':')),
+                None => match remote.split_once(':') {
+                    Some((host, rest)) => (host.to_string(), rest),
+                    None => (remote.to_string(), ""),
+                },
+            };
+            let mut parts = rest.split(':').filter(|p| !p.is_empty());
+            let port = parts.next().unwrap_or(default_port).to_string();
+            let proto = parts.next().unwrap_or(default_proto).to_string();
+            (host, port, proto)
+        })
+        .collect()
+}
+
+/// Certificates configured through the NetworkManager UI are plain paths,
+/// but newer versions may store them as `file://` URIs.
+fn file_path(value: &str) -> &str {
+    value.strip_prefix("file://").unwrap_or(value)
+}
+
+fn is_pkcs12(path: &str) -> bool {
+    let lower = path.to_lowercase();
+    lower.ends_with(".p12") || lower.ends_with(".pfx")
+}
+
+/// Converts the `[vpn]` data of an OpenVPN connection into profile text.
+/// File references are left as paths for the importer to inline.
+pub fn to_ovpn(vpn: &HashMap<String, String>) -> Result<String> {
+    let get = |key: &str| vpn.get(key).map(String::as_str).filter(|v| !v.is_empty());
+    let enabled = |key: &str| get(key) == Some("yes");
+    let mut lines = vec!["client".to_string()];
+
+    let connection_type = get("connection-type").unwrap_or("tls");
+    let dev_type = get("dev-type").unwrap_or("tun");
+    lines.push(format!("dev {}", get("dev").unwrap_or(dev_type)));
+    if get("dev").is_some() {
+        lines.push(format!("dev-type {}", dev_type));
+    }
+
+    let default_proto = if enabled("proto-tcp") { "tcp" } else { "udp" };
+    let remote = get("remote").ok_or_else(|| anyhow::anyhow!("Connection has no remote"))?;
+    for (host, port, proto) in remotes(remote, get("port").unwrap_or("1194"), default_proto) {
+        lines.push(format!("remote {} {} {}", host, port, proto));
+    }
+    if enabled("remote-random") {
+        lines.push("remote-random".to_string());
+    }
+    lines.push("nobind".to_string());
+    lines.push("persist-key".to_string());
+
+    match connection_type {
+        "tls" | "password" | "password-tls" => {
+            if let Some(ca) = get("ca") {
+                lines.push(format!("ca \"{}\"", file_path(ca)));
+            }
+            if connection_type != "password" {
+                match get("cert").map(file_path) {
+                    Some(cert) if is_pkcs12(cert) => lines.push(format!("pkcs12 \"{}\"", cert)),
+                    Some(cert) => {
+                        lines.push(format!("cert \"{}\"", cert));
+                        if let Some(key) = get("key") {
+                            lines.push(format!("key \"{}\"", file_path(key)));
+                        }
+                    }
+                    None => return Err(anyhow::anyhow!("TLS connection has no client certificate")),
+                }
+            }
+            if connection_type != "tls" {
+                lines.push("auth-user-pass".to_string());
+            }
+        }
+        "static-key" => {
+            let key = get("static-key").ok_or_else(|| anyhow::anyhow!("Static key connection has no key"))?;
+            match get("static-key-direction") {
+                Some(direction) => lines.push(format!("secret \"{}\" {}", file_path(key), direction)),
+                None => lines.push(format!("secret \"{}\"", file_path(key))),
+            }
+            if let (Some(local), Some(remote)) = (get("local-ip"), get("remote-ip")) {
+                lines.push(format!("ifconfig {} {}", local, remote));
+            }
+        }
+        other => return Err(anyhow::anyhow!("Unsupported connection type {}", other)),
+    }
+
+    if let Some(ta) = get("ta") {
+        match get("ta-dir") {
+            Some(direction) => lines.push(format!("tls-auth \"{}\" {}", file_path(ta), direction)),
+            None => lines.push(format!("tls-auth \"{}\"", file_path(ta))),
+        }
+    }
+    for key in ["tls-crypt", "tls-crypt-v2", "extra-certs"] {
+        if let Some(path) = get(key) {
+            lines.push(format!("{} \"{}\"", key, file_path(path)));
+        }
+    }
+    if let Some(crl) = get("crl-verify-file") {
+        lines.push(format!("crl-verify \"{}\"", file_path(crl)));
+    }
+    if let Some(crl) = get("crl-verify-dir") {
+        lines.push(format!("crl-verify \"{}\" dir", file_path(crl)));
+    }
+
+    // Stored as `type:name`, e.g. `subject:CN=vpn.example.com`
+    if let Some(verify) = get("verify-x509-name") {
+        match verify.split_once(':') {
+            Some((kind, name)) => lines.push(format!("verify-x509-name \"{}\" {}", name, kind)),
+            None => lines.push(format!("verify-x509-name \"{}\"", verify)),
+        }
+    }
+
+    for key in [
+        "remote-cert-tls",
+        "cipher",
+        "data-ciphers",
+        "data-ciphers-fallback",
+        "auth",
+        "tls-cipher",
+        "tls-version-min",
+        "tls-version-max",
+        "ping",
+        "ping-exit",
+        "ping-restart",
+        "connect-timeout",
+        "mtu-disc",
+    ] {
+        if let Some(value) = get(key) {
+            lines.push(format!("{} {}", key, value));
+        }
+    }
+    match get("mssfix") {
+        Some("yes") => lines.push("mssfix".to_string()),
+        Some(value) => lines.push(format!("mssfix {}", value)),
+        None => {}
+    }
+    if let Some(value) = get("reneg-seconds") {
+        lines.push(format!("reneg-sec {}", value));
+    }
+    if let Some(value) = get("tunnel-mtu") {
+        lines.push(format!("tun-mtu {}", value));
+    }
+    if let Some(value) = get("fragment-size") {
+        lines.push(format!("fragment {}", value));
+    }
+    if enabled("float") {
+        lines.push("float".to_string());
+    }
+
+    match get("comp-lzo") {
+        Some("yes") | Some("adaptive") => lines.push("comp-lzo adaptive".to_string()),
+        Some("no-by-default") | Some("no") => lines.push("comp-lzo no".to_string()),
+        _ => {}
+    }
+    match get("compress") {
+        Some("yes") => lines.push("compress".to_string()),
+        Some(algorithm) => lines.push(format!("compress {}", algorithm)),
+        None => {}
+    }
+
+    lines.push("verb 3".to_string());
+    Ok(lines.join("\n") + "\n")
+}
+
+/// Username and password, when the password is stored in the keyfile
+/// rather than in a keyring or asked for every time.
+fn stored_credentials(keyfile: &Keyfile) -> (Option<String>, Option<String>) {
+    let vpn = keyfile.get("vpn");
+    let username = vpn.and_then(|v| v.get("username")).filter(|u| !u.is_empty()).cloned();
+    let flags = vpn
+        .and_then(|v| v.get("password-flags"))
+        .and_then(|f| f.parse::<u32>().ok())
+        .unwrap_or(0);
+    let password = keyfile
+        .get("vpn-secrets")
+        .and_then(|s| s.get("password"))
+        .filter(|_| flags == 0)
+        .cloned();
+    (username, password)
+}
+
+fn import_keyfile(manager: &OpenVpnManager, path: &Path) -> Option<ImportReport> {
+    let source = path.display().to_string();
+    let failed = |error: String| ImportReport {
+        source: source.clone(),
+        error: Some(error),
+        ..Default::default()
+    };
+
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => return Some(failed(e.to_string())),
+    };
+    let keyfile = parse_keyfile(&content);
+    let connection = keyfile.get("connection")?;
+    if connection.get("type").map(String::as_str) != Some("vpn") {
+        return None;
+    }
+
+    let id = connection.get("id").cloned().unwrap_or_default();
+    let name = ProfileName::sanitize(&id).map_err(|e| e.to_string());
+    let vpn = keyfile.get("vpn").cloned().unwrap_or_default();
+    if vpn.get("service-type").map(String::as_str) != Some(OPENVPN_SERVICE) {
+        let mut report = failed("Not an OpenVPN connection".to_string());
+        report.name = Some(id);
+        return Some(report);
+    }
+
+    let ovpn = match to_ovpn(&vpn) {
+        Ok(ovpn) => ovpn,
+        Err(e) => {
+            let mut report = failed(e.to_string());
+            report.name = Some(id);
+            return Some(report);
+        }
+    };
+
+    let dir = path.parent().unwrap_or(Path::new("/"));
+    let mut report = importer::import_from_dir(manager, &source, ovpn.as_bytes(), name, dir);
+    let Some(name) = report.name.as_deref().and_then(|n| ProfileName::parse(n).ok()) else {
+        return Some(report);
+    };
+    if !report.imported {
+        return Some(report);
+    }
+
+    let (username, password) = stored_credentials(&keyfile);
+    if let Some(username) = &username {
+        let mut meta = manager.load_meta(&name);
+        meta.username = Some(username.clone());
+        if let Err(e) = manager.save_meta(&name, &meta) {
+            report.error = Some(e.to_string());
+        }
+    }
+    if let (Some(username), Some(password)) = (username, password) {
+        if let Err(e) = manager.save_credentials(&name, &format!("{}\n{}\n", username, password)) {
+            report.error = Some(e.to_string());
+        }
+    }
+
+    Some(report)
+}
+
+/// Imports every OpenVPN connection from a NetworkManager keyfile directory,
+/// or a single `.nmconnection` file. Other connection types are skipped.
+pub fn import(manager: &OpenVpnManager, path: Option<&Path>) -> Result<Vec<ImportReport>> {
+    let path = path.map(Path::to_path_buf).unwrap_or_else(|| PathBuf::from(SYSTEM_CONNECTIONS));
+
+    let files = if path.is_dir() {
+        let mut files: Vec<PathBuf> = fs::read_dir(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.is_file())
+            .collect();
+        files.sort();
+        files
+    } else {
+        vec![path.clone()]
+    };
+
+    let reports: Vec<ImportReport> = files.iter().filter_map(|f| import_keyfile(manager, f)).collect();
+    if reports.is_empty() {
+        return Err(anyhow::anyhow!("No VPN connections found in {}", path.display()));
+    }
+    Ok(reports)
+}
+
+// Directives with no NetworkManager equivalent that are safe to drop
+const IGNORED_DIRECTIVES: &[&str] = &[
+    "client", "nobind", "persist-key", "persist-tun", "verb", "mute", "resolv-retry",
+    "auth-nocache", "mute-replay-warnings", "pull", "tls-client", "key-direction", "proto",
+    "port", "dev", "dev-type", "setenv", "ignore-unknown-option", "explicit-exit-notify",
+    "allow-compression",
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NmExport {
+    /// Path of the written `.nmconnection` file.
+    pub keyfile: String,
+    /// Certificate and key files written next to it.
+    pub files: Vec<String>,
+    /// Directives that have no NetworkManager equivalent and were dropped.
+    pub skipped: Vec<String>,
+}
+
+/// A keyfile and the files it refers to, before anything is written.
+pub struct Conversion {
+    pub keyfile: String,
+    pub files: Vec<(PathBuf, Vec<u8>)>,
+    pub skipped: Vec<String>,
+}
+
+fn escape(value: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in value.chars().enumerate() {
+        match c {
+            ' ' if i == 0 => out.push_str("\\s"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '\\' => out.push_str("\\\\"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn write_keyfile(sections: &[(&str, BTreeMap<String, String>)]) -> String {
+    let mut out = String::new();
+    for (name, keys) in sections {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&format!("[{}]\n", name));
+        for (key, value) in keys {
+            out.push_str(&format!("{}={}\n", key, escape(value)));
+        }
+    }
+    out
+}
+
+/// A stable UUID derived from the profile name, so exporting again
+/// replaces the connection instead of adding a second one.
+fn connection_uuid(name: &str) -> String {
+    let hash = Sha256::digest(format!("rbw-vpn:{}", name).as_bytes());
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&hash[..16]);
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+/// Converts a profile into a keyfile for NetworkManager's openvpn plugin.
+/// Inline blocks become files in `cert_dir`, named after the profile.
+/// The password is only written when `credentials` holds one.
+pub fn to_keyfile(name: &str, content: &str, credentials: Option<&str>, cert_dir: &Path) -> Result<Conversion> {
+    let profile = Profile::parse(content);
+    let mut vpn = BTreeMap::new();
+    let mut secrets = BTreeMap::new();
+    let mut files = Vec::new();
+    let mut skipped = Vec::new();
+    let mut set = |key: &str, value: &str| {
+        vpn.insert(key.to_string(), value.to_string());
+    };
+
+    let proto = profile.get("proto").and_then(|a| a.first()).map(String::as_str).unwrap_or("udp");
+    let port = profile.get("port").and_then(|a| a.first()).map(String::as_str).unwrap_or("1194");
+    let mut remotes = Vec::new();
+    let mut has_user_pass = false;
+    let mut has_cert = false;
+    let mut has_secret = false;
+
+    let mut file_for = |directive: &str, body: &[u8], extension: &str| {
+        let path = cert_dir.join(format!("{}-{}.{}", name, directive, extension));
+        files.push((path.clone(), body.to_vec()));
+        path.to_string_lossy().to_string()
+    };
+
+    for line in &profile.lines {
+        match line {
+            Line::Directive { name: directive, args, .. } => {
+                let arg = |i: usize| args.get(i).map(String::as_str);
+                match directive.as_str() {
+                    "remote" => {
+                        let host = arg(0).ok_or_else(|| anyhow::anyhow!("remote without a host"))?;
+                        let host = if host.contains(':') { format!("[{}]", host) } else { host.to_string() };
+                        // NetworkManager only knows the plain protocol names, e.g. "tcp" for "tcp-client"
+                        let remote_proto = arg(2).unwrap_or(proto).trim_end_matches("-client");
+                        remotes.push(format!("{}:{}:{}", host, arg(1).unwrap_or(port), remote_proto));
+                    }
+                    "auth-user-pass" => has_user_pass = true,
+                    "ca" | "cert" | "key" | "tls-auth" | "tls-crypt" | "tls-crypt-v2" | "extra-certs"
+                    | "pkcs12" | "secret" | "crl-verify" => {
+                        let path = arg(0).filter(|p| *p != "[inline]").unwrap_or_default();
+                        if !path.is_empty() && !Path::new(path).is_absolute() {
+                            return Err(anyhow::anyhow!(
+                                "{} refers to {}, which NetworkManager cannot find; inline it first",
+                                directive,
+                                path
+                            ));
+                        }
+                        let key = match directive.as_str() {
+                            "tls-auth" => "ta",
+                            "secret" => "static-key",
+                            "crl-verify" if arg(1) == Some("dir") => "crl-verify-dir",
+                            "crl-verify" => "crl-verify-file",
+                            other => other,
+                        };
+                        if !path.is_empty() {
+                            set(key, path);
+                        }
+                        match directive.as_str() {
+                            "cert" | "pkcs12" => has_cert = true,
+                            "secret" => has_secret = true,
+                            _ => {}
+                        }
+                        match (directive.as_str(), arg(1)) {
+                            ("tls-auth", Some(d)) => set("ta-dir", d),
+                            ("secret", Some(d)) => set("static-key-direction", d),
+                            _ => {}
+                        }
+                    }
+                    "verify-x509-name" => {
+                        if let Some(value) = arg(0) {
+                            set("verify-x509-name", &format!("{}:{}", arg(1).unwrap_or("subject"), value));
+                        }
+                    }
+                    "remote-cert-tls" | "cipher" | "data-ciphers" | "data-ciphers-fallback" | "auth"
+                    | "tls-cipher" | "tls-version-min" | "tls-version-max" | "ping" | "ping-exit"
+                    | "ping-restart" | "connect-timeout" | "mtu-disc" => {
+                        if let Some(value) = arg(0) {
+                            set(directive, value);
+                        }
+                    }
+                    "reneg-sec" | "tun-mtu" | "fragment" => {
+                        let key = match directive.as_str() {
+                            "reneg-sec" => "reneg-seconds",
+                            "tun-mtu" => "tunnel-mtu",
+                            _ => "fragment-size",
+                        };
+                        if let Some(value) = arg(0) {
+                            set(key, value);
+                        }
+                    }
+                    "mssfix" => set("mssfix", arg(0).unwrap_or("yes")),
+                    "float" | "remote-random" => set(directive, "yes"),
+                    "comp-lzo" => set(
+                        "comp-lzo",
+                        match arg(0) {
+                            Some("no") => "no-by-default",
+                            _ => "adaptive",
+                        },
+                    ),
+                    "compress" => set("compress", arg(0).unwrap_or("yes")),
+                    "ifconfig" => {
+                        if let (Some(local), Some(remote)) = (arg(0), arg(1)) {
+                            set("local-ip", local);
+                            set("remote-ip", remote);
+                        }
+                    }
+                    other if IGNORED_DIRECTIVES.contains(&other) => {}
+                    other => skipped.push(other.to_string()),
+                }
+            }
+            Line::Block { tag, body, .. } => {
+                let path = match tag.as_str() {
+                    "pkcs12" => {
+                        let der = general_purpose::STANDARD
+                            .decode(body.split_whitespace().collect::<String>())
+                            .context("pkcs12 block is not valid base64")?;
+                        file_for(tag, &der, "p12")
+                    }
+                    "ca" | "cert" | "extra-certs" | "crl-verify" => file_for(tag, format!("{}\n", body.trim()).as_bytes(), "crt"),
+                    "key" | "tls-auth" | "tls-crypt" | "tls-crypt-v2" | "secret" => {
+                        file_for(tag, format!("{}\n", body.trim()).as_bytes(), "key")
+                    }
+                    other => {
+                        skipped.push(format!("<{}>", other));
+                        continue;
+                    }
+                };
+                match tag.as_str() {
+                    // NetworkManager reads every part of a PKCS#12 bundle from the same file
+                    "pkcs12" => {
+                        set("ca", &path);
+                        set("cert", &path);
+                        set("key", &path);
+                        has_cert = true;
+                    }
+                    "tls-auth" => set("ta", &path),
+                    "secret" => {
+                        set("static-key", &path);
+                        has_secret = true;
+                    }
+                    "crl-verify" => set("crl-verify-file", &path),
+                    tag => {
+                        set(tag, &path);
+                        has_cert |= tag == "cert";
+                    }
+                }
+            }
+            Line::Other(_) => {}
+        }
+    }
+
+    if remotes.is_empty() {
+        return Err(anyhow::anyhow!("Profile has no remote"));
+    }
+    set("remote", &remotes.join(", "));
+
+    if let Some(direction) = profile.get("key-direction").and_then(|a| a.first()) {
+        if profile.has("tls-auth") || profile.block("tls-auth").is_some() {
+            set("ta-dir", direction);
+        } else if has_secret {
+            set("static-key-direction", direction);
+        }
+    }
+
+    let dev = profile.get("dev").and_then(|a| a.first()).map(String::as_str).unwrap_or("tun");
+    let dev_type = profile
+        .get("dev-type")
+        .and_then(|a| a.first())
+        .map(String::as_str)
+        .unwrap_or(if dev.starts_with("tap") { "tap" } else { "tun" });
+    set("dev-type", dev_type);
+    if dev != "tun" && dev != "tap" {
+        set("dev", dev);
+    }
+
+    let connection_type = match (has_secret, has_cert, has_user_pass) {
+        (true, _, _) => "static-key",
+        (false, true, true) => "password-tls",
+        (false, false, true) => "password",
+        _ => "tls",
+    };
+    set("connection-type", connection_type);
+
+    if has_user_pass {
+        let mut lines = credentials.unwrap_or_default().lines();
+        let username = lines.next().filter(|u| !u.is_empty());
+        let password = lines.next().filter(|p| !p.is_empty());
+        if let Some(username) = username {
+            set("username", username);
+        }
+        match password {
+            Some(password) => {
+                set("password-flags", "0");
+                secrets.insert("password".to_string(), password.to_string());
+            }
+            // Not saved: NetworkManager asks every time
+            None => set("password-flags", "2"),
+        }
+    }
+    set("service-type", OPENVPN_SERVICE);
+
+    let connection = BTreeMap::from([
+        ("autoconnect".to_string(), "false".to_string()),
+        ("id".to_string(), name.to_string()),
+        ("type".to_string(), "vpn".to_string()),
+        ("uuid".to_string(), connection_uuid(name)),
+    ]);
+    let auto = BTreeMap::from([("method".to_string(), "auto".to_string())]);
+
+    let mut sections = vec![("connection", connection), ("vpn", vpn)];
+    if !secrets.is_empty() {
+        sections.push(("vpn-secrets", secrets));
+    }
+    sections.push(("ipv4", auto.clone()));
+    sections.push(("ipv6", auto));
+
+    skipped.sort();
+    skipped.dedup();
+    Ok(Conversion {
+        keyfile: write_keyfile(&sections),
+        files,
+        skipped,
+    })
+}
+
+/// Writes a stored profile as `<name>.nmconnection` into `dir`, with its
+/// certificates and keys next to it. NetworkManager only loads keyfiles
+/// owned by root, so they are written owner-only for an administrator to
+/// move into place.
+pub fn export(manager: &OpenVpnManager, name: &ProfileName, dir: &Path, include_credentials: bool) -> Result<NmExport> {
+    let content = manager.load_config(name)?;
+    let credentials = if include_credentials {
+        manager.load_credentials(name).ok()
+    } else {
+        // The username is still worth carrying over without the password
+        manager
+            .load_meta(name)
+            .username
+            .or_else(|| manager.load_credentials(name).ok()?.lines().next().map(str::to_string))
+    };
+
+    let dir = if dir.is_absolute() {
+        dir.to_path_buf()
+    } else {
+        std::env::current_dir()?.join(dir)
+    };
+    let conversion = to_keyfile(name.as_str(), &content, credentials.as_deref(), &dir)?;
+
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let mut written = Vec::new();
+    for (path, bytes) in &conversion.files {
+        Storage::write_atomic(path, bytes, FileMode::Secret)?;
+        written.push(path.to_string_lossy().to_string());
+    }
+    let keyfile = dir.join(format!("{}.nmconnection", name));
+    Storage::write_atomic(&keyfile, conversion.keyfile.as_bytes(), FileMode::Secret)?;
+
+    Ok(NmExport {
+        keyfile: keyfile.to_string_lossy().to_string(),
+        files: written,
+        skipped: conversion.skipped,
+    })
+}
+
+/// `rbw-vpn-client export-nm <profile> <dir> [--with-credentials]`
+pub fn run_cli(args: &[String]) -> i32 {
+    let include_credentials = args.iter().any(|a| a == "--with-credentials");
+    let positional: Vec<&String> = args.iter().filter(|a| !a.starts_with("--")).collect();
+    let [profile, dir] = positional[..] else {
+        eprintln!("usage: rbw-vpn-client export-nm <profile> <dir> [--with-credentials]");
+        return 2;
+    };
+
+    let result = ProfileName::parse(profile)
+        .map_err(anyhow::Error::from)
+        .and_then(|name| export(&OpenVpnManager::new(), &name, Path::new(dir), include_credentials));
+    match result {
+        Ok(export) => {
+            println!("{}", export.keyfile);
+            for file in &export.files {
+                println!("{}", file);
+            }
+            for directive in &export.skipped {
+                eprintln!("skipped {}: no NetworkManager equivalent", directive);
+            }
+            0
+        }
+        Err(e) => {
+            eprintln!("export-nm: {:#}", e);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CASES: &[&str] = &["inline", "pkcs12", "tls-auth", "password", "password-tls", "multi-remote"];
+
+    /// Each case is `testdata/nm/<case>.ovpn`, with optional `<case>.creds`,
+    /// converted and compared with `<case>.nmconnection`. Set `BLESS=1` to
+    /// rewrite the expected files after an intended change.
+    #[test]
+    fn keyfiles_match_golden_files() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/testdata/nm");
+        let cert_dir = Path::new("/etc/NetworkManager/certs");
+
+        for case in CASES {
+            let content = fs::read_to_string(dir.join(format!("{}.ovpn", case))).unwrap();
+            let credentials = fs::read_to_string(dir.join(format!("{}.creds", case))).ok();
+            let conversion = to_keyfile(case, &content, credentials.as_deref(), cert_dir).unwrap();
+
+            let expected_path = dir.join(format!("{}.nmconnection", case));
+            if std::env::var_os("BLESS").is_some() {
+                fs::write(&expected_path, &conversion.keyfile).unwrap();
+                continue;
+            }
+            let expected = fs::read_to_string(&expected_path).unwrap();
+            assert_eq!(conversion.keyfile, expected, "{}", case);
+        }
+    }
+
+    #[test]
+    fn writes_inline_blocks_to_files() {
+        let content = include_str!("testdata/nm/inline.ovpn");
+        let conversion = to_keyfile("inline", content, None, Path::new("/certs")).unwrap();
+        let files: Vec<(String, String)> = conversion
+            .files
+            .iter()
+            .map(|(path, body)| (path.display().to_string(), String::from_utf8_lossy(body).to_string()))
+            .collect();
+
+        assert_eq!(
+            files.iter().map(|(path, _)| path.as_str()).collect::<Vec<_>>(),
+            [
+                "/certs/inline-ca.crt",
+                "/certs/inline-cert.crt",
+                "/certs/inline-key.key",
+                "/certs/inline-tls-crypt.key"
+            ]
+        );
+        assert!(files[0].1.starts_with("-----BEGIN CERTIFICATE-----\n"));
+        assert!(files[3].1.ends_with("-----END OpenVPN Static key V1-----\n"));
+        assert!(conversion.skipped.is_empty());
+    }
+
+    #[test]
+    fn writes_pkcs12_as_der() {
+        let content = include_str!("testdata/nm/pkcs12.ovpn");
+        let conversion = to_keyfile("pkcs12", content, None, Path::new("/certs")).unwrap();
+        assert_eq!(conversion.files.len(), 1);
+        assert_eq!(conversion.files[0].0, Path::new("/certs/pkcs12-pkcs12.p12"));
+        assert_eq!(conversion.files[0].1, b"PKCS12 bundle");
+    }
+
+    #[test]
+    fn reports_dropped_directives() {
+        let content = include_str!("testdata/nm/multi-remote.ovpn");
+        let conversion = to_keyfile("multi-remote", content, None, Path::new("/certs")).unwrap();
+        assert_eq!(conversion.skipped, ["route"]);
+        assert!(conversion.files.is_empty());
+    }
+}
@@ -1,57 +1,130 @@
 use anyhow::{Context, Result};
 use std::fs;
 use std::path::PathBuf;
-use std::process::{Child, Command};
+use std::process::Child;
+#[cfg(not(target_os = "linux"))]
+use std::process::Command;
 use std::sync::{Arc, Mutex};
 use regex::Regex;
 
+use crate::history::{self, ChangeReason};
+use crate::metadata::ProfileMeta;
+use crate::paths;
+use crate::profile_name::ProfileName;
+use crate::remotes;
+use crate::settings::Settings;
+use crate::storage::{FileMode, Storage};
+
 lazy_static::lazy_static! {
     static ref VPN_PROCESS: Arc<Mutex<Option<Child>>> = Arc::new(Mutex::new(None));
+    #[cfg(target_os = "linux")]
+    static ref VPN_MANAGEMENT: Arc<Mutex<Option<crate::privilege::Management>>> = Arc::new(Mutex::new(None));
 }
 
 pub struct OpenVpnManager {
     config_dir: PathBuf,
+    state_dir: PathBuf,
+    runtime_dir: PathBuf,
 }
 
 impl OpenVpnManager {
     pub fn new() -> Self {
-        let config_dir = Self::get_config_dir();
-        fs::create_dir_all(&config_dir).ok();
+        let config_dir = paths::profiles_dir();
+        let state_dir = paths::state_dir();
+        let runtime_dir = paths::runtime_dir();
+        for dir in [&config_dir, &state_dir, &runtime_dir] {
+            Storage::create_private_dir(dir).ok();
+        }
 
-        Self { config_dir }
+        Self {
+            config_dir,
+            state_dir,
+            runtime_dir,
+        }
     }
 
-    #[cfg(target_os = "windows")]
-    fn get_config_dir() -> PathBuf {
-        let appdata = std::env::var("APPDATA").unwrap_or_else(|_| ".".to_string());
-        PathBuf::from(appdata).join("RBW VPN").join("configs")
+    pub fn repair_permissions(&self) -> Result<()> {
+        Storage::repair_permissions(&self.config_dir)?;
+        Storage::repair_permissions(&self.runtime_dir)
     }
 
-    #[cfg(target_os = "linux")]
-    fn get_config_dir() -> PathBuf {
-        let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
-        PathBuf::from(home).join(".config").join("RBW VPN").join("configs")
+    fn profile_path(&self, name: &ProfileName, extension: &str) -> PathBuf {
+        self.config_dir.join(format!("{}.{}", name, extension))
     }
 
-    #[cfg(target_os = "macos")]
-    fn get_config_dir() -> PathBuf {
-        let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
-        PathBuf::from(home)
-            .join("Library")
-            .join("Application Support")
-            .join("RBW VPN")
-            .join("configs")
+    /// Finds a stored profile whose name differs from `name` only by case,
+    /// which would share the same file on case-insensitive filesystems.
+    pub fn existing_name(&self, name: &ProfileName) -> Option<ProfileName> {
+        fs::read_dir(&self.config_dir)
+            .ok()?
+            .flatten()
+            .filter_map(|entry| {
+                let file_name = entry.file_name().to_str()?.to_string();
+                ProfileName::parse(file_name.strip_suffix(".ovpn")?).ok()
+            })
+            .find(|existing| existing.key() == name.key())
     }
 
-    pub fn save_config(&self, name: &str, content: &str) -> Result<()> {
-        let config_file = self.config_dir.join(format!("{}.ovpn", name));
-        fs::write(&config_file, content)
+    /// Writes the profile, keeping the content it replaces in its history.
+    pub fn save_config(&self, name: &ProfileName, content: &str, reason: ChangeReason) -> Result<()> {
+        let config_file = self.profile_path(name, "ovpn");
+        if let Ok(previous) = fs::read_to_string(&config_file) {
+            if previous != content {
+                history::record(name, &previous, reason)?;
+            }
+        }
+        Storage::write_atomic(&config_file, content.as_bytes(), FileMode::Secret)
             .with_context(|| format!("Failed to save config: {}", name))?;
         Ok(())
     }
 
-    pub fn load_credentials(&self, config_name: &str) -> Result<String> {
-    let creds_file = self.config_dir.join(format!("{}.creds", config_name));
+    pub fn load_config(&self, name: &ProfileName) -> Result<String> {
+        let config_file = self.profile_path(name, "ovpn");
+        fs::read_to_string(&config_file)
+            .with_context(|| format!("Failed to read config: {}", name))
+    }
+
+    pub fn config_exists(&self, name: &ProfileName) -> bool {
+        self.profile_path(name, "ovpn").exists()
+    }
+
+    pub fn load_meta(&self, name: &ProfileName) -> ProfileMeta {
+        let meta_file = self.profile_path(name, "meta.json");
+        fs::read_to_string(&meta_file)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save_meta(&self, name: &ProfileName, meta: &ProfileMeta) -> Result<()> {
+        let meta_file = self.profile_path(name, "meta.json");
+        Storage::write_atomic(&meta_file, serde_json::to_string_pretty(meta)?.as_bytes(), FileMode::Secret)
+            .with_context(|| format!("Failed to save metadata: {}", name))?;
+        Ok(())
+    }
+
+    pub fn list_meta(&self) -> Vec<(ProfileName, ProfileMeta)> {
+        let mut metas = Vec::new();
+
+        if let Ok(entries) = fs::read_dir(&self.config_dir) {
+            for entry in entries.flatten() {
+                if let Some(name) = entry.file_name().to_str() {
+                    let config_name = name
+                        .strip_suffix(".meta.json")
+                        .and_then(|n| ProfileName::parse(n).ok());
+                    if let Some(config_name) = config_name {
+                        let meta = self.load_meta(&config_name);
+                        metas.push((config_name, meta));
+                    }
+                }
+            }
+        }
+
+        metas
+    }
+
+    pub fn load_credentials(&self, config_name: &ProfileName) -> Result<String> {
+    let creds_file = self.profile_path(config_name, "creds");
     if creds_file.exists() {
         let content = fs::read_to_string(&creds_file)
             .with_context(|| format!("Failed to load credentials: {}", config_name))?;
@@ -61,9 +134,9 @@ impl OpenVpnManager {
     }
 }
 
-    pub fn save_credentials(&self, config_name: &str, credentials: &str) -> Result<()> {
-        let creds_file = self.config_dir.join(format!("{}.creds", config_name));
-        fs::write(&creds_file, credentials)
+    pub fn save_credentials(&self, config_name: &ProfileName, credentials: &str) -> Result<()> {
+        let creds_file = self.profile_path(config_name, "creds");
+        Storage::write_atomic(&creds_file, credentials.as_bytes(), FileMode::Secret)
             .with_context(|| format!("Failed to save credentials: {}", config_name))?;
         Ok(())
     }
@@ -74,8 +147,11 @@ impl OpenVpnManager {
         if let Ok(entries) = fs::read_dir(&self.config_dir) {
             for entry in entries.flatten() {
                 if let Some(name) = entry.file_name().to_str() {
-                    if name.ends_with(".ovpn") {
-                        let config_name = name.trim_end_matches(".ovpn").to_string();
+                    let config_name = name
+                        .strip_suffix(".ovpn")
+                        .and_then(|n| ProfileName::parse(n).ok());
+                    if let Some(config_name) = config_name {
+                        let config_name = config_name.to_string();
                         let parts: Vec<&str> = config_name.split('-').collect();
 
                         configs.push(crate::commands::VpnConfig {
@@ -99,26 +175,145 @@ impl OpenVpnManager {
         Ok(configs)
     }
 
-    pub fn delete_config(&self, name: &str) -> Result<()> {
-        let config_file = self.config_dir.join(format!("{}.ovpn", name));
+    /// Renames profiles saved before names were validated, whose names
+    /// `list_configs` would otherwise skip, to their sanitized form. Returns
+    /// the old and new names.
+    pub fn migrate_legacy_names(&self) -> Result<Vec<(String, ProfileName)>> {
+        let mut renamed = Vec::new();
+
+        for entry in fs::read_dir(&self.config_dir)?.flatten() {
+            let Some(stem) = entry
+                .file_name()
+                .to_str()
+                .and_then(|n| n.strip_suffix(".ovpn"))
+                .map(str::to_string)
+            else {
+                continue;
+            };
+            if ProfileName::parse(&stem).is_ok() {
+                continue;
+            }
+            let Ok(base) = ProfileName::sanitize(&stem) else {
+                continue;
+            };
+            let Some(name) = (1..100)
+                .filter_map(|n| match n {
+                    1 => Some(base.clone()),
+                    n => ProfileName::sanitize(&format!("{} ({})", base, n)).ok(),
+                })
+                .find(|candidate| self.existing_name(candidate).is_none())
+            else {
+                continue;
+            };
+
+            for ext in ["ovpn", "creds", "meta.json"] {
+                let source = self.config_dir.join(format!("{}.{}", stem, ext));
+                if source.exists() {
+                    fs::rename(&source, self.profile_path(&name, ext))
+                        .with_context(|| format!("Failed to rename {} to {}", stem, name))?;
+                }
+            }
+            renamed.push((stem, name));
+        }
+
+        Ok(renamed)
+    }
+
+    /// Removes the profile together with its credentials and metadata.
+    pub fn delete_config(&self, name: &ProfileName) -> Result<()> {
+        let config_file = self.profile_path(name, "ovpn");
         fs::remove_file(&config_file)
             .with_context(|| format!("Failed to delete config: {}", name))?;
+
+        for ext in ["creds", "meta.json"] {
+            match fs::remove_file(self.profile_path(name, ext)) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(e).with_context(|| format!("Failed to delete {} of {}", ext, name));
+                }
+                _ => {}
+            }
+        }
+        remotes::remove(name).ok();
+        history::remove(name)
+    }
+
+    /// Renames the profile together with its credentials and metadata.
+    pub fn rename_config(&self, from: &ProfileName, to: &ProfileName) -> Result<()> {
+        if let Some(existing) = self.existing_name(to).filter(|e| e != from) {
+            return Err(anyhow::anyhow!("A profile named {} already exists", existing));
+        }
+
+        let mut moved = Vec::new();
+        for ext in ["ovpn", "creds", "meta.json"] {
+            let source = self.profile_path(from, ext);
+            if ext != "ovpn" && !source.exists() {
+                continue;
+            }
+            let target = self.profile_path(to, ext);
+            if let Err(e) = fs::rename(&source, &target) {
+                for (source, target) in moved.iter().rev() {
+                    fs::rename(target, source).ok();
+                }
+                return Err(e).with_context(|| format!("Failed to rename {} to {}", from, to));
+            }
+            moved.push((source, target));
+        }
+
+        // The profile itself has moved, so a stale history is not worth failing over
+        history::rename(from, to).ok();
+        remotes::rename(from, to).ok();
         Ok(())
     }
 
-    pub fn connect(&self, config_name: &str) -> Result<()> {
-        let config_file = self.config_dir.join(format!("{}.ovpn", config_name));
-        let creds_file = self.config_dir.join(format!("{}.creds", config_name));
+    /// Copies the profile with its credentials. The copy keeps the account
+    /// and expiry details but is not linked to the portal config, so syncing
+    /// or deleting one never touches the other.
+    pub fn duplicate_config(&self, from: &ProfileName, to: &ProfileName) -> Result<()> {
+        if let Some(existing) = self.existing_name(to) {
+            return Err(anyhow::anyhow!("A profile named {} already exists", existing));
+        }
+
+        let content = self.load_config(from)?;
+        self.save_config(to, &content, ChangeReason::Duplicated)?;
+        if let Ok(credentials) = self.load_credentials(from) {
+            self.save_credentials(to, &credentials)?;
+        }
+
+        let meta = self.load_meta(from);
+        self.save_meta(
+            to,
+            &ProfileMeta {
+                status: meta.status,
+                server_code: meta.server_code,
+                username: meta.username,
+                expires_at: meta.expires_at,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Starts OpenVPN for the profile. `derived` replaces the profile's
+    /// content for this connection only, e.g. to force another protocol.
+    pub fn connect(&self, config_name: &ProfileName, settings: &Settings, derived: Option<&str>) -> Result<()> {
+        let openvpn_override = settings.openvpn_path.as_deref();
+        let mut config_file = self.profile_path(config_name, "ovpn");
+        let creds_file = self.profile_path(config_name, "creds");
 
         if !config_file.exists() {
             return Err(anyhow::anyhow!("Config file not found: {}", config_name));
         }
 
+        if let Some(content) = derived {
+            config_file = self.runtime_dir.join("derived.ovpn");
+            Storage::write_atomic(&config_file, content.as_bytes(), FileMode::Secret)
+                .context("Failed to write connection config")?;
+        }
+
         #[cfg(target_os = "windows")]
-        let openvpn_path = r"C:\Program Files\OpenVPN\bin\openvpn.exe";
+        let openvpn_path = openvpn_override.unwrap_or(r"C:\Program Files\OpenVPN\bin\openvpn.exe");
 
-        #[cfg(not(target_os = "windows"))]
-        let openvpn_path = "openvpn";
+        #[cfg(target_os = "macos")]
+        let openvpn_path = openvpn_override.unwrap_or("openvpn");
 
         // Check if OpenVPN binary exists
         #[cfg(target_os = "windows")]
@@ -155,7 +350,7 @@ impl OpenVpnManager {
         use std::process::Stdio;
         use std::fs::OpenOptions;
         
-        let log_file = self.config_dir.join("openvpn.log");
+        let log_file = self.state_dir.join("openvpn.log");
         let log_output = OpenOptions::new()
             .create(true)
             .write(true)
@@ -163,6 +358,13 @@ impl OpenVpnManager {
             .open(&log_file)
             .context("Failed to create log file")?;
         
+        #[cfg(target_os = "linux")]
+        let strategy = crate::privilege::Strategy::detect()?;
+
+        #[cfg(target_os = "linux")]
+        let mut cmd = strategy.command(openvpn_override.map(std::path::Path::new))?;
+
+        #[cfg(not(target_os = "linux"))]
         let mut cmd = Command::new(openvpn_path);
 
         #[cfg(target_os = "windows")]
@@ -172,8 +374,12 @@ impl OpenVpnManager {
             cmd.creation_flags(CREATE_NO_WINDOW);
         }
 
+        if derived.is_some() {
+            // Relative paths in the profile still resolve next to it
+            cmd.arg("--cd").arg(paths::profiles_dir());
+        }
         cmd.arg("--config").arg(&config_file);
-        cmd.arg("--status").arg(self.config_dir.join("status.txt")).arg("1");
+        cmd.arg("--status").arg(self.state_dir.join("status.txt")).arg("1");
         cmd.stdout(Stdio::from(log_output.try_clone()?));
         
         // Add credentials if file exists
@@ -181,17 +387,61 @@ impl OpenVpnManager {
             cmd.arg("--auth-user-pass").arg(&creds_file);
         }
 
+        if settings.persist_tun {
+            cmd.arg("--persist-tun").arg("--persist-key");
+        }
+
+        #[cfg(target_os = "linux")]
+        let management = if strategy.needs_management() {
+            let mgmt = crate::privilege::Management::new()?;
+            let pw_file = self.runtime_dir.join("management.pw");
+            Storage::write_atomic(&pw_file, mgmt.password.as_bytes(), FileMode::Secret)
+                .context("Failed to write management password")?;
+
+            cmd.arg("--management")
+                .arg("127.0.0.1")
+                .arg(mgmt.port.to_string())
+                .arg(&pw_file);
+            Some(mgmt)
+        } else {
+            None
+        };
+
+        #[cfg(target_os = "linux")]
+        let child = cmd.spawn()
+            .with_context(|| format!("Failed to start OpenVPN via {}", strategy.name()))?;
+
+        #[cfg(not(target_os = "linux"))]
         let child = cmd.spawn()
             .with_context(|| format!("Failed to start OpenVPN. Make sure OpenVPN is installed."))?;
 
         let mut process = VPN_PROCESS.lock().unwrap();
         *process = Some(child);
 
+        #[cfg(target_os = "linux")]
+        {
+            *VPN_MANAGEMENT.lock().unwrap() = management;
+        }
+
         Ok(())
     }
 
-    pub fn get_config_ip(&self, config_name: &str) -> Result<String> {
-        let config_path = self.config_dir.join(format!("{}.ovpn", config_name));
+    /// Hosts from every `remote` line, in the order OpenVPN tries them.
+    pub fn remote_hosts(&self, config_name: &ProfileName) -> Result<Vec<String>> {
+        let content = self.load_config(config_name)?;
+        Ok(content
+            .lines()
+            .filter_map(|line| {
+                let mut parts = line.split_whitespace();
+                (parts.next() == Some("remote")).then(|| parts.next()).flatten()
+            })
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// The remote OpenVPN will try first, after reordering by past results.
+    pub fn get_config_ip(&self, config_name: &ProfileName) -> Result<String> {
+        let config_path = self.profile_path(config_name, "ovpn");
         
         if !config_path.exists() {
             return Ok("Unknown".to_string());
@@ -200,21 +450,40 @@ impl OpenVpnManager {
         let content = fs::read_to_string(&config_path)
             .context("Failed to read config file")?;
         
-        for line in content.lines() {
-            if line.trim().starts_with("remote ") {
-                let parts: Vec<&str> = line.split_whitespace().collect();
-                if parts.len() >= 2 {
-                    return Ok(parts[1].to_string());
-                }
-            }
-        }
-        
-        Ok("Unknown".to_string())
+        Ok(remotes::first_host(config_name, &content).unwrap_or_else(|| "Unknown".to_string()))
     }
 
     pub fn disconnect(&self) -> Result<()> {
         let mut process = VPN_PROCESS.lock().unwrap();
 
+        // openvpn started through pkexec runs as root, ask it to exit instead
+        #[cfg(target_os = "linux")]
+        let management = VPN_MANAGEMENT.lock().unwrap().take();
+
+        #[cfg(target_os = "linux")]
+        if let Some(mgmt) = management {
+            if let Some(mut child) = process.take() {
+                if let Err(e) = mgmt.send_signal("SIGTERM") {
+                    // Still running: keep both so a later disconnect can try again
+                    if matches!(child.try_wait(), Ok(None)) {
+                        *process = Some(child);
+                        *VPN_MANAGEMENT.lock().unwrap() = Some(mgmt);
+                        return Err(e);
+                    }
+                }
+
+                for _ in 0..50 {
+                    if !matches!(child.try_wait(), Ok(None)) {
+                        break;
+                    }
+                    std::thread::sleep(std::time::Duration::from_millis(100));
+                }
+            }
+
+            fs::remove_file(self.runtime_dir.join("management.pw")).ok();
+            return Ok(());
+        }
+
         if let Some(mut child) = process.take() {
             child.kill().context("Failed to kill OpenVPN process")?;
         }
@@ -222,6 +491,33 @@ impl OpenVpnManager {
         Ok(())
     }
 
+    /// The log of the current or last connection.
+    pub fn session_log(&self) -> String {
+        fs::read_to_string(self.state_dir.join("openvpn.log")).unwrap_or_default()
+    }
+
+    /// Whether the running connection has completed its TLS handshake and
+    /// brought up the tunnel.
+    pub fn handshake_completed(&self) -> bool {
+        self.session_log().contains("Initialization Sequence Completed")
+    }
+
+    /// The server's address inside the tunnel, from the pushed `route-gateway`
+    /// or, with net30 topology, the peer of `ifconfig`.
+    pub fn tunnel_gateway(&self) -> Option<String> {
+        let log = self.session_log();
+        let gateway = Regex::new(r"route-gateway (\d+\.\d+\.\d+\.\d+)").unwrap();
+        if let Some(caps) = gateway.captures_iter(&log).last() {
+            return Some(caps[1].to_string());
+        }
+        let ifconfig = Regex::new(r"ifconfig \d+\.\d+\.\d+\.\d+ (\d+\.\d+\.\d+\.\d+)").unwrap();
+        ifconfig
+            .captures_iter(&log)
+            .last()
+            .map(|caps| caps[1].to_string())
+            .filter(|peer| !peer.starts_with("255."))
+    }
+
     pub fn is_connected(&self) -> bool {
         let mut process = VPN_PROCESS.lock().unwrap();
         
@@ -247,10 +543,10 @@ impl OpenVpnManager {
         }
     }
 
-    pub fn get_logs(&self) -> Result<String> {
+    pub fn get_logs(&self, max_lines: usize) -> Result<String> {
         use std::io::{BufRead, BufReader};
         
-        let log_file = self.config_dir.join("openvpn.log");
+        let log_file = self.state_dir.join("openvpn.log");
         
         if !log_file.exists() {
             return Ok(String::new());
@@ -259,13 +555,13 @@ impl OpenVpnManager {
         let file = fs::File::open(&log_file)?;
         let reader = BufReader::new(file);
         
-        // Get last 100 lines
+        // Get last lines
         let lines: Vec<String> = reader.lines()
             .filter_map(|l| l.ok())
             .collect::<Vec<_>>()
             .into_iter()
             .rev()
-            .take(100)
+            .take(max_lines)
             .collect::<Vec<_>>()
             .into_iter()
             .rev()
@@ -275,7 +571,7 @@ impl OpenVpnManager {
     }
 
    pub fn get_stats(&self) -> Result<(u64, u64)> {
-        let status_file = self.config_dir.join("status.txt");
+        let status_file = self.state_dir.join("status.txt");
         
         if !status_file.exists() {
             return Ok((0, 0));
@@ -301,14 +597,14 @@ impl OpenVpnManager {
         Ok((bytes_sent, bytes_received))
     }
 
-    pub fn get_connection_details(&self, config_name: &str) -> Result<(String, String, String, u16, String)> {
+    pub fn get_connection_details(&self, config_name: &ProfileName) -> Result<(String, String, String, u16, String)> {
         let mut private_ipv4 = String::new();
         let mut private_ipv6 = String::new();
         let mut server_ip = String::new();
         let mut server_port = 0u16;
         let mut protocol = String::from("UDP");
 
-        let log_file = self.config_dir.join("openvpn.log");
+        let log_file = self.state_dir.join("openvpn.log");
         if log_file.exists() {
             let content = fs::read_to_string(&log_file)?;
             
@@ -327,8 +623,8 @@ impl OpenVpnManager {
                 private_ipv6 = caps[1].to_string();
             }
             
-            // Extract server IP:port
-            if let Some(caps) = server_regex.captures(&content) {
+            // Extract server IP:port, the last one is the remote in use after failovers
+            if let Some(caps) = server_regex.captures_iter(&content).last() {
                 server_ip = caps[1].to_string();
                 server_port = caps[2].parse().unwrap_or(1194);
             }
@@ -341,7 +637,7 @@ impl OpenVpnManager {
 
         // Fallback to config
         if server_ip.is_empty() {
-            let config_path = self.config_dir.join(format!("{}.ovpn", config_name));
+            let config_path = self.profile_path(config_name, "ovpn");
             if config_path.exists() {
                 let config_content = fs::read_to_string(&config_path)?;
                 for line in config_content.lines() {
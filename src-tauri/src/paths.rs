@@ -0,0 +1,311 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Puts every directory under one root, for tests and portable installs.
+pub const HOME_OVERRIDE_ENV: &str = "RBW_VPN_HOME";
+
+#[cfg(target_os = "linux")]
+const APP_DIR: &str = "rbw-vpn";
+
+#[cfg(not(target_os = "linux"))]
+const APP_DIR: &str = "RBW-Tech OVPN";
+
+const MIGRATION_MARKER: &str = ".migrated-v1";
+
+#[derive(Debug, Clone)]
+pub struct AppPaths {
+    pub config: PathBuf,
+    pub data: PathBuf,
+    pub state: PathBuf,
+    pub cache: PathBuf,
+    pub runtime: PathBuf,
+}
+
+impl AppPaths {
+    pub fn resolve() -> Self {
+        if let Some(root) = std::env::var_os(HOME_OVERRIDE_ENV).filter(|r| !r.is_empty()) {
+            let root = PathBuf::from(root);
+            return Self {
+                config: root.join("config"),
+                data: root.join("data"),
+                state: root.join("state"),
+                cache: root.join("cache"),
+                runtime: root.join("runtime"),
+            };
+        }
+
+        Self::platform()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn platform() -> Self {
+        let home = home_dir();
+        let cache = xdg_dir("XDG_CACHE_HOME", || home.join(".cache")).join(APP_DIR);
+
+        Self {
+            config: xdg_dir("XDG_CONFIG_HOME", || home.join(".config")).join(APP_DIR),
+            data: xdg_dir("XDG_DATA_HOME", || home.join(".local").join("share")).join(APP_DIR),
+            state: xdg_dir("XDG_STATE_HOME", || home.join(".local").join("state")).join(APP_DIR),
+            // The spec leaves the fallback to the app when XDG_RUNTIME_DIR is unset
+            runtime: xdg_dir("XDG_RUNTIME_DIR", || cache.join("run")).join(APP_DIR),
+            cache,
+        }
+    }
+
+    #[cfg(target_os = "windows")]
+    fn platform() -> Self {
+        let roaming = env_dir("APPDATA").unwrap_or_else(|| home_dir().join("AppData").join("Roaming"));
+        let local = env_dir("LOCALAPPDATA").unwrap_or_else(|| home_dir().join("AppData").join("Local"));
+        let local = local.join(APP_DIR);
+
+        Self {
+            config: roaming.join(APP_DIR),
+            data: local.clone(),
+            state: local.join("state"),
+            cache: local.join("cache"),
+            runtime: local.join("runtime"),
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    fn platform() -> Self {
+        let library = home_dir().join("Library");
+        let support = library.join("Application Support").join(APP_DIR);
+
+        Self {
+            config: support.clone(),
+            data: support.clone(),
+            state: support.join("state"),
+            cache: library.join("Caches").join(APP_DIR),
+            runtime: support.join("runtime"),
+        }
+    }
+}
+
+pub fn config_dir() -> PathBuf {
+    AppPaths::resolve().config
+}
+
+pub fn data_dir() -> PathBuf {
+    AppPaths::resolve().data
+}
+
+pub fn profiles_dir() -> PathBuf {
+    data_dir().join("profiles")
+}
+
+pub fn state_dir() -> PathBuf {
+    AppPaths::resolve().state
+}
+
+pub fn cache_dir() -> PathBuf {
+    AppPaths::resolve().cache
+}
+
+pub fn runtime_dir() -> PathBuf {
+    AppPaths::resolve().runtime
+}
+
+/// System-wide policy written by administrators. Not affected by
+/// `RBW_VPN_HOME`, so users cannot point the app at a policy of their own.
+pub fn policy_file() -> PathBuf {
+    #[cfg(target_os = "linux")]
+    let dir = PathBuf::from("/etc/rbw-vpn");
+
+    #[cfg(target_os = "windows")]
+    let dir = env_dir("ProgramData")
+        .unwrap_or_else(|| PathBuf::from(r"C:\ProgramData"))
+        .join(APP_DIR);
+
+    #[cfg(target_os = "macos")]
+    let dir = PathBuf::from("/Library/Application Support").join(APP_DIR);
+
+    dir.join("policy.json")
+}
+
+/// Folders the OpenVPN GUI (or the distribution's OpenVPN package) keeps
+/// client profiles in, with how many subfolders deep profiles may sit.
+pub fn openvpn_gui_dirs() -> Vec<(PathBuf, usize)> {
+    #[cfg(target_os = "windows")]
+    let dirs = vec![
+        (home_dir().join("OpenVPN").join("config"), 3),
+        (
+            env_dir("ProgramFiles")
+                .unwrap_or_else(|| PathBuf::from(r"C:\Program Files"))
+                .join("OpenVPN")
+                .join("config"),
+            3,
+        ),
+    ];
+
+    // Only the client folder; /etc/openvpn and /etc/openvpn/server hold
+    // server configs
+    #[cfg(target_os = "linux")]
+    let dirs = vec![(PathBuf::from("/etc/openvpn/client"), 0)];
+
+    #[cfg(target_os = "macos")]
+    let dirs = vec![(home_dir().join("Library/Application Support/Tunnelblick/Configurations"), 3)];
+
+    dirs
+}
+
+fn env_dir(var: &str) -> Option<PathBuf> {
+    std::env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+}
+
+#[cfg(target_os = "linux")]
+fn xdg_dir(var: &str, default: impl FnOnce() -> PathBuf) -> PathBuf {
+    // Relative XDG values are invalid per the spec and must be ignored
+    env_dir(var).unwrap_or_else(default)
+}
+
+pub fn home_dir() -> PathBuf {
+    #[cfg(windows)]
+    let var = "USERPROFILE";
+    #[cfg(not(windows))]
+    let var = "HOME";
+
+    if let Some(home) = env_dir(var) {
+        return home;
+    }
+
+    #[cfg(unix)]
+    if let Some(home) = passwd_home() {
+        return home;
+    }
+
+    std::env::temp_dir()
+}
+
+#[cfg(unix)]
+fn passwd_home() -> Option<PathBuf> {
+    use std::ffi::CStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result: *mut libc::passwd = std::ptr::null_mut();
+    let mut buf = vec![0 as libc::c_char; 4096];
+
+    let rc = unsafe {
+        libc::getpwuid_r(
+            libc::geteuid(),
+            &mut pwd,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        )
+    };
+    if rc != 0 || result.is_null() || pwd.pw_dir.is_null() {
+        return None;
+    }
+
+    let dir = unsafe { CStr::from_ptr(pwd.pw_dir) };
+    Some(PathBuf::from(std::ffi::OsStr::from_bytes(dir.to_bytes()))).filter(|p| p.is_absolute())
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct MigrationReport {
+    pub moved: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
+/// Directories used before the XDG layout: `Storage` kept the API key in
+/// "RBW-Tech OVPN", the profiles lived in "RBW VPN/configs".
+fn legacy_dirs() -> (PathBuf, PathBuf) {
+    #[cfg(target_os = "windows")]
+    let base = env_dir("APPDATA").unwrap_or_else(|| home_dir().join("AppData").join("Roaming"));
+
+    #[cfg(target_os = "linux")]
+    let base = home_dir().join(".config");
+
+    #[cfg(target_os = "macos")]
+    let base = home_dir().join("Library").join("Application Support");
+
+    (base.join("RBW-Tech OVPN"), base.join("RBW VPN").join("configs"))
+}
+
+/// Moves files from the legacy directories into the new layout once. Files
+/// that already exist at the destination are left in the legacy directory.
+pub fn migrate_legacy() -> Result<MigrationReport> {
+    let mut report = MigrationReport::default();
+
+    if std::env::var_os(HOME_OVERRIDE_ENV).is_some() {
+        return Ok(report);
+    }
+
+    let paths = AppPaths::resolve();
+    let marker = paths.config.join(MIGRATION_MARKER);
+    if marker.exists() {
+        return Ok(report);
+    }
+
+    let (legacy_storage, legacy_configs) = legacy_dirs();
+    let profiles = paths.data.join("profiles");
+
+    for (name, dest) in [
+        ("api_key.txt", &paths.config),
+        ("servers.json", &paths.cache),
+    ] {
+        move_file(&legacy_storage.join(name), &dest.join(name), &mut report)?;
+    }
+
+    if let Ok(entries) = fs::read_dir(&legacy_configs) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some(name) = path.file_name().and_then(|n| n.to_str()).map(str::to_string) else {
+                continue;
+            };
+
+            let dest = if name.ends_with(".ovpn") || name.ends_with(".creds") || name.ends_with(".meta.json") {
+                profiles.join(&name)
+            } else if name == "openvpn.log" {
+                paths.state.join(&name)
+            } else {
+                continue;
+            };
+            move_file(&path, &dest, &mut report)?;
+        }
+    }
+
+    // Only removes them when nothing was left behind
+    fs::remove_dir(&legacy_configs).ok();
+    if let Some(parent) = legacy_configs.parent() {
+        fs::remove_dir(parent).ok();
+    }
+    if legacy_storage != paths.config {
+        fs::remove_dir(&legacy_storage).ok();
+    }
+
+    crate::storage::Storage::create_private_dir(&paths.config)?;
+    fs::write(&marker, chrono::Utc::now().to_rfc3339()).context("Failed to write migration marker")?;
+
+    Ok(report)
+}
+
+fn move_file(src: &Path, dest: &Path, report: &mut MigrationReport) -> Result<()> {
+    if !src.is_file() || src == dest {
+        return Ok(());
+    }
+    if dest.exists() {
+        report.skipped.push(src.display().to_string());
+        return Ok(());
+    }
+
+    if let Some(parent) = dest.parent() {
+        crate::storage::Storage::create_private_dir(parent)?;
+    }
+
+    // rename fails across filesystems, fall back to copy + delete
+    if fs::rename(src, dest).is_err() {
+        fs::copy(src, dest)
+            .with_context(|| format!("Failed to migrate {}", src.display()))?;
+        fs::remove_file(src).ok();
+    }
+
+    report.moved.push(dest.display().to_string());
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use crate::api::{ApiClient, RevokeTarget};
+use crate::storage::{FileMode, Storage};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+lazy_static::lazy_static! {
+    static ref QUEUE_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::new(());
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PendingAction {
+    Revoke { target: RevokeTarget },
+}
+
+/// Server-side work that failed after the local change already happened,
+/// kept until a retry succeeds or the user discards it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingOperation {
+    pub id: String,
+    pub profile: String,
+    pub action: PendingAction,
+    pub created_at: String,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+}
+
+fn path() -> PathBuf {
+    crate::paths::state_dir().join("pending.json")
+}
+
+pub fn load() -> Vec<PendingOperation> {
+    fs::read_to_string(path())
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save(ops: &[PendingOperation]) -> Result<()> {
+    let path = path();
+    if let Some(parent) = path.parent() {
+        Storage::create_private_dir(parent)?;
+    }
+    Storage::write_atomic(&path, serde_json::to_string_pretty(ops)?.as_bytes(), FileMode::Secret)
+        .context("Failed to save pending operations")
+}
+
+pub async fn enqueue(profile: &str, action: PendingAction, error: String) -> Result<PendingOperation> {
+    let _guard = QUEUE_LOCK.lock().await;
+
+    let op = PendingOperation {
+        id: format!("{:016x}", rand::random::<u64>()),
+        profile: profile.to_string(),
+        action,
+        created_at: chrono::Utc::now().to_rfc3339(),
+        attempts: 1,
+        last_error: Some(error),
+    };
+
+    let mut ops = load();
+    ops.push(op.clone());
+    save(&ops)?;
+
+    Ok(op)
+}
+
+pub async fn discard(id: &str) -> Result<()> {
+    let _guard = QUEUE_LOCK.lock().await;
+
+    let mut ops = load();
+    let before = ops.len();
+    ops.retain(|op| op.id != id);
+    if ops.len() == before {
+        return Err(anyhow::anyhow!("No pending operation with id {}", id));
+    }
+    save(&ops)
+}
+
+/// Runs every queued operation once and returns the ones still failing.
+pub async fn retry_all(client: &ApiClient, api_key: &str) -> Result<Vec<PendingOperation>> {
+    let _guard = QUEUE_LOCK.lock().await;
+
+    let mut remaining = Vec::new();
+    for mut op in load() {
+        let result = match &op.action {
+            PendingAction::Revoke { target } => client.revoke(api_key, target).await,
+        };
+
+        if let Err(e) = result {
+            op.attempts += 1;
+            op.last_error = Some(e.to_string());
+            remaining.push(op);
+        }
+    }
+
+    save(&remaining)?;
+    Ok(remaining)
+}
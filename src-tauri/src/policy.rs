@@ -0,0 +1,357 @@
+use crate::api::Server;
+use crate::metadata::{ProfileMeta, ProfileSource};
+use crate::settings::Settings;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::Path;
+
+/// Restrictions an administrator sets in the system-wide policy file, e.g.
+///
+/// ```json
+/// {
+///   "settings": { "sync_enabled": false, "kill_switch": true },
+///   "allowed_servers": ["sg", "de"],
+///   "allow_imported_profiles": false
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Policy {
+    /// Setting values forced over the user's settings. These keys are locked.
+    pub settings: Map<String, Value>,
+    /// Server codes profiles may connect to. Unset allows every server.
+    pub allowed_servers: Option<Vec<String>>,
+    pub allow_imported_profiles: bool,
+    managed: bool,
+    /// Entries that were skipped because this version does not support them.
+    warnings: Vec<String>,
+    /// Why the file could not be used. Connections and imports are refused
+    /// until it is fixed.
+    error: Option<String>,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            settings: Map::new(),
+            allowed_servers: None,
+            allow_imported_profiles: true,
+            managed: false,
+            warnings: Vec::new(),
+            error: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PolicyStatus {
+    pub managed: bool,
+    pub locked_settings: Vec<String>,
+    pub allowed_servers: Option<Vec<String>>,
+    pub allow_imported_profiles: bool,
+    pub warnings: Vec<String>,
+    pub error: Option<String>,
+}
+
+impl Policy {
+    /// Reads the policy file. Keys this version does not know are skipped
+    /// with a warning. A file that cannot be trusted or parsed still loads,
+    /// as a policy that refuses every connection and import, so a broken
+    /// policy never silently lifts its restrictions nor keeps the app from
+    /// starting.
+    pub fn load() -> Self {
+        let path = crate::paths::policy_file();
+        if !path.exists() {
+            return Self::default();
+        }
+
+        match Self::read(&path) {
+            Ok(policy) => {
+                for warning in &policy.warnings {
+                    eprintln!("Policy file {}: {}", path.display(), warning);
+                }
+                policy
+            }
+            Err(e) => {
+                eprintln!("{:#}", e);
+                Self {
+                    managed: true,
+                    error: Some(format!("{:#}", e)),
+                    ..Self::default()
+                }
+            }
+        }
+    }
+
+    fn read(path: &Path) -> Result<Self> {
+        check_ownership(path)?;
+
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read policy file {}", path.display()))?;
+        let value = serde_json::from_str(&content)
+            .with_context(|| format!("Invalid policy file {}", path.display()))?;
+        Self::from_value(value).with_context(|| format!("Invalid policy file {}", path.display()))
+    }
+
+    fn from_value(value: Value) -> Result<Self> {
+        let Value::Object(fields) = value else {
+            return Err(anyhow::anyhow!("The policy must be a JSON object"));
+        };
+
+        let mut policy = Self {
+            managed: true,
+            ..Self::default()
+        };
+        for (key, value) in fields {
+            match key.as_str() {
+                "settings" => {
+                    let Value::Object(settings) = value else {
+                        return Err(anyhow::anyhow!("settings must be an object"));
+                    };
+                    for (key, value) in settings {
+                        policy.add_setting(key, value)?;
+                    }
+                }
+                "allowed_servers" => {
+                    policy.allowed_servers = serde_json::from_value(value)
+                        .context("allowed_servers must be a list of server codes")?;
+                }
+                "allow_imported_profiles" => {
+                    policy.allow_imported_profiles = serde_json::from_value(value)
+                        .context("allow_imported_profiles must be true or false")?;
+                }
+                _ => policy
+                    .warnings
+                    .push(format!("Ignoring unknown key {}", key)),
+            }
+        }
+        Ok(policy)
+    }
+
+    /// Settings this version does not have are skipped; a value that is
+    /// invalid for a known setting makes the whole policy unusable.
+    fn add_setting(&mut self, key: String, value: Value) -> Result<()> {
+        let known = key != "schema_version"
+            && serde_json::to_value(Settings::default())?
+                .as_object()
+                .is_some_and(|fields| fields.contains_key(&key));
+        if !known {
+            self.warnings
+                .push(format!("Ignoring unsupported setting {}", key));
+            return Ok(());
+        }
+
+        let mut patch = Map::new();
+        patch.insert(key.clone(), value.clone());
+        Settings::default()
+            .patched(Value::Object(patch))
+            .with_context(|| format!("Invalid value for setting {}", key))?;
+        self.settings.insert(key, value);
+        Ok(())
+    }
+
+    /// Refuses everything while the policy file is unusable.
+    fn check_usable(&self) -> Result<()> {
+        match &self.error {
+            Some(error) => Err(anyhow::anyhow!(
+                "The policy set by your administrator could not be read: {}",
+                error
+            )),
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_locked(&self, key: &str) -> bool {
+        self.settings.contains_key(key)
+    }
+
+    /// Returns the user's settings with the policy values merged over them.
+    pub fn apply(&self, settings: &Settings) -> Result<Settings> {
+        if self.settings.is_empty() {
+            return Ok(settings.clone());
+        }
+
+        settings
+            .patched(Value::Object(self.settings.clone()))
+            .context("Invalid settings in policy file")
+    }
+
+    pub fn allows_server(&self, code: &str) -> bool {
+        self.error.is_none()
+            && self
+                .allowed_servers
+                .as_ref()
+                .is_none_or(|allowed| allowed.iter().any(|a| a.eq_ignore_ascii_case(code)))
+    }
+
+    pub fn check_server(&self, code: &str) -> Result<()> {
+        self.check_usable()?;
+        if !self.allows_server(code) {
+            return Err(anyhow::anyhow!(
+                "Server {} is not allowed by your administrator",
+                code
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn check_import(&self) -> Result<()> {
+        self.check_usable()?;
+        if !self.allow_imported_profiles {
+            return Err(anyhow::anyhow!(
+                "Importing profiles is disabled by your administrator"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks a profile before connecting. Every `remote` host must be the
+    /// address of an allowed server in the server list.
+    pub fn check_profile(
+        &self,
+        name: &str,
+        meta: &ProfileMeta,
+        remotes: &[String],
+        servers: &[Server],
+    ) -> Result<()> {
+        self.check_usable()?;
+        if meta.source == ProfileSource::Imported {
+            self.check_import()?;
+        }
+
+        if self.allowed_servers.is_none() {
+            return Ok(());
+        }
+
+        // The code is stored beside the profile and can be edited, so it only
+        // narrows what the remotes already have to satisfy
+        if let Some(code) = &meta.server_code {
+            self.check_server(code)?;
+        }
+
+        let allowed_hosts: Vec<&str> = servers
+            .iter()
+            .filter(|s| self.allows_server(&s.code))
+            .map(|s| s.ip.as_str())
+            .collect();
+
+        if remotes.is_empty() || !remotes.iter().all(|r| allowed_hosts.contains(&r.as_str())) {
+            return Err(anyhow::anyhow!(
+                "Profile {} does not use a server allowed by your administrator",
+                name
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn status(&self) -> PolicyStatus {
+        let mut locked_settings: Vec<String> = self.settings.keys().cloned().collect();
+        locked_settings.sort();
+
+        PolicyStatus {
+            managed: self.managed,
+            locked_settings,
+            allowed_servers: self.allowed_servers.clone(),
+            allow_imported_profiles: self.allow_imported_profiles,
+            warnings: self.warnings.clone(),
+            error: self.error.clone(),
+        }
+    }
+}
+
+/// A policy that ordinary users can rewrite enforces nothing.
+#[cfg(unix)]
+fn check_ownership(path: &Path) -> Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let meta = fs::metadata(path)?;
+    if meta.uid() != 0 || meta.mode() & 0o022 != 0 {
+        return Err(anyhow::anyhow!(
+            "Policy file {} must be owned by root and not writable by other users",
+            path.display()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_ownership(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn skips_unknown_keys_with_a_warning() {
+        let policy = Policy::from_value(json!({
+            "settings": { "kill_switch": true, "tray_icon": false },
+            "allowed_servers": ["sg"],
+            "audit_log": true
+        }))
+        .unwrap();
+
+        assert!(policy.is_locked("kill_switch"));
+        assert!(!policy.is_locked("tray_icon"));
+        assert!(policy.apply(&Settings::default()).unwrap().kill_switch);
+        assert_eq!(policy.status().warnings.len(), 2);
+        assert!(policy.check_server("sg").is_ok());
+    }
+
+    #[test]
+    fn invalid_values_are_errors() {
+        assert!(Policy::from_value(json!({ "settings": { "kill_switch": "yes" } })).is_err());
+        assert!(Policy::from_value(json!({ "allowed_servers": "sg" })).is_err());
+        assert!(Policy::from_value(json!(["sg"])).is_err());
+    }
+
+    #[test]
+    fn unusable_policy_refuses_everything() {
+        let policy = Policy {
+            managed: true,
+            error: Some("Policy file is not owned by root".to_string()),
+            ..Policy::default()
+        };
+
+        assert!(policy.check_server("sg").is_err());
+        assert!(policy.check_import().is_err());
+        assert!(policy
+            .check_profile(
+                "work",
+                &ProfileMeta::default(),
+                &["203.0.113.5".to_string()],
+                &[]
+            )
+            .is_err());
+        assert!(policy.status().error.is_some());
+    }
+
+    #[test]
+    fn server_code_does_not_vouch_for_remotes() {
+        let policy = Policy {
+            allowed_servers: Some(vec!["sg".to_string()]),
+            ..Policy::default()
+        };
+        let servers: Vec<Server> = serde_json::from_value(json!([
+            { "code": "sg", "name": "Singapore", "ip": "203.0.113.5", "udp_port": 1194, "tcp_port": 443 },
+            { "code": "us", "name": "United States", "ip": "198.51.100.7", "udp_port": 1194, "tcp_port": 443 }
+        ]))
+        .unwrap();
+        let meta = |code: &str| ProfileMeta {
+            server_code: Some(code.to_string()),
+            ..ProfileMeta::default()
+        };
+        let check = |meta: &ProfileMeta, remote: &str| {
+            policy.check_profile("work", meta, &[remote.to_string()], &servers)
+        };
+
+        assert!(check(&meta("sg"), "203.0.113.5").is_ok());
+        assert!(check(&meta("sg"), "198.51.100.7").is_err());
+        assert!(check(&meta("us"), "203.0.113.5").is_err());
+        assert!(check(&ProfileMeta::default(), "203.0.113.5").is_ok());
+    }
+}
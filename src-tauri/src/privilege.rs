@@ -0,0 +1,247 @@
+use anyhow::{Context, Result};
+use std::ffi::CString;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+pub const HELPER_NAME: &str = "rbw-vpn-helper";
+pub const HELPER_INSTALL_DIR: &str = "/usr/lib/rbw-vpn-client";
+
+const CAP_NET_ADMIN: u32 = 12;
+const VFS_CAP_REVISION_MASK: u32 = 0xFF00_0000;
+const VFS_CAP_REVISION_2: u32 = 0x0200_0000;
+const VFS_CAP_REVISION_3: u32 = 0x0300_0000;
+
+const OPENVPN_CANDIDATES: &[&str] = &["/usr/sbin/openvpn", "/usr/bin/openvpn", "/sbin/openvpn"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Strategy {
+    /// The app itself runs as root, openvpn is started directly.
+    Root,
+    /// openvpn is started through the helper carrying the `cap_net_admin` file capability.
+    CapHelper(PathBuf),
+    /// openvpn is started as root through pkexec and the shipped polkit policy.
+    Pkexec,
+}
+
+impl Strategy {
+    pub fn detect() -> Result<Self> {
+        let cap_helper = find_helper().filter(|helper| has_net_admin(helper));
+        Self::choose(unsafe { libc::geteuid() } == 0, cap_helper, find_in_path("pkexec").is_some()).ok_or_else(|| {
+            anyhow::anyhow!(
+                "OpenVPN needs network administration privileges on Linux, but neither pkexec (polkit) \
+                 nor the {} helper with the cap_net_admin capability is available. \
+                 Install polkit, or run `sudo setcap cap_net_admin+ep {}/{}`.",
+                HELPER_NAME,
+                HELPER_INSTALL_DIR,
+                HELPER_NAME
+            )
+        })
+    }
+
+    /// Prefers running as root, then the capability helper, then pkexec.
+    fn choose(root: bool, cap_helper: Option<PathBuf>, pkexec: bool) -> Option<Self> {
+        if root {
+            return Some(Strategy::Root);
+        }
+        if let Some(helper) = cap_helper {
+            return Some(Strategy::CapHelper(helper));
+        }
+        pkexec.then_some(Strategy::Pkexec)
+    }
+
+    /// The command starting OpenVPN, `openvpn` being the binary from
+    /// settings. The helper only runs a binary owned by root and checks so
+    /// itself.
+    pub fn command(&self, openvpn: Option<&Path>) -> Result<Command> {
+        let openvpn = match openvpn {
+            Some(path) if path.is_absolute() && path.is_file() => path.to_path_buf(),
+            Some(path) => return Err(anyhow::anyhow!("OpenVPN binary not found at {}", path.display())),
+            None => find_openvpn().ok_or_else(|| {
+                anyhow::anyhow!("OpenVPN not installed. Install the `openvpn` package from your distribution.")
+            })?,
+        };
+
+        let cmd = match self {
+            Strategy::Root => Command::new(openvpn),
+            Strategy::CapHelper(helper) => {
+                let mut cmd = Command::new(helper);
+                cmd.arg("--openvpn").arg(openvpn);
+                cmd
+            }
+            Strategy::Pkexec => {
+                let mut cmd = Command::new("pkexec");
+                cmd.arg(openvpn);
+                cmd
+            }
+        };
+
+        Ok(cmd)
+    }
+
+    /// The helper, run with enough privilege to change firewall rules.
+    pub fn helper_command(&self) -> Result<Command> {
+        let helper = match self {
+            Strategy::CapHelper(helper) => helper.clone(),
+            _ => find_helper().ok_or_else(|| anyhow::anyhow!("The {} helper is not installed", HELPER_NAME))?,
+        };
+
+        let cmd = match self {
+            Strategy::Pkexec => {
+                let mut cmd = Command::new("pkexec");
+                cmd.arg(helper);
+                cmd
+            }
+            _ => Command::new(helper),
+        };
+
+        Ok(cmd)
+    }
+
+    /// Processes started through pkexec run as root and cannot be killed by
+    /// the app, so they are stopped through the management interface instead.
+    pub fn needs_management(&self) -> bool {
+        matches!(self, Strategy::Pkexec)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Strategy::Root => "root",
+            Strategy::CapHelper(_) => "cap_net_admin helper",
+            Strategy::Pkexec => "pkexec",
+        }
+    }
+}
+
+pub struct Management {
+    pub port: u16,
+    pub password: String,
+}
+
+impl Management {
+    pub fn new() -> Result<Self> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")
+            .context("Failed to reserve management port")?;
+        let port = listener.local_addr()?.port();
+        drop(listener);
+
+        let mut bytes = [0u8; 16];
+        std::fs::File::open("/dev/urandom")
+            .and_then(|mut f| f.read_exact(&mut bytes))
+            .context("Failed to generate management password")?;
+        let password = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+
+        Ok(Self { port, password })
+    }
+
+    pub fn send_signal(&self, signal: &str) -> Result<()> {
+        let mut stream = TcpStream::connect_timeout(
+            &([127, 0, 0, 1], self.port).into(),
+            Duration::from_secs(3),
+        )
+        .context("Failed to reach OpenVPN management interface")?;
+        stream.set_read_timeout(Some(Duration::from_secs(3)))?;
+
+        // OpenVPN prompts with "ENTER PASSWORD:" before accepting commands.
+        let mut buf = [0u8; 256];
+        let _ = stream.read(&mut buf);
+        stream.write_all(format!("{}\nsignal {}\n", self.password, signal).as_bytes())?;
+        let _ = stream.read(&mut buf);
+
+        Ok(())
+    }
+}
+
+fn find_helper() -> Option<PathBuf> {
+    let beside_exe = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|p| p.join(HELPER_NAME)));
+
+    beside_exe
+        .into_iter()
+        .chain(std::iter::once(Path::new(HELPER_INSTALL_DIR).join(HELPER_NAME)))
+        .find(|p| p.is_file())
+}
+
+pub fn find_openvpn() -> Option<PathBuf> {
+    OPENVPN_CANDIDATES
+        .iter()
+        .map(PathBuf::from)
+        .find(|p| p.is_file())
+        .or_else(|| find_in_path("openvpn"))
+}
+
+fn find_in_path(name: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(name))
+        .find(|p| p.is_file())
+}
+
+/// Reads the `security.capability` xattr and checks that `CAP_NET_ADMIN` is
+/// in the permitted set, the same thing `getcap` reports.
+fn has_net_admin(path: &Path) -> bool {
+    let Ok(c_path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    let name = CString::new("security.capability").unwrap();
+    let mut buf = [0u8; 24];
+
+    let len = unsafe {
+        libc::getxattr(
+            c_path.as_ptr(),
+            name.as_ptr(),
+            buf.as_mut_ptr() as *mut libc::c_void,
+            buf.len(),
+        )
+    };
+    if len < 12 {
+        return false;
+    }
+
+    let word = |i: usize| u32::from_le_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+    let revision = word(0) & VFS_CAP_REVISION_MASK;
+    if revision != VFS_CAP_REVISION_2 && revision != VFS_CAP_REVISION_3 {
+        return false;
+    }
+
+    let permitted = word(4);
+    permitted & (1 << CAP_NET_ADMIN) != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chooses_the_least_intrusive_strategy() {
+        let helper = PathBuf::from("/usr/lib/rbw-vpn-client/rbw-vpn-helper");
+        let cases = [
+            (true, Some(helper.clone()), true, Some(Strategy::Root)),
+            (true, None, false, Some(Strategy::Root)),
+            (false, Some(helper.clone()), true, Some(Strategy::CapHelper(helper.clone()))),
+            (false, None, true, Some(Strategy::Pkexec)),
+            (false, None, false, None),
+        ];
+        for (root, cap_helper, pkexec, expected) in cases {
+            assert_eq!(Strategy::choose(root, cap_helper.clone(), pkexec), expected, "{} {:?} {}", root, cap_helper, pkexec);
+        }
+    }
+
+    #[test]
+    fn passes_the_openvpn_binary_to_the_helper() {
+        let openvpn = Path::new("/bin/sh");
+        let cmd = Strategy::CapHelper(PathBuf::from("/opt/helper")).command(Some(openvpn)).unwrap();
+        assert_eq!(cmd.get_program(), "/opt/helper");
+        assert_eq!(cmd.get_args().collect::<Vec<_>>(), ["--openvpn", "/bin/sh"]);
+
+        let cmd = Strategy::Pkexec.command(Some(openvpn)).unwrap();
+        assert_eq!(cmd.get_args().collect::<Vec<_>>(), ["/bin/sh"]);
+
+        assert!(Strategy::Root.command(Some(Path::new("openvpn"))).is_err());
+        assert!(Strategy::Root.command(Some(Path::new("/nonexistent/openvpn"))).is_err());
+    }
+}
@@ -0,0 +1,205 @@
+use crate::api::Server;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::time::timeout;
+
+pub const PROBE_CACHE_TTL: Duration = Duration::from_secs(300);
+
+// Latency credit given to servers in the user's preferred region
+const REGION_BONUS_MS: u64 = 50;
+
+// P_CONTROL_HARD_RESET_CLIENT_V2, key id 0
+const OPCODE_HARD_RESET_CLIENT_V2: u8 = 7 << 3;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeResult {
+    pub code: String,
+    pub tcp_latency_ms: Option<u64>,
+    pub udp_reachable: Option<bool>,
+    pub probed_at: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerRanking {
+    pub code: String,
+    pub name: String,
+    pub ip: String,
+    pub udp_port: u16,
+    pub tcp_port: u16,
+    pub latency_ms: Option<u64>,
+    pub udp_reachable: Option<bool>,
+    pub preferred_region: bool,
+}
+
+pub async fn probe_tcp(addr: SocketAddr, limit: Duration) -> Option<u64> {
+    let started = Instant::now();
+    match timeout(limit, TcpStream::connect(addr)).await {
+        Ok(Ok(_)) => Some(started.elapsed().as_millis() as u64),
+        _ => None,
+    }
+}
+
+/// Sends an OpenVPN client hard-reset packet. `Some(true)` means the server
+/// answered, `Some(false)` means the port was refused, `None` means no answer,
+/// which is expected for servers using tls-auth/tls-crypt.
+pub async fn probe_udp(addr: SocketAddr, limit: Duration) -> Option<bool> {
+    let bind: SocketAddr = if addr.is_ipv4() {
+        ([0, 0, 0, 0], 0).into()
+    } else {
+        ([0u16; 8], 0).into()
+    };
+    let socket = UdpSocket::bind(bind).await.ok()?;
+    socket.connect(addr).await.ok()?;
+
+    let session_id = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default();
+
+    let mut packet = Vec::with_capacity(14);
+    packet.push(OPCODE_HARD_RESET_CLIENT_V2);
+    packet.extend_from_slice(&session_id.to_be_bytes());
+    packet.push(0); // no acks
+    packet.extend_from_slice(&0u32.to_be_bytes()); // message packet id
+
+    if socket.send(&packet).await.is_err() {
+        return Some(false);
+    }
+
+    let mut buf = [0u8; 1500];
+    match timeout(limit, socket.recv(&mut buf)).await {
+        Ok(Ok(_)) => Some(true),
+        Ok(Err(e)) if e.kind() == ErrorKind::ConnectionRefused => Some(false),
+        _ => None,
+    }
+}
+
+pub async fn probe_server(server: &Server, limit: Duration) -> ProbeResult {
+    let ip = server.ip.parse::<std::net::IpAddr>().ok();
+
+    let (tcp_latency_ms, udp_reachable) = match ip {
+        Some(ip) => tokio::join!(
+            probe_tcp(SocketAddr::new(ip, server.tcp_port), limit),
+            probe_udp(SocketAddr::new(ip, server.udp_port), limit),
+        ),
+        None => (None, None),
+    };
+
+    ProbeResult {
+        code: server.code.clone(),
+        tcp_latency_ms,
+        udp_reachable,
+        probed_at: chrono::Utc::now().to_rfc3339(),
+    }
+}
+
+pub async fn probe_servers(servers: &[Server], limit: Duration) -> Vec<ProbeResult> {
+    let mut tasks = tokio::task::JoinSet::new();
+    for server in servers.iter().cloned() {
+        tasks.spawn(async move { probe_server(&server, limit).await });
+    }
+
+    let mut results = Vec::with_capacity(servers.len());
+    while let Some(result) = tasks.join_next().await {
+        if let Ok(result) = result {
+            results.push(result);
+        }
+    }
+
+    results
+}
+
+fn in_region(server: &Server, region: Option<&str>) -> bool {
+    match region {
+        Some(region) if !region.is_empty() => {
+            let region = region.to_lowercase();
+            server.code.to_lowercase().starts_with(&region)
+        }
+        _ => false,
+    }
+}
+
+/// Orders servers best-first: reachable servers by latency (with a bonus for
+/// the preferred region), then unreachable ones.
+pub fn rank_servers(
+    servers: &[Server],
+    probes: &HashMap<String, ProbeResult>,
+    region: Option<&str>,
+) -> Vec<ServerRanking> {
+    let mut ranked: Vec<ServerRanking> = servers
+        .iter()
+        .map(|s| {
+            let probe = probes.get(&s.code);
+            ServerRanking {
+                code: s.code.clone(),
+                name: s.name.clone(),
+                ip: s.ip.clone(),
+                udp_port: s.udp_port,
+                tcp_port: s.tcp_port,
+                latency_ms: probe.and_then(|p| p.tcp_latency_ms),
+                udp_reachable: probe.and_then(|p| p.udp_reachable),
+                preferred_region: in_region(s, region),
+            }
+        })
+        .collect();
+
+    ranked.sort_by_key(|r| match r.latency_ms {
+        Some(ms) if r.preferred_region => (0, ms.saturating_sub(REGION_BONUS_MS)),
+        Some(ms) => (0, ms),
+        None if r.udp_reachable == Some(true) => (1, 0),
+        None => (2, 0),
+    });
+
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    const LIMIT: Duration = Duration::from_millis(500);
+
+    #[tokio::test]
+    async fn tcp_measures_a_listening_port() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        assert!(probe_tcp(addr, LIMIT).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn tcp_fails_on_a_closed_port() {
+        let addr = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        assert_eq!(probe_tcp(addr, LIMIT).await, None);
+    }
+
+    #[tokio::test]
+    async fn udp_sends_a_hard_reset_and_sees_the_answer() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = server.local_addr().unwrap();
+        let answer = tokio::spawn(async move {
+            let mut buf = [0u8; 1500];
+            let (len, from) = server.recv_from(&mut buf).await.unwrap();
+            server.send_to(&[0x40], from).await.unwrap();
+            buf[..len].to_vec()
+        });
+
+        assert_eq!(probe_udp(addr, LIMIT).await, Some(true));
+        let packet = answer.await.unwrap();
+        assert_eq!(packet.len(), 14);
+        assert_eq!(packet[0], OPCODE_HARD_RESET_CLIENT_V2);
+        assert_eq!(&packet[9..], &[0, 0, 0, 0, 0]);
+    }
+
+    #[tokio::test]
+    async fn udp_without_an_answer_is_unknown() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = server.local_addr().unwrap();
+        assert_eq!(probe_udp(addr, Duration::from_millis(100)).await, None);
+        drop(server);
+    }
+}
@@ -0,0 +1,324 @@
+use std::fmt;
+
+/// One line of an OpenVPN config, or a whole inline block. The original
+/// text is kept so untouched parts are written back byte for byte.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Line {
+    Directive {
+        name: String,
+        args: Vec<String>,
+        raw: String,
+    },
+    Block {
+        tag: String,
+        body: String,
+        raw: String,
+    },
+    /// Blank lines, comments and anything else that is not parsed.
+    Other(String),
+}
+
+impl Line {
+    pub fn directive(name: &str, args: &[&str]) -> Self {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        let raw = std::iter::once(name.to_string())
+            .chain(args.iter().map(|a| quote(a)))
+            .collect::<Vec<_>>()
+            .join(" ");
+        Line::Directive {
+            name: name.to_string(),
+            args,
+            raw,
+        }
+    }
+
+    pub fn block(tag: &str, body: &str) -> Self {
+        let body = body.trim_end().to_string();
+        Line::Block {
+            raw: format!("<{tag}>\n{body}\n</{tag}>"),
+            tag: tag.to_string(),
+            body,
+        }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            Line::Directive { name, .. } => Some(name),
+            _ => None,
+        }
+    }
+}
+
+fn quote(arg: &str) -> String {
+    let needs_quotes = arg.is_empty()
+        || arg.starts_with(['#', ';'])
+        || arg.contains(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '\\'));
+    if needs_quotes {
+        format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        arg.to_string()
+    }
+}
+
+/// Splits a config line into words the way openvpn does: whitespace
+/// separates, double quotes group with backslash escapes, single quotes
+/// group literally, a backslash escapes the next character and `#` or
+/// `;` between words starts a comment. The privilege helper checks
+/// configs with this too, so both read a line the same way.
+pub fn split_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote: Option<char> = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match quote {
+            Some('"') => match c {
+                '"' => quote = None,
+                '\\' => word.extend(chars.next()),
+                _ => word.push(c),
+            },
+            Some(_) => match c {
+                '\'' => quote = None,
+                _ => word.push(c),
+            },
+            None => match c {
+                '"' | '\'' => {
+                    quote = Some(c);
+                    in_word = true;
+                }
+                '\\' => {
+                    word.extend(chars.next());
+                    in_word = true;
+                }
+                '#' | ';' if !in_word => break,
+                c if c.is_whitespace() => {
+                    if in_word {
+                        words.push(std::mem::take(&mut word));
+                        in_word = false;
+                    }
+                }
+                _ => {
+                    word.push(c);
+                    in_word = true;
+                }
+            },
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub lines: Vec<Line>,
+    crlf: bool,
+    final_newline: bool,
+}
+
+impl Profile {
+    pub fn parse(content: &str) -> Self {
+        let mut lines = Vec::new();
+        let mut source = content.lines();
+
+        while let Some(line) = source.next() {
+            let trimmed = line.trim();
+
+            if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with(';') {
+                lines.push(Line::Other(line.to_string()));
+                continue;
+            }
+
+            if let Some(tag) = trimmed
+                .strip_prefix('<')
+                .and_then(|t| t.strip_suffix('>'))
+                .filter(|t| !t.starts_with('/'))
+            {
+                let close = format!("</{}>", tag);
+                let mut raw = vec![line.to_string()];
+                let mut body = Vec::new();
+                let mut closed = false;
+
+                for inner in source.by_ref() {
+                    raw.push(inner.to_string());
+                    if inner.trim() == close {
+                        closed = true;
+                        break;
+                    }
+                    body.push(inner);
+                }
+
+                if closed {
+                    lines.push(Line::Block {
+                        tag: tag.to_string(),
+                        body: body.join("\n"),
+                        raw: raw.join("\n"),
+                    });
+                } else {
+                    lines.extend(raw.into_iter().map(Line::Other));
+                }
+                continue;
+            }
+
+            let mut words = split_words(trimmed);
+            if words.is_empty() {
+                lines.push(Line::Other(line.to_string()));
+                continue;
+            }
+            let name = words.remove(0).trim_start_matches("--").to_string();
+            lines.push(Line::Directive {
+                name,
+                args: words,
+                raw: line.to_string(),
+            });
+        }
+
+        Self {
+            lines,
+            crlf: content.contains("\r\n"),
+            final_newline: content.is_empty() || content.ends_with('\n'),
+        }
+    }
+
+    /// Arguments of the first occurrence of a directive.
+    pub fn get(&self, name: &str) -> Option<&[String]> {
+        self.lines.iter().find_map(|line| match line {
+            Line::Directive { name: n, args, .. } if n == name => Some(args.as_slice()),
+            _ => None,
+        })
+    }
+
+    /// Arguments of every occurrence of a directive, in order.
+    pub fn get_all(&self, name: &str) -> Vec<&[String]> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Directive { name: n, args, .. } if n == name => Some(args.as_slice()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn has(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    pub fn block(&self, tag: &str) -> Option<&str> {
+        self.lines.iter().find_map(|line| match line {
+            Line::Block { tag: t, body, .. } if t == tag => Some(body.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Replaces the first occurrence of a directive and drops any others,
+    /// or inserts it before the first inline block when it is missing.
+    pub fn set(&mut self, name: &str, args: &[&str]) {
+        let new = Line::directive(name, args);
+        match self.lines.iter().position(|l| l.name() == Some(name)) {
+            Some(first) => {
+                self.lines[first] = new;
+                let mut index = 0;
+                self.lines.retain(|l| {
+                    index += 1;
+                    index - 1 == first || l.name() != Some(name)
+                });
+            }
+            None => self.insert(new),
+        }
+    }
+
+    /// Replaces every occurrence of a directive with `lines`, placed where
+    /// the first occurrence was.
+    pub fn replace_all(&mut self, name: &str, lines: Vec<Line>) {
+        match self.lines.iter().position(|l| l.name() == Some(name)) {
+            Some(first) => {
+                let end = first + lines.len();
+                self.lines.splice(first..first + 1, lines);
+                let mut index = 0;
+                self.lines.retain(|l| {
+                    index += 1;
+                    (first..end).contains(&(index - 1)) || l.name() != Some(name)
+                });
+            }
+            None => {
+                for line in lines {
+                    self.insert(line);
+                }
+            }
+        }
+    }
+
+    /// Inserts a line before the first inline block, where directives go.
+    pub fn insert(&mut self, line: Line) {
+        let at = self
+            .lines
+            .iter()
+            .position(|l| matches!(l, Line::Block { .. }))
+            .unwrap_or(self.lines.len());
+        self.lines.insert(at, line);
+    }
+
+    /// Removes every occurrence of a directive, returning how many there were.
+    pub fn remove(&mut self, name: &str) -> usize {
+        let before = self.lines.len();
+        self.lines.retain(|l| l.name() != Some(name));
+        before - self.lines.len()
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let newline = if self.crlf { "\r\n" } else { "\n" };
+
+        for (i, line) in self.lines.iter().enumerate() {
+            let raw = match line {
+                Line::Directive { raw, .. } | Line::Block { raw, .. } | Line::Other(raw) => raw,
+            };
+            f.write_str(&raw.replace('\n', newline))?;
+            if i + 1 < self.lines.len() || self.final_newline {
+                f.write_str(newline)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_like_openvpn() {
+        assert_eq!(split_words("remote  host 1194 # comment"), ["remote", "host", "1194"]);
+        assert_eq!(split_words("remote host 1194 ;comment"), ["remote", "host", "1194"]);
+        assert_eq!(split_words(r#"auth-user-pass "a b\"c" 'd\e'"#), ["auth-user-pass", "a b\"c", "d\\e"]);
+        assert_eq!(split_words("route-up a#b"), ["route-up", "a#b"]);
+    }
+
+    #[test]
+    fn round_trips_unchanged() {
+        let content = "# work profile\r\nclient\r\nremote vpn.example.com 1194 udp # primary\r\n; remote old.example.com\r\n\
+                       auth-user-pass \"C:\\\\Program Files\\\\creds.txt\"\r\nsetenv UV_NAME 'a b'\r\n\
+                       <ca>\r\n-----BEGIN CERTIFICATE-----\r\nMIIB\r\n-----END CERTIFICATE-----\r\n</ca>\r\n";
+        let profile = Profile::parse(content);
+        assert_eq!(profile.to_string(), content);
+
+        assert_eq!(profile.get("remote").unwrap(), ["vpn.example.com", "1194", "udp"]);
+        assert_eq!(profile.get("auth-user-pass").unwrap(), ["C:\\Program Files\\creds.txt"]);
+        assert_eq!(profile.get("setenv").unwrap(), ["UV_NAME", "a b"]);
+        assert!(profile.block("ca").unwrap().contains("MIIB"));
+    }
+
+    #[test]
+    fn written_arguments_read_back() {
+        for arg in ["a b", "it's", "say \"hi\"", "C:\\dir", "#tag", ";x", ""] {
+            let mut profile = Profile::parse("client\n");
+            profile.set("setenv", &["NAME", arg]);
+            let reparsed = Profile::parse(&profile.to_string());
+            assert_eq!(reparsed.get("setenv").unwrap(), ["NAME", arg], "{:?}", arg);
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+pub const MAX_PROFILE_NAME_LEN: usize = 64;
+
+const EXTENSIONS: &[&str] = &[".ovpn", ".conf"];
+
+const RESERVED: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum ProfileNameError {
+    #[error("Profile name is empty")]
+    Empty,
+    #[error("Profile name is longer than {MAX_PROFILE_NAME_LEN} characters")]
+    TooLong,
+    #[error("Profile name contains invalid character {0:?}")]
+    InvalidCharacter(char),
+    #[error("Profile name cannot start with '.' or contain '..'")]
+    InvalidDots,
+    #[error("Profile name {0:?} is reserved")]
+    Reserved(String),
+}
+
+/// A profile name that is safe to use as a file stem inside the config
+/// directory: no path separators, no traversal, no device names.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ProfileName(String);
+
+impl ProfileName {
+    pub fn parse(raw: &str) -> Result<Self, ProfileNameError> {
+        let mut name = raw.trim();
+        for ext in EXTENSIONS {
+            let split = name.len().saturating_sub(ext.len());
+            if split > 0 && name.get(split..).is_some_and(|tail| tail.eq_ignore_ascii_case(ext)) {
+                name = name[..split].trim_end();
+            }
+        }
+
+        if name.is_empty() {
+            return Err(ProfileNameError::Empty);
+        }
+        if name.chars().count() > MAX_PROFILE_NAME_LEN {
+            return Err(ProfileNameError::TooLong);
+        }
+        if let Some(c) = name.chars().find(|c| !is_allowed(*c)) {
+            return Err(ProfileNameError::InvalidCharacter(c));
+        }
+        if name.starts_with('.') || name.ends_with('.') || name.contains("..") {
+            return Err(ProfileNameError::InvalidDots);
+        }
+
+        let stem = name.split('.').next().unwrap_or(name).trim_end();
+        if RESERVED.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+            return Err(ProfileNameError::Reserved(name.to_string()));
+        }
+
+        Ok(Self(name.to_string()))
+    }
+
+    /// Builds a valid name from untrusted input such as a portal label or a
+    /// file name, replacing anything not allowed instead of rejecting it.
+    pub fn sanitize(raw: &str) -> Result<Self, ProfileNameError> {
+        let cleaned: String = raw
+            .trim()
+            .chars()
+            .map(|c| if is_allowed(c) { c } else { '-' })
+            .collect();
+        let cleaned = cleaned.replace("..", "-");
+        let cleaned = cleaned.trim_matches(|c| c == '.' || c == ' ');
+        let truncated: String = cleaned.chars().take(MAX_PROFILE_NAME_LEN).collect();
+
+        Self::parse(&truncated).or_else(|e| match e {
+            // The suffix goes on the stem, since "nul.txt" is as reserved as "nul"
+            ProfileNameError::Reserved(name) => Self::parse(&match name.split_once('.') {
+                Some((stem, rest)) => format!("{}-profile.{}", stem.trim_end(), rest),
+                None => format!("{}-profile", name),
+            }),
+            e => Err(e),
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Comparison key for filesystems that ignore case (Windows, macOS).
+    pub fn key(&self) -> String {
+        self.0.to_lowercase()
+    }
+}
+
+fn is_allowed(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ' ' | '@' | '+' | '(' | ')')
+}
+
+impl fmt::Display for ProfileName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for ProfileName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for ProfileName {
+    type Error = ProfileNameError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(&value)
+    }
+}
+
+impl From<ProfileName> for String {
+    fn from(name: ProfileName) -> Self {
+        name.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_plain_names() {
+        assert_eq!(ProfileName::parse("de-fra-udp").unwrap().as_str(), "de-fra-udp");
+        assert_eq!(ProfileName::parse(" office (2).ovpn ").unwrap().as_str(), "office (2)");
+        assert_eq!(ProfileName::parse("home.CONF").unwrap().as_str(), "home");
+        assert_eq!(ProfileName::parse("v1.2").unwrap().as_str(), "v1.2");
+    }
+
+    #[test]
+    fn rejects_traversal() {
+        for raw in ["..", "a..b", ".hidden", "trailing.", "..ovpn"] {
+            assert_eq!(ProfileName::parse(raw), Err(ProfileNameError::InvalidDots), "{raw}");
+        }
+        assert!(ProfileName::parse("../etc").is_err());
+    }
+
+    #[test]
+    fn rejects_separators_and_control_characters() {
+        assert_eq!(ProfileName::parse("a/b"), Err(ProfileNameError::InvalidCharacter('/')));
+        assert_eq!(ProfileName::parse("a\\b"), Err(ProfileNameError::InvalidCharacter('\\')));
+        assert_eq!(ProfileName::parse("c:x"), Err(ProfileNameError::InvalidCharacter(':')));
+        assert_eq!(ProfileName::parse("a\0b"), Err(ProfileNameError::InvalidCharacter('\0')));
+        assert_eq!(ProfileName::parse("a\nb"), Err(ProfileNameError::InvalidCharacter('\n')));
+    }
+
+    #[test]
+    fn rejects_reserved_windows_names() {
+        for raw in ["CON", "nul", "Com1", "lpt9.ovpn", "aux.txt", "PRN "] {
+            assert!(matches!(ProfileName::parse(raw), Err(ProfileNameError::Reserved(_))), "{raw}");
+        }
+        assert!(ProfileName::parse("console").is_ok());
+        assert!(ProfileName::parse("com10").is_ok());
+    }
+
+    #[test]
+    fn limits_length() {
+        let max = "a".repeat(MAX_PROFILE_NAME_LEN);
+        assert!(ProfileName::parse(&max).is_ok());
+        assert!(ProfileName::parse(&format!("{max}.ovpn")).is_ok());
+        assert_eq!(ProfileName::parse(&format!("{max}a")), Err(ProfileNameError::TooLong));
+        // Counted in characters, not bytes
+        assert!(ProfileName::parse(&"é".repeat(MAX_PROFILE_NAME_LEN)).is_ok());
+    }
+
+    #[test]
+    fn rejects_empty_names() {
+        for raw in ["", "   ", ".ovpn", " .conf"] {
+            assert!(ProfileName::parse(raw).is_err(), "{raw:?}");
+        }
+    }
+
+    #[test]
+    fn sanitizes_untrusted_names() {
+        let sanitize = |raw: &str| ProfileName::sanitize(raw).unwrap().to_string();
+        assert_eq!(sanitize("../../etc/passwd"), "----etc-passwd");
+        assert_eq!(sanitize("a/b\\c"), "a-b-c");
+        assert_eq!(sanitize("a\0b"), "a-b");
+        assert_eq!(sanitize(".hidden."), "hidden");
+        assert_eq!(sanitize("CON"), "CON-profile");
+        assert_eq!(sanitize("nul.ovpn"), "nul-profile");
+        assert_eq!(sanitize("aux.txt"), "aux-profile.txt");
+        assert_eq!(sanitize(&"x".repeat(100)).chars().count(), MAX_PROFILE_NAME_LEN);
+        assert!(ProfileName::sanitize("/").is_ok());
+        assert!(ProfileName::sanitize("..").is_ok());
+        assert!(ProfileName::sanitize("").is_err());
+    }
+
+    #[test]
+    fn sanitized_names_parse_back() {
+        for raw in ["a/b", "..", "CON", "  x  ", "日本語/サーバー", "a..b..c", &"y".repeat(200)] {
+            let name = ProfileName::sanitize(raw).unwrap();
+            assert_eq!(ProfileName::parse(name.as_str()).unwrap(), name, "{raw}");
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use crate::api::ApiClient;
+use crate::commands;
+use crate::metadata::ProfileStatus;
+use crate::openvpn::OpenVpnManager;
+use crate::probe;
+use crate::profile_name::ProfileName;
+use crate::server_cache;
+use crate::settings::{Settings, SettingsStore};
+use crate::state::AppState;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+
+const SAMPLES: u32 = 5;
+const REPLY_TIMEOUT_SECS: u64 = 2;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionQuality {
+    /// Average round-trip time to the tunnel gateway, `None` when every
+    /// sample was lost.
+    pub rtt_ms: Option<f64>,
+    /// Mean difference between consecutive round-trip times.
+    pub jitter_ms: Option<f64>,
+    pub loss_percent: f64,
+    /// False until the gateway has answered a ping during this connection.
+    /// Gateways that drop ICMP never do, so their loss says nothing.
+    pub measurable: bool,
+    pub measured_at: String,
+    /// Since when the quality has been below the limits from settings.
+    pub degraded_since: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerSwitch {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerSwitchFailure {
+    pub from: String,
+    pub error: String,
+    /// Set when neither the new server nor the previous one could be
+    /// reconnected, leaving the VPN down.
+    pub disconnected: bool,
+}
+
+fn ping_command(host: &str) -> tokio::process::Command {
+    let mut cmd = tokio::process::Command::new("ping");
+
+    #[cfg(target_os = "windows")]
+    {
+        const CREATE_NO_WINDOW: u32 = 0x08000000;
+        cmd.creation_flags(CREATE_NO_WINDOW);
+        cmd.args(["-n", &SAMPLES.to_string(), "-w", &(REPLY_TIMEOUT_SECS * 1000).to_string()]);
+    }
+
+    // macOS takes the reply timeout in milliseconds
+    #[cfg(target_os = "macos")]
+    cmd.args(["-n", "-c", &SAMPLES.to_string(), "-W", &(REPLY_TIMEOUT_SECS * 1000).to_string()]);
+
+    #[cfg(target_os = "linux")]
+    cmd.args(["-n", "-c", &SAMPLES.to_string(), "-W", &REPLY_TIMEOUT_SECS.to_string()]);
+
+    cmd.arg(host).kill_on_drop(true);
+    cmd
+}
+
+/// Pings `host` a few times with the system `ping`, which needs no extra
+/// privileges, and summarises the replies.
+pub async fn measure(host: &str) -> Result<ConnectionQuality> {
+    let limit = Duration::from_secs(SAMPLES as u64 * (REPLY_TIMEOUT_SECS + 1));
+    let output = tokio::time::timeout(limit, ping_command(host).output())
+        .await
+        .context("ping did not finish")?
+        .context("Failed to run ping")?;
+    let output = String::from_utf8_lossy(&output.stdout);
+
+    let reply = Regex::new(r"time[=<]\s*([\d.]+)\s*ms").unwrap();
+    let rtts: Vec<f64> = reply
+        .captures_iter(&output)
+        .filter_map(|caps| caps[1].parse().ok())
+        .collect();
+
+    let received = (rtts.len() as u32).min(SAMPLES);
+    let rtt_ms = (!rtts.is_empty()).then(|| rtts.iter().sum::<f64>() / rtts.len() as f64);
+    let jitter_ms = (rtts.len() > 1).then(|| {
+        rtts.windows(2).map(|w| (w[1] - w[0]).abs()).sum::<f64>() / (rtts.len() - 1) as f64
+    });
+
+    Ok(ConnectionQuality {
+        rtt_ms,
+        jitter_ms,
+        loss_percent: (SAMPLES - received) as f64 * 100.0 / SAMPLES as f64,
+        measurable: received > 0,
+        measured_at: Utc::now().to_rfc3339(),
+        degraded_since: None,
+    })
+}
+
+pub fn is_degraded(quality: &ConnectionQuality, settings: &Settings) -> bool {
+    quality.measurable
+        && (quality.loss_percent > settings.quality_max_loss_percent as f64
+            || quality.rtt_ms.is_none_or(|rtt| rtt > settings.quality_max_rtt_ms as f64))
+}
+
+/// Connects to the best-ranked other server that has a usable profile,
+/// going back to the current one if that fails. Returns `None` without
+/// touching the connection when the user connected or disconnected since
+/// `generation`.
+async fn switch_server(
+    app: &AppHandle,
+    settings: &Settings,
+    current: &ProfileName,
+    generation: u64,
+) -> Result<Option<ProfileName>> {
+    let manager = OpenVpnManager::new();
+    let state = app.state::<AppState>();
+    let current_code = manager.load_meta(current).server_code;
+
+    let servers = server_cache::current_servers(&app.state::<ApiClient>(), settings.server_cache_ttl_secs).await?;
+    let timeout = Duration::from_millis(settings.probe_timeout_ms);
+    let probes = commands::probe_with_cache(&state, &servers, timeout, true)
+        .await
+        .into_iter()
+        .map(|p| (p.code.clone(), p))
+        .collect();
+
+    let mut profiles: HashMap<String, ProfileName> = HashMap::new();
+    for config in manager.list_configs()? {
+        let Ok(name) = ProfileName::parse(&config.name) else {
+            continue;
+        };
+        let meta = manager.load_meta(&name);
+        if let (ProfileStatus::Active, Some(code)) = (meta.status, meta.server_code) {
+            profiles.entry(code).or_insert(name);
+        }
+    }
+
+    let target = probe::rank_servers(&servers, &probes, settings.preferred_region.as_deref())
+        .into_iter()
+        .filter(|r| current_code.as_deref() != Some(r.code.as_str()))
+        .filter(|r| r.latency_ms.is_some() || r.udp_reachable == Some(true))
+        .find_map(|r| profiles.remove(&r.code))
+        .ok_or_else(|| anyhow::anyhow!("No other reachable server has a profile"))?;
+
+    let Some(ours) = state.claim_generation(generation) else {
+        return Ok(None);
+    };
+    manager.disconnect()?;
+    state.set_connection(None);
+
+    let connect = |name: ProfileName| {
+        commands::connect_profile(app.state(), app.state(), app.state(), name, None)
+    };
+    if let Err(e) = connect(target.clone()).await {
+        manager.disconnect().ok();
+        let failed = format!("Failed to connect to {}: {}", target, e);
+        // The user may have picked a connection of their own meanwhile
+        if state.claim_generation(ours).is_some() {
+            if let Err(e) = connect(current.clone()).await {
+                return Err(anyhow::anyhow!("{}. Reconnecting to {} failed too: {}", failed, current, e));
+            }
+        }
+        return Err(anyhow::anyhow!(failed));
+    }
+    Ok(Some(target))
+}
+
+async fn check(app: &AppHandle, settings: &Settings) {
+    let manager = OpenVpnManager::new();
+    let state = app.state::<AppState>();
+    let generation = state.generation();
+    let Some(conn) = state.get_connection() else {
+        return;
+    };
+    if !manager.is_connected() || !manager.handshake_completed() {
+        return;
+    }
+    let Some(gateway) = manager.tunnel_gateway() else {
+        return;
+    };
+    let Ok(mut quality) = measure(&gateway).await else {
+        return;
+    };
+    quality.measurable |= conn.quality.as_ref().is_some_and(|q| q.measurable);
+
+    if is_degraded(&quality, settings) {
+        quality.degraded_since = conn
+            .quality
+            .and_then(|q| q.degraded_since)
+            .or_else(|| Some(quality.measured_at.clone()));
+    }
+
+    // The user may have disconnected or switched while this was measuring
+    if !state.update_connection(&conn.connected_at, |c| c.quality = Some(quality.clone())) {
+        return;
+    }
+    app.emit("connection-quality", &quality).ok();
+
+    let degraded_for = quality
+        .degraded_since
+        .as_deref()
+        .and_then(|since| DateTime::parse_from_rfc3339(since).ok())
+        .map(|since| (Utc::now() - since.with_timezone(&Utc)).num_seconds());
+    if !settings.auto_switch_server || degraded_for.is_none_or(|secs| secs < settings.auto_switch_after_secs as i64) {
+        return;
+    }
+
+    let Ok(current) = ProfileName::parse(&conn.config_name) else {
+        return;
+    };
+    match switch_server(app, settings, &current, generation).await {
+        Ok(Some(target)) => {
+            app.emit(
+                "server-switched",
+                ServerSwitch {
+                    from: current.to_string(),
+                    to: target.to_string(),
+                },
+            )
+            .ok();
+        }
+        Ok(None) => {}
+        Err(e) => {
+            let disconnected = state.get_connection().is_none();
+            app.emit(
+                "server-switch-failed",
+                ServerSwitchFailure {
+                    from: current.to_string(),
+                    error: e.to_string(),
+                    disconnected,
+                },
+            )
+            .ok();
+            // Wait for another full period below the limits before retrying.
+            // A reconnect starts a new connection, which waits anyway.
+            state.update_connection(&conn.connected_at, |c| {
+                if let Some(q) = c.quality.as_mut() {
+                    q.degraded_since = Some(Utc::now().to_rfc3339());
+                }
+            });
+        }
+    }
+}
+
+/// Measures the tunnel on the interval from settings and emits
+/// "connection-quality" with each result.
+pub fn spawn_monitor(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let interval = app.state::<SettingsStore>().get().quality_interval_secs;
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+
+            let settings = app.state::<SettingsStore>().get();
+            if settings.quality_monitor {
+                check(&app, &settings).await;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quality(rtt_ms: Option<f64>, loss_percent: f64, measurable: bool) -> ConnectionQuality {
+        ConnectionQuality {
+            rtt_ms,
+            jitter_ms: None,
+            loss_percent,
+            measurable,
+            measured_at: Utc::now().to_rfc3339(),
+            degraded_since: None,
+        }
+    }
+
+    #[test]
+    fn silent_gateways_are_not_degraded() {
+        let settings = Settings::default();
+        assert!(!is_degraded(&quality(None, 100.0, false), &settings));
+    }
+
+    #[test]
+    fn loss_counts_once_the_gateway_has_answered() {
+        let settings = Settings::default();
+        assert!(is_degraded(&quality(None, 100.0, true), &settings));
+        assert!(is_degraded(&quality(Some(20.0), 60.0, true), &settings));
+        assert!(is_degraded(&quality(Some(900.0), 0.0, true), &settings));
+        assert!(!is_degraded(&quality(Some(20.0), 0.0, true), &settings));
+    }
+}
@@ -0,0 +1,271 @@
+use crate::editor::{self, ProfileEdit, Remote};
+use crate::profile::Profile;
+use crate::profile_name::ProfileName;
+use crate::storage::{FileMode, Storage};
+use anyhow::Result;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::net::{IpAddr, ToSocketAddrs};
+use std::path::PathBuf;
+
+const DEFAULT_PORT: u16 = 1194;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RemoteHealth {
+    pub successes: u32,
+    pub failures: u32,
+    pub last_success: Option<String>,
+    pub last_failure: Option<String>,
+}
+
+impl RemoteHealth {
+    fn last_failed(&self) -> bool {
+        match (&self.last_success, &self.last_failure) {
+            (Some(success), Some(failure)) => failure > success,
+            (None, Some(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RemoteStatus {
+    pub host: String,
+    pub port: u16,
+    pub proto: String,
+    /// Position in the profile file.
+    pub index: usize,
+    pub health: RemoteHealth,
+    pub in_use: bool,
+}
+
+// Profile name -> remote key -> health
+type HealthFile = HashMap<String, HashMap<String, RemoteHealth>>;
+
+fn path() -> PathBuf {
+    crate::paths::state_dir().join("remote_health.json")
+}
+
+fn load() -> HealthFile {
+    fs::read_to_string(path())
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save(health: &HealthFile) -> Result<()> {
+    Storage::write_atomic(&path(), serde_json::to_string_pretty(health)?.as_bytes(), FileMode::Public)
+}
+
+fn key(remote: &Remote, default_proto: &str) -> String {
+    format!(
+        "{}:{}/{}",
+        remote.host,
+        remote.port.unwrap_or(DEFAULT_PORT),
+        remote.proto.as_deref().unwrap_or(default_proto)
+    )
+}
+
+/// Remotes in the order they should be tried: the last one that connected
+/// first, then the rest in file order, with remotes whose last attempt
+/// failed moved to the end. Profiles using `remote-random` keep file order.
+fn order(profile: &Profile, health: &HashMap<String, RemoteHealth>) -> Vec<(usize, Remote)> {
+    let settings = editor::settings(profile);
+    if profile.has("remote-random") {
+        return settings.remotes.into_iter().enumerate().collect();
+    }
+    let last_good = settings
+        .remotes
+        .iter()
+        .filter_map(|r| {
+            let h = health.get(&key(r, &settings.proto))?;
+            (!h.last_failed()).then_some(h.last_success.as_deref()?)
+        })
+        .max();
+
+    let mut remotes: Vec<(usize, Remote)> = settings.remotes.into_iter().enumerate().collect();
+    remotes.sort_by_key(|(_, r)| match health.get(&key(r, &settings.proto)) {
+        Some(h) if last_good.is_some() && h.last_success.as_deref() == last_good => 0,
+        Some(h) if h.last_failed() => 2,
+        _ => 1,
+    });
+    remotes
+}
+
+/// The profile with its remotes reordered by their history, or `None` when
+/// the order would not change.
+pub fn reordered(name: &ProfileName, content: &str) -> Result<Option<String>> {
+    let mut profile = Profile::parse(content);
+    let ordered = order(&profile, &load().remove(name.as_str()).unwrap_or_default());
+    if ordered.iter().enumerate().all(|(i, (index, _))| i == *index) {
+        return Ok(None);
+    }
+
+    editor::apply(
+        &mut profile,
+        &ProfileEdit {
+            remotes: Some(ordered.into_iter().map(|(_, r)| r).collect()),
+            ..Default::default()
+        },
+    )?;
+    Ok(Some(profile.to_string()))
+}
+
+/// The host OpenVPN will try first.
+pub fn first_host(name: &ProfileName, content: &str) -> Option<String> {
+    let health = load().remove(name.as_str()).unwrap_or_default();
+    order(&Profile::parse(content), &health).into_iter().next().map(|(_, r)| r.host)
+}
+
+fn matches(remote: &Remote, ip: IpAddr, port: u16) -> bool {
+    if remote.port.unwrap_or(DEFAULT_PORT) != port {
+        return false;
+    }
+    match remote.host.parse::<IpAddr>() {
+        Ok(host) => host == ip,
+        Err(_) => (remote.host.as_str(), port)
+            .to_socket_addrs()
+            .map(|mut addrs| addrs.any(|a| a.ip() == ip))
+            .unwrap_or(false),
+    }
+}
+
+/// Position in the file of the remote OpenVPN reached at `ip:port`.
+pub fn identify(content: &str, ip: &str, port: u16) -> Option<usize> {
+    let ip: IpAddr = ip.parse().ok()?;
+    let remotes = editor::settings(&Profile::parse(content)).remotes;
+    // Literal addresses first, so names are only resolved when needed
+    remotes
+        .iter()
+        .position(|r| r.host.parse::<IpAddr>().is_ok() && matches(r, ip, port))
+        .or_else(|| remotes.iter().position(|r| matches(r, ip, port)))
+}
+
+/// [`identify`] on a blocking thread, since resolving names blocks.
+pub async fn identify_blocking(content: &str, ip: &str, port: u16) -> Option<usize> {
+    let (content, ip) = (content.to_string(), ip.to_string());
+    tokio::task::spawn_blocking(move || identify(&content, &ip, port))
+        .await
+        .ok()
+        .flatten()
+}
+
+// Log lines that mean a remote could not be reached, as opposed to the
+// server turning the client away
+const UNREACHABLE: &[&str] = &[
+    "TLS key negotiation failed to occur within",
+    "TLS Error: TLS handshake failed",
+    "Connection timed out",
+    "Connection refused",
+    "No route to host",
+    "Network is unreachable",
+    "Cannot resolve host address",
+];
+
+/// Whether a failed attempt's log shows the remotes were unreachable. An
+/// authentication failure says nothing about them.
+pub fn unreachable(log: &str) -> bool {
+    !log.contains("AUTH_FAILED") && UNREACHABLE.iter().any(|line| log.contains(line))
+}
+
+/// The remote at file position `index` as `host:port`.
+pub fn label(content: &str, index: usize) -> Option<String> {
+    let remotes = editor::settings(&Profile::parse(content)).remotes;
+    let remote = remotes.get(index)?;
+    Some(format!("{}:{}", remote.host, remote.port.unwrap_or(DEFAULT_PORT)))
+}
+
+/// Records the outcome of a connection. `connected` is the file position of
+/// the remote that was reached, or `None` when none was; every remote tried
+/// before it counts as failed. Only call it with `None` when the log shows
+/// the remotes were [`unreachable`].
+pub fn record(name: &ProfileName, content: &str, connected: Option<usize>) -> Result<()> {
+    let profile = Profile::parse(content);
+    let mut all = load();
+    let health = all.entry(name.to_string()).or_default();
+    let settings = editor::settings(&profile);
+    let now = Utc::now().to_rfc3339();
+
+    // With remote-random the order tried is unknown, so only the success counts
+    let tried = if profile.has("remote-random") {
+        Vec::new()
+    } else {
+        order(&profile, health)
+    };
+    for (_, remote) in tried.iter().take_while(|(index, _)| Some(*index) != connected) {
+        let entry = health.entry(key(remote, &settings.proto)).or_default();
+        entry.failures += 1;
+        entry.last_failure = Some(now.clone());
+    }
+    if let Some(remote) = connected.and_then(|i| settings.remotes.get(i)) {
+        let entry = health.entry(key(remote, &settings.proto)).or_default();
+        entry.successes += 1;
+        entry.last_success = Some(now);
+    }
+
+    save(&all)
+}
+
+/// Every remote of the profile with its history, in file order.
+pub fn status(name: &ProfileName, content: &str, in_use: Option<usize>) -> Vec<RemoteStatus> {
+    let settings = editor::settings(&Profile::parse(content));
+    let mut health = load().remove(name.as_str()).unwrap_or_default();
+    settings
+        .remotes
+        .iter()
+        .enumerate()
+        .map(|(index, r)| RemoteStatus {
+            host: r.host.clone(),
+            port: r.port.unwrap_or(DEFAULT_PORT),
+            proto: r.proto.clone().unwrap_or_else(|| settings.proto.clone()),
+            index,
+            health: health.remove(&key(r, &settings.proto)).unwrap_or_default(),
+            in_use: in_use == Some(index),
+        })
+        .collect()
+}
+
+pub fn rename(from: &ProfileName, to: &ProfileName) -> Result<()> {
+    let mut all = load();
+    match all.remove(from.as_str()) {
+        Some(health) => {
+            all.insert(to.to_string(), health);
+            save(&all)
+        }
+        None => Ok(()),
+    }
+}
+
+pub fn remove(name: &ProfileName) -> Result<()> {
+    let mut all = load();
+    if all.remove(name.as_str()).is_some() {
+        save(&all)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timeouts_mean_unreachable() {
+        assert!(unreachable(
+            "TLS Error: TLS key negotiation failed to occur within 60 seconds (check your network connectivity)\n\
+             TLS Error: TLS handshake failed\n"
+        ));
+        assert!(unreachable("TCP: connect to [AF_INET]203.0.113.1:443 failed: Connection timed out\n"));
+        assert!(unreachable("RESOLVE: Cannot resolve host address: vpn.example.com:1194 (Name or service not known)\n"));
+    }
+
+    #[test]
+    fn authentication_failures_are_not_unreachable() {
+        assert!(!unreachable("AUTH: Received control message: AUTH_FAILED\nSIGTERM[soft,auth-failure] received\n"));
+        assert!(!unreachable(
+            "TLS Error: TLS handshake failed\nAUTH: Received control message: AUTH_FAILED\n"
+        ));
+        assert!(!unreachable("Options error: Unrecognized option or missing parameter(s)\n"));
+    }
+}
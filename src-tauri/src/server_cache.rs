@@ -0,0 +1,88 @@
+use crate::api::{ApiClient, Server, ServerListFetch};
+use crate::storage::{FileMode, Storage};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerCache {
+    pub servers: Vec<Server>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub fetched_at: DateTime<Utc>,
+}
+
+impl ServerCache {
+    fn path() -> PathBuf {
+        crate::paths::cache_dir().join("servers.json")
+    }
+
+    pub fn load() -> Option<Self> {
+        let content = fs::read_to_string(Self::path()).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path();
+        if let Some(parent) = path.parent() {
+            Storage::create_private_dir(parent)?;
+        }
+        Storage::write_atomic(&path, serde_json::to_string_pretty(self)?.as_bytes(), FileMode::Public)
+            .context("Failed to save server cache")?;
+        Ok(())
+    }
+
+    pub fn is_fresh(&self, ttl_secs: u64) -> bool {
+        (Utc::now() - self.fetched_at).num_seconds() < ttl_secs as i64
+    }
+}
+
+/// Fetches the server list, sending the cached validators so an unchanged
+/// list costs a 304 instead of a full download.
+pub async fn revalidate(client: &ApiClient, cached: Option<ServerCache>) -> Result<ServerCache> {
+    let fetch = client
+        .list_servers_conditional(
+            cached.as_ref().and_then(|c| c.etag.as_deref()),
+            cached.as_ref().and_then(|c| c.last_modified.as_deref()),
+        )
+        .await?;
+
+    let cache = match fetch {
+        ServerListFetch::NotModified => {
+            let mut cache = cached
+                .ok_or_else(|| anyhow::anyhow!("Server list not modified but nothing is cached"))?;
+            cache.fetched_at = Utc::now();
+            cache
+        }
+        ServerListFetch::Fresh {
+            servers,
+            etag,
+            last_modified,
+        } => ServerCache {
+            servers,
+            etag,
+            last_modified,
+            fetched_at: Utc::now(),
+        },
+    };
+
+    cache.save()?;
+    Ok(cache)
+}
+
+/// Returns a usable server list for internal callers, preferring a fresh
+/// cache, then the network, then whatever stale copy is on disk.
+pub async fn current_servers(client: &ApiClient, ttl_secs: u64) -> Result<Vec<Server>> {
+    let cached = ServerCache::load();
+
+    if let Some(cache) = cached.as_ref().filter(|c| c.is_fresh(ttl_secs)) {
+        return Ok(cache.servers.clone());
+    }
+
+    match revalidate(client, cached.clone()).await {
+        Ok(cache) => Ok(cache.servers),
+        Err(e) => cached.map(|c| c.servers).ok_or(e),
+    }
+}
@@ -0,0 +1,313 @@
+use crate::policy::Policy;
+use crate::storage::{FileMode, Storage};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use tokio::sync::watch;
+
+pub const CURRENT_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub schema_version: u32,
+    /// Overrides the bundled or system OpenVPN binary.
+    pub openvpn_path: Option<String>,
+    pub connect_wait_secs: u64,
+    pub status_poll_interval_secs: u64,
+    pub log_lines: usize,
+    /// Keeps the tunnel device and keys across reconnects (`--persist-tun
+    /// --persist-key`).
+    pub persist_tun: bool,
+    /// Blocks traffic outside the tunnel with firewall rules while connected
+    /// or reconnecting, so nothing leaks while the tunnel is down.
+    pub kill_switch: bool,
+    /// Overrides the portal URL, for self-hosted deployments.
+    pub api_base_url: Option<String>,
+    pub api_timeout_secs: u64,
+    pub api_connect_timeout_secs: u64,
+    pub proxy: Option<String>,
+    pub sync_enabled: bool,
+    pub sync_interval_minutes: u64,
+    pub server_cache_ttl_secs: u64,
+    pub probe_timeout_ms: u64,
+    pub preferred_region: Option<String>,
+    pub expiry_warning_days: u64,
+    /// Retries over TCP when a UDP connection does not complete its handshake.
+    pub tcp_fallback: bool,
+    pub tcp_fallback_timeout_secs: u64,
+    /// Measures latency and packet loss through the tunnel while connected.
+    pub quality_monitor: bool,
+    pub quality_interval_secs: u64,
+    pub quality_max_rtt_ms: u64,
+    pub quality_max_loss_percent: u64,
+    /// Moves to the next-best server when quality stays below the limits
+    /// for `auto_switch_after_secs`.
+    pub auto_switch_server: bool,
+    pub auto_switch_after_secs: u64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            schema_version: CURRENT_SCHEMA_VERSION,
+            openvpn_path: None,
+            connect_wait_secs: 2,
+            status_poll_interval_secs: 3,
+            log_lines: 100,
+            persist_tun: false,
+            kill_switch: false,
+            api_base_url: None,
+            api_timeout_secs: 20,
+            api_connect_timeout_secs: 10,
+            proxy: None,
+            sync_enabled: true,
+            sync_interval_minutes: 30,
+            server_cache_ttl_secs: 600,
+            probe_timeout_ms: 3000,
+            preferred_region: None,
+            expiry_warning_days: 7,
+            tcp_fallback: false,
+            tcp_fallback_timeout_secs: 15,
+            quality_monitor: true,
+            quality_interval_secs: 30,
+            quality_max_rtt_ms: 400,
+            quality_max_loss_percent: 20,
+            auto_switch_server: false,
+            auto_switch_after_secs: 120,
+        }
+    }
+}
+
+impl Settings {
+    fn path() -> PathBuf {
+        crate::paths::config_dir().join("settings.json")
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let in_range = |name: &str, value: u64, min: u64, max: u64| -> Result<()> {
+            if value < min || value > max {
+                return Err(anyhow::anyhow!("{} must be between {} and {}", name, min, max));
+            }
+            Ok(())
+        };
+
+        in_range("connect_wait_secs", self.connect_wait_secs, 1, 60)?;
+        in_range("status_poll_interval_secs", self.status_poll_interval_secs, 1, 60)?;
+        in_range("log_lines", self.log_lines as u64, 10, 10_000)?;
+        in_range("api_timeout_secs", self.api_timeout_secs, 1, 300)?;
+        in_range("api_connect_timeout_secs", self.api_connect_timeout_secs, 1, 120)?;
+        in_range("sync_interval_minutes", self.sync_interval_minutes, 5, 24 * 60)?;
+        in_range("server_cache_ttl_secs", self.server_cache_ttl_secs, 0, 7 * 24 * 3600)?;
+        in_range("probe_timeout_ms", self.probe_timeout_ms, 100, 30_000)?;
+        in_range("expiry_warning_days", self.expiry_warning_days, 1, 90)?;
+        in_range("tcp_fallback_timeout_secs", self.tcp_fallback_timeout_secs, 5, 120)?;
+        in_range("quality_interval_secs", self.quality_interval_secs, 10, 600)?;
+        in_range("quality_max_rtt_ms", self.quality_max_rtt_ms, 50, 5000)?;
+        in_range("quality_max_loss_percent", self.quality_max_loss_percent, 1, 100)?;
+        in_range("auto_switch_after_secs", self.auto_switch_after_secs, 30, 3600)?;
+
+        if let Some(path) = &self.openvpn_path {
+            if !std::path::Path::new(path).is_absolute() {
+                return Err(anyhow::anyhow!("openvpn_path must be an absolute path"));
+            }
+        }
+
+        if let Some(url) = &self.api_base_url {
+            if !url.starts_with("https://") {
+                return Err(anyhow::anyhow!("api_base_url must be an https:// URL"));
+            }
+        }
+
+        if let Some(proxy) = &self.proxy {
+            let scheme = proxy.split("://").next().unwrap_or_default();
+            if !proxy.contains("://") || !["http", "https", "socks5", "socks5h"].contains(&scheme) {
+                return Err(anyhow::anyhow!("proxy must be an http://, https:// or socks5:// URL"));
+            }
+            // The same check the HTTP client makes, so a saved proxy can always be used
+            reqwest::Proxy::all(proxy.as_str()).map_err(|e| anyhow::anyhow!("proxy is not a valid URL: {}", e))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn load() -> Result<Self> {
+        let path = Self::path();
+
+        if !path.exists() {
+            let settings = Self::default();
+            settings.save()?;
+            return Ok(settings);
+        }
+
+        let content = fs::read_to_string(&path).context("Failed to read settings")?;
+        Self::parse(&content)
+    }
+
+    /// Parses, migrates and validates the contents of a settings file.
+    pub fn parse(content: &str) -> Result<Self> {
+        let value: Value = serde_json::from_str(content).context("Settings file is not valid JSON")?;
+        let migrated = migrate(value)?;
+        let settings: Settings = serde_json::from_value(migrated).context("Invalid settings")?;
+        settings.validate()?;
+
+        Ok(settings)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path();
+        if let Some(parent) = path.parent() {
+            Storage::create_private_dir(parent)?;
+        }
+        // The proxy URL may carry credentials
+        Storage::write_atomic(&path, serde_json::to_string_pretty(self)?.as_bytes(), FileMode::Secret)
+            .context("Failed to save settings")
+    }
+
+    /// Applies a partial update given as a JSON object of setting keys.
+    pub fn patched(&self, patch: Value) -> Result<Self> {
+        let Value::Object(patch) = patch else {
+            return Err(anyhow::anyhow!("Settings update must be an object"));
+        };
+
+        let mut value = serde_json::to_value(self)?;
+        let fields = value
+            .as_object_mut()
+            .ok_or_else(|| anyhow::anyhow!("Settings did not serialize to an object"))?;
+
+        for (key, new_value) in patch {
+            if key == "schema_version" {
+                return Err(anyhow::anyhow!("schema_version cannot be changed"));
+            }
+            if !fields.contains_key(&key) {
+                return Err(anyhow::anyhow!("Unknown setting: {}", key));
+            }
+            fields.insert(key, new_value);
+        }
+
+        let settings: Settings = serde_json::from_value(value).context("Invalid settings value")?;
+        settings.validate()?;
+        Ok(settings)
+    }
+}
+
+/// Upgrade steps: `MIGRATIONS[i]` takes a document from schema version
+/// `i + 1` to `i + 2`.
+const MIGRATIONS: &[fn(&mut Value)] = &[];
+
+/// Upgrades a stored settings document one schema version at a time.
+fn migrate(mut value: Value) -> Result<Value> {
+    // Settings files have carried a version since they were introduced
+    let version = value
+        .get("schema_version")
+        .map(|v| v.as_u64().ok_or_else(|| anyhow::anyhow!("schema_version must be a number")))
+        .transpose()?
+        .unwrap_or(CURRENT_SCHEMA_VERSION as u64);
+
+    if version > CURRENT_SCHEMA_VERSION as u64 {
+        return Err(anyhow::anyhow!(
+            "Settings were written by a newer version (schema {})",
+            version
+        ));
+    }
+    if version < 1 {
+        return Err(anyhow::anyhow!("Unsupported settings schema version {}", version));
+    }
+
+    for (step, migration) in MIGRATIONS.iter().enumerate().skip(version as usize - 1) {
+        migration(&mut value);
+        value["schema_version"] = Value::from(step as u32 + 2);
+    }
+
+    Ok(value)
+}
+
+/// Holds the live settings and notifies subscribers when they change. The
+/// user's own settings are kept apart from the effective ones so policy
+/// values are never written into the user's settings file.
+pub struct SettingsStore {
+    user: RwLock<Settings>,
+    current: RwLock<Settings>,
+    policy: Policy,
+    changes: watch::Sender<Settings>,
+}
+
+impl SettingsStore {
+    pub fn new(user: Settings, policy: Policy) -> Result<Self> {
+        let current = policy.apply(&user)?;
+        let (changes, _) = watch::channel(current.clone());
+        Ok(Self {
+            user: RwLock::new(user),
+            current: RwLock::new(current),
+            policy,
+            changes,
+        })
+    }
+
+    /// The effective settings, with the policy applied.
+    pub fn get(&self) -> Settings {
+        self.current.read().unwrap().clone()
+    }
+
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    pub fn update(&self, patch: Value) -> Result<Settings> {
+        if let Some(key) = patch
+            .as_object()
+            .and_then(|fields| fields.keys().find(|key| self.policy.is_locked(key)))
+        {
+            return Err(anyhow::anyhow!("{} is set by your administrator", key));
+        }
+
+        let user = self.user.read().unwrap().patched(patch)?;
+        let settings = self.policy.apply(&user)?;
+        user.save()?;
+
+        *self.user.write().unwrap() = user;
+        *self.current.write().unwrap() = settings.clone();
+        self.changes.send_replace(settings.clone());
+
+        Ok(settings)
+    }
+
+    /// Picks up a settings file replaced on disk, e.g. by a restore.
+    pub fn reload(&self) -> Result<Settings> {
+        let user = Settings::load()?;
+        let settings = self.policy.apply(&user)?;
+
+        *self.user.write().unwrap() = user;
+        *self.current.write().unwrap() = settings.clone();
+        self.changes.send_replace(settings.clone());
+
+        Ok(settings)
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<Settings> {
+        self.changes.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unsupported_versions_are_errors() {
+        assert!(Settings::parse(r#"{"schema_version": 0}"#).is_err());
+        assert!(Settings::parse(r#"{"schema_version": 99}"#).is_err());
+        assert!(Settings::parse(r#"{"schema_version": "1"}"#).is_err());
+    }
+
+    #[test]
+    fn fills_in_defaults() {
+        let settings = Settings::parse(r#"{"schema_version": 1, "log_lines": 200}"#).unwrap();
+        assert_eq!(settings.log_lines, 200);
+        assert_eq!(settings.connect_wait_secs, Settings::default().connect_wait_secs);
+        assert_eq!(Settings::parse("{}").unwrap(), Settings::default());
+    }
+}
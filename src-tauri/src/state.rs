@@ -1,21 +1,36 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::probe::ProbeResult;
+use crate::quality::ConnectionQuality;
+use crate::subnets::SubnetConflict;
 
 pub struct AppState {
     api_key: Arc<RwLock<Option<String>>>,
     connection: Arc<RwLock<Option<VpnConnection>>>,
+    /// Bumped by every connect or disconnect the user starts.
+    generation: Arc<AtomicU64>,
+    probes: Arc<RwLock<HashMap<String, (Instant, ProbeResult)>>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VpnConnection {
     pub config_name: String,
     pub server: String,
+    /// The profile remote the tunnel reached, when it could be told apart.
+    pub remote: Option<String>,
     pub server_ip: String,
     pub server_port: u16,
     pub protocol: String,
     pub private_ipv4: String,
     pub private_ipv6: String,
     pub connected_at: String,
+    pub quality: Option<ConnectionQuality>,
+    /// Overlaps between the VPN's networks and local ones, found once connected.
+    pub subnet_conflicts: Vec<SubnetConflict>,
     pub bytes_sent: u64,
     pub bytes_received: u64,
     pub speed_up: u64,   
@@ -27,6 +42,8 @@ impl AppState {
         Self {
             api_key: Arc::new(RwLock::new(None)),
             connection: Arc::new(RwLock::new(None)),
+            generation: Arc::new(AtomicU64::new(0)),
+            probes: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
@@ -45,4 +62,66 @@ impl AppState {
     pub fn get_connection(&self) -> Option<VpnConnection> {
         self.connection.read().unwrap().clone()
     }
-}
\ No newline at end of file
+
+    /// Changes the current connection if it is still the one started at
+    /// `connected_at`. Returns whether it was.
+    pub fn update_connection(&self, connected_at: &str, update: impl FnOnce(&mut VpnConnection)) -> bool {
+        match self.connection.write().unwrap().as_mut() {
+            Some(conn) if conn.connected_at == connected_at => {
+                update(conn);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Records that the user started connecting or disconnecting, so
+    /// background work begun before it leaves the connection alone.
+    pub fn next_generation(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Takes over the connection for background work, unless the user
+    /// changed it since `generation`. Returns the generation now owned.
+    pub fn claim_generation(&self, generation: u64) -> Option<u64> {
+        self.generation
+            .compare_exchange(generation, generation + 1, Ordering::SeqCst, Ordering::SeqCst)
+            .ok()
+            .map(|g| g + 1)
+    }
+
+    pub fn cache_probe(&self, result: ProbeResult) {
+        self.probes
+            .write()
+            .unwrap()
+            .insert(result.code.clone(), (Instant::now(), result));
+    }
+
+    pub fn get_cached_probe(&self, code: &str, ttl: Duration) -> Option<ProbeResult> {
+        self.probes
+            .read()
+            .unwrap()
+            .get(code)
+            .filter(|(at, _)| at.elapsed() < ttl)
+            .map(|(_, result)| result.clone())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_changes_win_over_background_claims() {
+        let state = AppState::new();
+        let seen = state.generation();
+        let ours = state.claim_generation(seen).unwrap();
+        assert!(state.claim_generation(seen).is_none());
+
+        state.next_generation();
+        assert!(state.claim_generation(ours).is_none());
+    }
+}
@@ -1,44 +1,147 @@
 use anyhow::{Context, Result};
 use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+static TEMP_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+// Files that hold keys, passwords or tokens and must only be readable by the owner
+const SECRET_EXTENSIONS: &[&str] = &["ovpn", "creds", "pw"];
+const SECRET_FILES: &[&str] = &["api_key.txt", "settings.json"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileMode {
+    Public,
+    Secret,
+}
 
 pub struct Storage;
 
 impl Storage {
-    #[cfg(target_os = "windows")]
-    fn get_storage_dir() -> PathBuf {
-        let appdata = std::env::var("APPDATA").unwrap_or_else(|_| ".".to_string());
-        PathBuf::from(appdata).join("RBW-Tech OVPN")
+    /// Creates `dir` and its parents, restricting `dir` itself to the owner.
+    pub fn create_private_dir(dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+        }
+
+        Ok(())
     }
 
-    #[cfg(target_os = "linux")]
-    fn get_storage_dir() -> PathBuf {
-        let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
-        PathBuf::from(home).join(".config").join("RBW-Tech OVPN")
+    /// Writes through a temp file in the same directory, fsyncs it and renames
+    /// it over `path`, so readers see either the old or the new content.
+    pub fn write_atomic(path: &Path, contents: &[u8], mode: FileMode) -> Result<()> {
+        let dir = path
+            .parent()
+            .ok_or_else(|| anyhow::anyhow!("Invalid path: {}", path.display()))?;
+        let file_name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| anyhow::anyhow!("Invalid path: {}", path.display()))?;
+
+        let temp_path = dir.join(format!(
+            ".{}.tmp-{}-{}",
+            file_name,
+            std::process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let result = Self::write_temp(&temp_path, contents, mode)
+            .and_then(|_| fs::rename(&temp_path, path).map_err(Into::into));
+
+        if result.is_err() {
+            fs::remove_file(&temp_path).ok();
+        }
+        result.with_context(|| format!("Failed to write {}", path.display()))?;
+
+        // Persist the rename itself
+        #[cfg(unix)]
+        if let Ok(dir) = fs::File::open(dir) {
+            dir.sync_all().ok();
+        }
+
+        Ok(())
     }
 
-    #[cfg(target_os = "macos")]
-    fn get_storage_dir() -> PathBuf {
-        let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
-        PathBuf::from(home)
-            .join("Library")
-            .join("Application Support")
-            .join("RBW-Tech OVPN")
+    fn write_temp(temp_path: &Path, contents: &[u8], mode: FileMode) -> Result<()> {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(match mode {
+                FileMode::Secret => 0o600,
+                FileMode::Public => 0o644,
+            });
+        }
+        #[cfg(not(unix))]
+        let _ = mode;
+
+        let mut file = options.open(temp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        Ok(())
+    }
+
+    pub fn is_secret_file(path: &Path) -> bool {
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+        SECRET_FILES.contains(&name) || SECRET_EXTENSIONS.contains(&ext)
+    }
+
+    /// Tightens modes left behind by older versions, which wrote everything
+    /// with the default umask: the directory becomes 0700, secrets 0600.
+    #[cfg(unix)]
+    pub fn repair_permissions(dir: &Path) -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        if !dir.is_dir() {
+            return Ok(());
+        }
+
+        let restrict = |path: &Path, mode: u32| -> Result<()> {
+            let current = fs::metadata(path)?.permissions().mode() & 0o777;
+            if current & 0o077 != 0 {
+                fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+            }
+            Ok(())
+        };
+
+        restrict(dir, 0o700)?;
+        for entry in fs::read_dir(dir)?.flatten() {
+            let path = entry.path();
+            if path.is_file() && Self::is_secret_file(&path) {
+                restrict(&path, 0o600)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn repair_permissions(_dir: &Path) -> Result<()> {
+        Ok(())
     }
 
     pub fn save_api_key(api_key: &str) -> Result<()> {
-        let storage_dir = Self::get_storage_dir();
-        fs::create_dir_all(&storage_dir)?;
+        let storage_dir = crate::paths::config_dir();
+        Self::create_private_dir(&storage_dir)?;
         
         let key_file = storage_dir.join("api_key.txt");
-        fs::write(&key_file, api_key)
+        Self::write_atomic(&key_file, api_key.as_bytes(), FileMode::Secret)
             .context("Failed to save API key")?;
         
         Ok(())
     }
 
     pub fn load_api_key() -> Result<String> {
-        let storage_dir = Self::get_storage_dir();
+        let storage_dir = crate::paths::config_dir();
         let key_file = storage_dir.join("api_key.txt");
         
         if !key_file.exists() {
@@ -52,7 +155,7 @@ impl Storage {
     }
 
     pub fn delete_api_key() -> Result<()> {
-        let storage_dir = Self::get_storage_dir();
+        let storage_dir = crate::paths::config_dir();
         let key_file = storage_dir.join("api_key.txt");
         
         if key_file.exists() {
@@ -61,4 +164,4 @@ impl Storage {
         
         Ok(())
     }
-}
\ No newline at end of file
+}
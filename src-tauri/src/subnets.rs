@@ -0,0 +1,350 @@
+use crate::metadata::ProfileMeta;
+use crate::profile::Profile;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::Ipv4Addr;
+
+/// An IPv4 network. IPv6 routes are not checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subnet {
+    network: u32,
+    prefix: u8,
+}
+
+fn mask(prefix: u8) -> u32 {
+    u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0)
+}
+
+impl Subnet {
+    fn new(addr: Ipv4Addr, prefix: u8) -> Self {
+        Self {
+            network: u32::from(addr) & mask(prefix),
+            prefix,
+        }
+    }
+
+    fn with_mask(addr: Ipv4Addr, netmask: Ipv4Addr) -> Option<Self> {
+        let bits = u32::from(netmask);
+        let prefix = bits.leading_ones() as u8;
+        // Only contiguous masks describe a network
+        (bits == mask(prefix)).then(|| Self::new(addr, prefix))
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        let (addr, prefix) = s.split_once('/')?;
+        let prefix: u8 = prefix.parse().ok().filter(|p| *p <= 32)?;
+        Some(Self::new(addr.parse().ok()?, prefix))
+    }
+
+    fn overlaps(&self, other: &Subnet) -> bool {
+        (self.network ^ other.network) & mask(self.prefix.min(other.prefix)) == 0
+    }
+}
+
+impl fmt::Display for Subnet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", Ipv4Addr::from(self.network), self.prefix)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NetworkSource {
+    /// A `route` directive in the profile.
+    Profile,
+    /// A route the server pushed.
+    Pushed,
+    /// The range the tunnel interface gets its address from.
+    Tunnel,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubnetConflict {
+    pub vpn_network: String,
+    pub source: NetworkSource,
+    pub local_network: String,
+    pub interface: String,
+    pub guidance: String,
+}
+
+/// Networks reachable through local interfaces, from the kernel routing
+/// table, leaving out the default route and `exclude` (the tunnel itself).
+#[cfg(target_os = "linux")]
+fn local_routes(exclude: Option<&str>) -> Vec<(Subnet, String)> {
+    let hex_addr = |field: &str| u32::from_str_radix(field, 16).ok().map(|v| Ipv4Addr::from(v.to_le_bytes()));
+
+    let Ok(table) = std::fs::read_to_string("/proc/net/route") else {
+        return Vec::new();
+    };
+    let mut routes: Vec<(Subnet, String)> = table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let interface = *fields.first()?;
+            let flags = u32::from_str_radix(fields.get(3)?, 16).ok()?;
+            // RTF_UP
+            if flags & 1 == 0 || Some(interface) == exclude {
+                return None;
+            }
+            let subnet = Subnet::with_mask(hex_addr(fields.get(1)?)?, hex_addr(fields.get(7)?)?)?;
+            (subnet.prefix > 0).then(|| (subnet, interface.to_string()))
+        })
+        .collect();
+    routes.dedup();
+    routes
+}
+
+#[cfg(not(target_os = "linux"))]
+fn local_routes(_exclude: Option<&str>) -> Vec<(Subnet, String)> {
+    Vec::new()
+}
+
+/// The network of an `ifconfig <local> <netmask|peer>` pair. With net30 or
+/// point-to-point topology the second address is the peer, not a mask.
+fn ifconfig_network(local: &str, second: &str) -> Option<Subnet> {
+    let local: Ipv4Addr = local.parse().ok()?;
+    let second: Ipv4Addr = second.parse().ok()?;
+    if second.octets()[0] == 255 {
+        Subnet::with_mask(local, second)
+    } else {
+        Some(Subnet::new(local, 30))
+    }
+}
+
+fn route_network(args: &[&str]) -> Option<Subnet> {
+    let network: Ipv4Addr = args.first()?.parse().ok()?;
+    let netmask = match args.get(1) {
+        // OpenVPN keywords like `default` stand for the host mask
+        Some(m) => m.parse().unwrap_or(Ipv4Addr::BROADCAST),
+        None => Ipv4Addr::BROADCAST,
+    };
+    Subnet::with_mask(network, netmask)
+}
+
+fn profile_networks(content: &str) -> Vec<(Subnet, NetworkSource)> {
+    let profile = Profile::parse(content);
+    let routes = profile
+        .get_all("route")
+        .into_iter()
+        .filter_map(|a| route_network(&a.iter().map(String::as_str).collect::<Vec<_>>()))
+        .map(|s| (s, NetworkSource::Profile));
+    let tunnel = profile
+        .get("ifconfig")
+        .and_then(|a| ifconfig_network(a.first()?, a.get(1)?))
+        .map(|s| (s, NetworkSource::Tunnel));
+    routes.chain(tunnel).collect()
+}
+
+/// Routes and the tunnel network from the last `PUSH_REPLY` in the log.
+fn pushed_networks(log: &str) -> Vec<(Subnet, NetworkSource)> {
+    let Some(reply) = log.lines().rev().find_map(|line| line.split_once("PUSH_REPLY,").map(|(_, r)| r)) else {
+        return Vec::new();
+    };
+    reply
+        .trim_end_matches(['\'', ' '])
+        .split(',')
+        .filter_map(|option| {
+            let mut parts = option.split_whitespace();
+            let name = parts.next()?;
+            let args: Vec<&str> = parts.collect();
+            match name {
+                "route" => Some((route_network(&args)?, NetworkSource::Pushed)),
+                "ifconfig" => Some((ifconfig_network(args.first()?, args.get(1)?)?, NetworkSource::Tunnel)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+fn guidance(vpn: &Subnet, source: NetworkSource, local: &Subnet, interface: &str) -> String {
+    if source == NetworkSource::Tunnel {
+        return format!(
+            "The VPN assigns addresses from {}, which overlaps {} on {}. Hosts on that local network are \
+             unreachable while connected. Change your router's LAN range, or ask the VPN administrator to use \
+             another tunnel subnet.",
+            vpn, local, interface
+        );
+    }
+
+    let fix = match source {
+        NetworkSource::Pushed => format!(
+            "ask the VPN administrator to stop pushing it, or add `pull-filter ignore \"route {} {}\"` to the \
+             profile",
+            Ipv4Addr::from(vpn.network),
+            Ipv4Addr::from(mask(vpn.prefix))
+        ),
+        _ => "remove the route from the profile".to_string(),
+    };
+    match vpn.prefix.cmp(&local.prefix) {
+        std::cmp::Ordering::Greater => format!(
+            "Traffic to {} goes through the VPN instead of {}, so local hosts in that range are unreachable \
+             while connected. If you need them, {}.",
+            vpn, interface, fix
+        ),
+        std::cmp::Ordering::Less => format!(
+            "Traffic to {} stays on {} because the local route is more specific, so VPN hosts in that range \
+             are unreachable. Change your router's LAN range to reach them.",
+            local, interface
+        ),
+        std::cmp::Ordering::Equal => format!(
+            "Both the VPN and {} route {}; which one is used depends on route metrics, so connections may go \
+             to either. Change your router's LAN range, or {}.",
+            interface, vpn, fix
+        ),
+    }
+}
+
+fn conflicts(vpn: &[(Subnet, NetworkSource)], local: &[(Subnet, String)]) -> Vec<SubnetConflict> {
+    let mut found = Vec::new();
+    for (vpn_net, source) in vpn {
+        for (local_net, interface) in local {
+            if vpn_net.overlaps(local_net) {
+                found.push(SubnetConflict {
+                    vpn_network: vpn_net.to_string(),
+                    source: *source,
+                    local_network: local_net.to_string(),
+                    interface: interface.clone(),
+                    guidance: guidance(vpn_net, *source, local_net, interface),
+                });
+            }
+        }
+    }
+    found
+}
+
+/// Checks the profile's routes, and the networks the server pushed the last
+/// time it connected, against the local routing table.
+pub fn check_before(content: &str, meta: &ProfileMeta) -> Vec<SubnetConflict> {
+    let remembered = |networks: &[String], source| {
+        networks
+            .iter()
+            .filter_map(|n| Subnet::parse(n))
+            .map(move |s| (s, source))
+            .collect::<Vec<_>>()
+    };
+
+    let mut vpn = profile_networks(content);
+    vpn.extend(remembered(&meta.pushed_networks, NetworkSource::Pushed));
+    vpn.extend(remembered(meta.tunnel_network.as_slice(), NetworkSource::Tunnel));
+    conflicts(&vpn, &local_routes(None))
+}
+
+/// Checks a running connection using the options the server pushed, as
+/// logged, and records them in `meta` for [`check_before`].
+pub fn check_after(content: &str, log: &str, meta: &mut ProfileMeta) -> Vec<SubnetConflict> {
+    let pushed = pushed_networks(log);
+    let of = |source| {
+        pushed
+            .iter()
+            .filter(move |(_, s)| *s == source)
+            .map(|(network, _)| network.to_string())
+    };
+    meta.pushed_networks = of(NetworkSource::Pushed).collect();
+    meta.tunnel_network = of(NetworkSource::Tunnel).next();
+
+    let device = Regex::new(r"TUN/TAP device (\S+) opened")
+        .unwrap()
+        .captures_iter(log)
+        .last()
+        .map(|caps| caps[1].to_string());
+
+    let mut vpn = profile_networks(content);
+    vpn.extend(pushed);
+    conflicts(&vpn, &local_routes(device.as_deref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subnet(s: &str) -> Subnet {
+        Subnet::parse(s).unwrap()
+    }
+
+    #[test]
+    fn overlapping_subnets() {
+        let cases = [
+            ("10.0.0.0/8", "10.1.2.0/24", true),
+            ("10.1.2.0/24", "10.0.0.0/8", true),
+            ("192.168.1.0/24", "192.168.1.0/24", true),
+            ("192.168.1.0/24", "192.168.2.0/24", false),
+            ("192.168.1.128/25", "192.168.1.0/25", false),
+            ("0.0.0.0/0", "172.16.0.0/12", true),
+            ("10.8.0.4/30", "10.8.0.8/30", false),
+        ];
+        for (a, b, expected) in cases {
+            assert_eq!(subnet(a).overlaps(&subnet(b)), expected, "{} and {}", a, b);
+        }
+    }
+
+    #[test]
+    fn netmasks() {
+        let cases = [
+            ("255.255.255.0", Some("192.168.1.0/24")),
+            ("255.255.255.255", Some("192.168.1.77/32")),
+            ("0.0.0.0", Some("0.0.0.0/0")),
+            ("255.0.255.0", None),
+            ("255.255.255.1", None),
+            ("0.255.255.255", None),
+        ];
+        for (netmask, expected) in cases {
+            let subnet = Subnet::with_mask(Ipv4Addr::new(192, 168, 1, 77), netmask.parse().unwrap());
+            assert_eq!(subnet.map(|s| s.to_string()).as_deref(), expected, "{}", netmask);
+        }
+    }
+
+    #[test]
+    fn tunnel_networks() {
+        let cases = [
+            // net30: the second address is the peer
+            ("10.8.0.6", "10.8.0.5", Some("10.8.0.4/30")),
+            // subnet topology: the second address is the netmask
+            ("10.8.0.2", "255.255.255.0", Some("10.8.0.0/24")),
+            ("10.8.0.2", "255.255.0.0", Some("10.8.0.0/16")),
+            ("10.8.0.2", "255.0.255.0", None),
+            ("10.8.0.2", "peer", None),
+        ];
+        for (local, second, expected) in cases {
+            let network = ifconfig_network(local, second);
+            assert_eq!(network.map(|s| s.to_string()).as_deref(), expected, "{} {}", local, second);
+        }
+    }
+
+    #[test]
+    fn route_networks() {
+        let cases: [(&[&str], Option<&str>); 6] = [
+            (&["10.10.0.0", "255.255.0.0"], Some("10.10.0.0/16")),
+            (&["10.10.0.0", "255.255.0.0", "vpn_gateway"], Some("10.10.0.0/16")),
+            (&["10.10.0.0", "255.255.0.0", "vpn_gateway", "100"], Some("10.10.0.0/16")),
+            (&["192.168.5.10"], Some("192.168.5.10/32")),
+            (&["192.168.5.10", "default"], Some("192.168.5.10/32")),
+            (&["vpn_gateway"], None),
+        ];
+        for (args, expected) in cases {
+            assert_eq!(route_network(args).map(|s| s.to_string()).as_deref(), expected, "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn reads_the_last_push_reply() {
+        let log = "\
+2024-05-02 09:14:01 PUSH: Received control message: 'PUSH_REPLY,route 172.20.0.0 255.255.0.0,topology net30,ifconfig 10.9.0.6 10.9.0.5'
+2024-05-02 09:20:11 SIGUSR1[soft,ping-restart] received, process restarting
+2024-05-02 09:20:14 PUSH: Received control message: 'PUSH_REPLY,redirect-gateway def1,route 10.10.0.0 255.255.0.0,route 192.168.50.0 255.255.255.0 vpn_gateway,route-gateway 10.8.0.1,topology subnet,ping 10,ping-restart 120,ifconfig 10.8.0.2 255.255.255.0,peer-id 0,cipher AES-256-GCM'
+2024-05-02 09:20:14 TUN/TAP device tun0 opened
+";
+        let networks: Vec<(String, NetworkSource)> =
+            pushed_networks(log).into_iter().map(|(s, source)| (s.to_string(), source)).collect();
+        assert_eq!(
+            networks,
+            [
+                ("10.10.0.0/16".to_string(), NetworkSource::Pushed),
+                ("192.168.50.0/24".to_string(), NetworkSource::Pushed),
+                ("10.8.0.0/24".to_string(), NetworkSource::Tunnel),
+            ]
+        );
+        assert!(pushed_networks("2024-05-02 09:20:14 Initialization Sequence Completed\n").is_empty());
+    }
+}
@@ -0,0 +1,512 @@
+use crate::api::{ApiClient, RemoteConfig, RevokeTarget};
+use crate::expiry::profile_expiry;
+use crate::history::ChangeReason;
+use crate::metadata::{content_hash, ProfileMeta, ProfileSource, ProfileStatus};
+use crate::openvpn::OpenVpnManager;
+use crate::pending::PendingAction;
+use crate::profile_name::ProfileName;
+use crate::settings::SettingsStore;
+use crate::state::AppState;
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+
+lazy_static::lazy_static! {
+    static ref SYNC_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::new(());
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncReport {
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub unchanged: Vec<String>,
+    pub revoked: Vec<String>,
+    pub conflicts: Vec<String>,
+    /// Portal configs left out, with the reason.
+    pub skipped: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+fn remote_status(remote: &RemoteConfig) -> ProfileStatus {
+    match remote.status.as_str() {
+        "revoked" | "disabled" | "deleted" => ProfileStatus::Revoked,
+        "expired" => ProfileStatus::Expired,
+        _ => ProfileStatus::Active,
+    }
+}
+
+fn synced_meta(remote: &RemoteConfig, content: &str) -> ProfileMeta {
+    let account_expiry = remote
+        .expires_at
+        .as_deref()
+        .and_then(|e| chrono::DateTime::parse_from_rfc3339(e).ok())
+        .map(|e| e.with_timezone(&chrono::Utc));
+
+    ProfileMeta {
+        source: ProfileSource::Synced,
+        status: ProfileStatus::Active,
+        remote_id: Some(remote.id.clone()),
+        server_code: Some(remote.server_code.clone()),
+        username: None,
+        synced_hash: Some(content_hash(content)),
+        remote_hash: None,
+        synced_at: Some(chrono::Utc::now().to_rfc3339()),
+        expires_at: profile_expiry(content, account_expiry).map(|e| e.to_rfc3339()),
+        pushed_networks: Vec::new(),
+        tunnel_network: None,
+    }
+}
+
+/// Brings local profiles in line with the portal. Profiles are matched by
+/// remote ID first, then by name and content hash. A profile edited locally
+/// while the portal copy also changed is left alone and flagged as a conflict.
+pub async fn sync_configs(
+    client: &ApiClient,
+    manager: &OpenVpnManager,
+    api_key: &str,
+) -> Result<SyncReport> {
+    let _guard = SYNC_LOCK.lock().await;
+
+    // Configs deleted locally whose revocation is still queued stay deleted
+    let revoking: HashSet<String> = crate::pending::load()
+        .into_iter()
+        .filter_map(|op| match op.action {
+            PendingAction::Revoke {
+                target: RevokeTarget::Config { id },
+            } => Some(id),
+            _ => None,
+        })
+        .collect();
+    let remotes: Vec<RemoteConfig> = client
+        .list_remote_configs(api_key)
+        .await?
+        .into_iter()
+        .filter(|r| !revoking.contains(&r.id))
+        .collect();
+    let mut report = SyncReport::default();
+
+    let by_remote_id = linked_profiles(manager);
+
+    for (remote, name, linked) in pair(&remotes, &by_remote_id, &mut report) {
+        if let Err(e) = sync_one(client, manager, api_key, remote, &name, linked, &mut report).await {
+            report.errors.push(format!("{}: {}", name, e));
+        }
+    }
+
+    // Profiles that disappeared from the portal can no longer authenticate
+    let remote_ids: HashSet<&str> = remotes.iter().map(|r| r.id.as_str()).collect();
+    for (id, (name, mut meta)) in by_remote_id {
+        if !remote_ids.contains(id.as_str()) && meta.status != ProfileStatus::Revoked {
+            meta.status = ProfileStatus::Revoked;
+            manager.save_meta(&name, &meta)?;
+            report.revoked.push(name.to_string());
+        }
+    }
+
+    Ok(report)
+}
+
+fn linked_profiles(manager: &OpenVpnManager) -> HashMap<String, (ProfileName, ProfileMeta)> {
+    manager
+        .list_meta()
+        .into_iter()
+        .filter(|(name, _)| manager.config_exists(name))
+        .filter_map(|(name, meta)| meta.remote_id.clone().map(|id| (id, (name, meta))))
+        .collect()
+}
+
+type Pairing<'a> = (&'a RemoteConfig, ProfileName, Option<(ProfileName, ProfileMeta)>);
+
+/// Pairs each portal config with the local profile it syncs into. A config
+/// is never matched by name to a profile linked to another config; renewing
+/// leaves the superseded config on the portal under the same name. Unlinked
+/// configs whose names collide get a numbered name of their own. Names are
+/// compared case-insensitively, like the filesystems they end up on.
+fn pair<'a>(
+    remotes: &'a [RemoteConfig],
+    by_remote_id: &HashMap<String, (ProfileName, ProfileMeta)>,
+    report: &mut SyncReport,
+) -> Vec<Pairing<'a>> {
+    let linked_keys: HashSet<String> = by_remote_id.values().map(|(name, _)| name.key()).collect();
+    let mut taken = linked_keys.clone();
+
+    let mut pairs = Vec::new();
+    for remote in remotes {
+        if let Some((name, meta)) = by_remote_id.get(&remote.id) {
+            pairs.push((remote, name.clone(), Some((name.clone(), meta.clone()))));
+            continue;
+        }
+
+        let base = match ProfileName::sanitize(&remote.name) {
+            Ok(base) => base,
+            Err(e) => {
+                report.errors.push(format!("{}: {}", remote.name, e));
+                continue;
+            }
+        };
+        if linked_keys.contains(&base.key()) {
+            report.skipped.push(format!(
+                "{}: the profile of that name is linked to another portal config",
+                remote.name
+            ));
+            continue;
+        }
+
+        let name = (1..100)
+            .filter_map(|n| match n {
+                1 => Some(base.clone()),
+                n => ProfileName::sanitize(&format!("{} ({})", base, n)).ok(),
+            })
+            .find(|candidate| !taken.contains(&candidate.key()));
+        match name {
+            Some(name) => {
+                taken.insert(name.key());
+                pairs.push((remote, name, None));
+            }
+            None => report
+                .skipped
+                .push(format!("{}: no free profile name for this config", remote.name)),
+        }
+    }
+    pairs
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Plan {
+    Unchanged,
+    Conflict,
+    Update,
+}
+
+/// What to do with a linked profile given the hash of its local content and
+/// of the portal copy.
+fn plan(meta: &ProfileMeta, local_hash: &str, remote_hash: &str) -> Plan {
+    let locally_edited = meta.synced_hash.as_deref() != Some(local_hash);
+
+    if meta.synced_hash.as_deref() == Some(remote_hash) {
+        Plan::Unchanged
+    } else if locally_edited && local_hash != remote_hash {
+        Plan::Conflict
+    } else {
+        Plan::Update
+    }
+}
+
+fn portal_hash(remote: &RemoteConfig) -> Option<String> {
+    remote.content_hash.as_deref().map(str::to_lowercase)
+}
+
+/// The portal config a renewal created: one with the renewed content that
+/// is not `previous` and not already linked to a local profile. Configs
+/// without a hash on the portal are downloaded to compare.
+pub async fn find_regenerated(
+    client: &ApiClient,
+    manager: &OpenVpnManager,
+    api_key: &str,
+    previous: Option<&str>,
+    content: &str,
+) -> Result<Option<String>> {
+    let hash = content_hash(content);
+    let linked = linked_profiles(manager);
+    let candidates: Vec<RemoteConfig> = client
+        .list_remote_configs(api_key)
+        .await?
+        .into_iter()
+        .filter(|r| Some(r.id.as_str()) != previous && !linked.contains_key(&r.id))
+        .filter(|r| portal_hash(r).is_none_or(|h| h == hash))
+        .collect();
+
+    for remote in candidates {
+        let matches = match portal_hash(&remote) {
+            Some(_) => true,
+            None => content_hash(&client.download_config(api_key, &remote.id).await?) == hash,
+        };
+        if matches {
+            return Ok(Some(remote.id));
+        }
+    }
+    Ok(None)
+}
+
+async fn sync_one(
+    client: &ApiClient,
+    manager: &OpenVpnManager,
+    api_key: &str,
+    remote: &RemoteConfig,
+    name: &ProfileName,
+    linked: Option<(ProfileName, ProfileMeta)>,
+    report: &mut SyncReport,
+) -> Result<()> {
+    let status = remote_status(remote);
+
+    if status != ProfileStatus::Active {
+        if let Some((_, mut meta)) = linked {
+            if meta.status != status {
+                meta.status = status;
+                manager.save_meta(name, &meta)?;
+                report.revoked.push(name.to_string());
+            }
+        }
+        return Ok(());
+    }
+
+    let Some((_, mut meta)) = linked else {
+        let content = client.download_config(api_key, &remote.id).await?;
+        let hash = content_hash(&content);
+        // A local profile differing only in case is the same file on some systems
+        let name = &manager.existing_name(name).unwrap_or_else(|| name.clone());
+
+        if manager.config_exists(name) {
+            // Same name but never linked: adopt it only if it is the same profile
+            if content_hash(&manager.load_config(name)?) == hash {
+                manager.save_meta(name, &synced_meta(remote, &content))?;
+                report.unchanged.push(name.to_string());
+            } else {
+                // Link it so resolve_conflict knows which portal copy to compare with
+                let meta = ProfileMeta {
+                    status: ProfileStatus::Conflict,
+                    remote_id: Some(remote.id.clone()),
+                    synced_hash: None,
+                    remote_hash: Some(hash),
+                    ..manager.load_meta(name)
+                };
+                manager.save_meta(name, &meta)?;
+                report.conflicts.push(name.to_string());
+            }
+            return Ok(());
+        }
+
+        manager.save_config(name, &content, ChangeReason::Synced)?;
+        manager.save_meta(name, &synced_meta(remote, &content))?;
+        report.added.push(name.to_string());
+        return Ok(());
+    };
+
+    let local_hash = content_hash(&manager.load_config(name)?);
+
+    // Without a hash from the portal the content has to be fetched to compare
+    let (remote_hash, downloaded) = match portal_hash(remote) {
+        Some(hash) => (hash, None),
+        None => {
+            let content = client.download_config(api_key, &remote.id).await?;
+            (content_hash(&content), Some(content))
+        }
+    };
+
+    match plan(&meta, &local_hash, &remote_hash) {
+        Plan::Unchanged => {
+            if meta.status != ProfileStatus::Active {
+                meta.status = ProfileStatus::Active;
+                meta.remote_hash = None;
+                manager.save_meta(name, &meta)?;
+            }
+            report.unchanged.push(name.to_string());
+            return Ok(());
+        }
+        Plan::Conflict => {
+            meta.status = ProfileStatus::Conflict;
+            meta.remote_hash = Some(remote_hash);
+            manager.save_meta(name, &meta)?;
+            report.conflicts.push(name.to_string());
+            return Ok(());
+        }
+        Plan::Update => {}
+    }
+
+    let content = match downloaded {
+        Some(content) => content,
+        None => client.download_config(api_key, &remote.id).await?,
+    };
+    manager.save_config(name, &content, ChangeReason::Synced)?;
+    manager.save_meta(
+        name,
+        &ProfileMeta {
+            source: meta.source,
+            username: meta.username,
+            ..synced_meta(remote, &content)
+        },
+    )?;
+    report.updated.push(name.to_string());
+
+    Ok(())
+}
+
+/// Settles a conflict flagged by `sync_configs`, either by taking the portal
+/// version or by keeping the local edits and accepting the portal version as seen.
+pub async fn resolve_conflict(
+    client: &ApiClient,
+    manager: &OpenVpnManager,
+    api_key: &str,
+    name: &ProfileName,
+    keep_remote: bool,
+) -> Result<()> {
+    let mut meta = manager.load_meta(name);
+    if meta.status != ProfileStatus::Conflict {
+        return Err(anyhow::anyhow!("Profile {} has no sync conflict", name));
+    }
+    let remote_id = meta
+        .remote_id
+        .clone()
+        .ok_or_else(|| anyhow::anyhow!("Profile {} is not linked to the portal", name))?;
+
+    if keep_remote {
+        let content = client.download_config(api_key, &remote_id).await?;
+        manager.save_config(name, &content, ChangeReason::Synced)?;
+        meta.synced_hash = Some(content_hash(&content));
+        if let Some(expiry) = profile_expiry(&content, None) {
+            meta.expires_at = Some(expiry.to_rfc3339());
+        }
+    } else {
+        meta.synced_hash = meta.remote_hash.clone();
+    }
+
+    meta.status = ProfileStatus::Active;
+    meta.remote_hash = None;
+    meta.synced_at = Some(chrono::Utc::now().to_rfc3339());
+    manager.save_meta(name, &meta)
+}
+
+/// Runs a sync with the stored API key and reports the outcome to the frontend.
+pub async fn run_and_notify(app: &AppHandle) {
+    let Some(api_key) = app.state::<AppState>().get_api_key() else {
+        return;
+    };
+    let client = app.state::<ApiClient>();
+
+    if let Ok(remaining) = crate::pending::retry_all(&client, &api_key).await {
+        app.emit("pending-operations", remaining).ok();
+    }
+
+    match sync_configs(&client, &OpenVpnManager::new(), &api_key).await {
+        Ok(report) => {
+            app.emit("configs-synced", report).ok();
+        }
+        Err(e) => {
+            app.emit("configs-sync-failed", e.to_string()).ok();
+        }
+    }
+}
+
+/// Syncs on the interval from settings. A settings change restarts the wait
+/// so a new interval or re-enabling sync takes effect right away.
+pub fn spawn_periodic(app: AppHandle) {
+    let mut changes = app.state::<SettingsStore>().subscribe();
+
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let settings = changes.borrow_and_update().clone();
+
+            if !settings.sync_enabled {
+                if changes.changed().await.is_err() {
+                    return;
+                }
+                continue;
+            }
+
+            let interval = Duration::from_secs(settings.sync_interval_minutes * 60);
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => run_and_notify(&app).await,
+                changed = changes.changed() => {
+                    if changed.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn remote(id: &str, name: &str, content: &str) -> RemoteConfig {
+        RemoteConfig {
+            id: id.to_string(),
+            name: name.to_string(),
+            server_code: "de1".to_string(),
+            protocol: "udp".to_string(),
+            content_hash: Some(content_hash(content).to_uppercase()),
+            status: "active".to_string(),
+            expires_at: None,
+        }
+    }
+
+    #[test]
+    fn renewed_profile_follows_the_regenerated_config() {
+        let name = ProfileName::parse("de1").unwrap();
+        // What renew_config leaves behind once linked to the new config
+        let meta = ProfileMeta {
+            remote_id: Some("new".to_string()),
+            synced_hash: Some(content_hash("renewed")),
+            ..Default::default()
+        };
+        let by_remote_id = HashMap::from([("new".to_string(), (name.clone(), meta.clone()))]);
+        let remotes = vec![remote("old", "de1", "expired"), remote("new", "de1", "renewed")];
+
+        let mut report = SyncReport::default();
+        let pairs = pair(&remotes, &by_remote_id, &mut report);
+        assert_eq!(pairs.len(), 1);
+        let (paired, paired_name, linked) = &pairs[0];
+        assert_eq!(paired.id, "new");
+        assert_eq!(paired_name, &name);
+        assert!(linked.is_some());
+        assert!(report.errors.is_empty());
+
+        let remote_hash = portal_hash(paired).unwrap();
+        assert_eq!(plan(&meta, &content_hash("renewed"), &remote_hash), Plan::Unchanged);
+    }
+
+    #[test]
+    fn unlinked_configs_keep_matching_by_name() {
+        let remotes = vec![remote("1", "de1", "a")];
+        let mut report = SyncReport::default();
+        let pairs = pair(&remotes, &HashMap::new(), &mut report);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].1.as_str(), "de1");
+        assert!(pairs[0].2.is_none());
+    }
+
+    #[test]
+    fn configs_named_like_a_linked_profile_are_reported() {
+        let linked = ProfileName::parse("de1").unwrap();
+        let by_remote_id = HashMap::from([("1".to_string(), (linked, ProfileMeta::default()))]);
+        let remotes = vec![remote("1", "de1", "a"), remote("2", "DE1", "b")];
+
+        let mut report = SyncReport::default();
+        let pairs = pair(&remotes, &by_remote_id, &mut report);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].0.id, "1");
+        assert_eq!(report.skipped.len(), 1);
+        assert!(report.skipped[0].starts_with("DE1:"));
+    }
+
+    #[test]
+    fn colliding_unlinked_configs_get_their_own_names() {
+        let remotes = vec![
+            remote("1", "de/1", "a"),
+            remote("2", "de:1", "b"),
+            remote("3", "DE-1", "c"),
+        ];
+        let mut report = SyncReport::default();
+        let pairs = pair(&remotes, &HashMap::new(), &mut report);
+
+        let names: Vec<&str> = pairs.iter().map(|(_, name, _)| name.as_str()).collect();
+        assert_eq!(names, ["de-1", "de-1 (2)", "DE-1 (3)"]);
+        assert!(report.skipped.is_empty() && report.errors.is_empty());
+    }
+
+    #[test]
+    fn plans_linked_profiles() {
+        let meta = ProfileMeta {
+            synced_hash: Some("a".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(plan(&meta, "a", "a"), Plan::Unchanged);
+        assert_eq!(plan(&meta, "a", "b"), Plan::Update);
+        assert_eq!(plan(&meta, "c", "b"), Plan::Conflict);
+        assert_eq!(plan(&meta, "b", "b"), Plan::Update);
+    }
+}
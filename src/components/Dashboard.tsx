@@ -26,6 +26,13 @@ interface Server {
   tcp_port: number;
 }
 
+interface ServerList {
+  servers: Server[];
+  cached: boolean;
+  stale: boolean;
+  fetched_at: string;
+}
+
 interface VpnConfig {
   name: string;
   server: string;
@@ -107,6 +114,16 @@ export default function Dashboard() {
 
   const queryClient = useQueryClient();
 
+  useEffect(() => {
+    const unlisten = listen<ServerList>("servers-updated", (event) => {
+      queryClient.setQueryData(["servers"], event.payload.servers);
+    });
+
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, [queryClient]);
+
   const { data: configs = [] } = useQuery<VpnConfig[]>({
     queryKey: ["configs"],
     queryFn: () => invoke("list_configs"),
@@ -133,13 +150,30 @@ export default function Dashboard() {
 
   const { data: servers = [] } = useQuery<Server[]>({
     queryKey: ["servers"],
-    queryFn: () => invoke("list_servers"),
+    queryFn: () =>
+      invoke<ServerList>("list_servers").then((list) => list.servers),
   });
 
+  const { data: settings } = useQuery<{ status_poll_interval_secs: number }>({
+    queryKey: ["settings"],
+    queryFn: () => invoke("get_settings"),
+  });
+
+  useEffect(() => {
+    const unlisten = listen("settings-changed", (event) => {
+      queryClient.setQueryData(["settings"], event.payload);
+    });
+
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, [queryClient]);
+
+  // The backend derives speeds from this interval, so both must match
   const { data: vpnStatus } = useQuery<VpnConnection | null>({
     queryKey: ["vpn-status"],
     queryFn: () => invoke("get_vpn_status"),
-    refetchInterval: 3000,
+    refetchInterval: (settings?.status_poll_interval_secs ?? 3) * 1000,
   });
 
   const connectMutation = useMutation({
@@ -14,6 +14,13 @@ export interface Server {
   tcp_port: number;
 }
 
+export interface ServerList {
+  servers: Server[];
+  cached: boolean;
+  stale: boolean;
+  fetched_at: string;
+}
+
 export interface VpnConfig {
   name: string;
   server: string;
@@ -21,10 +28,248 @@ export interface VpnConfig {
   created_at: string;
 }
 
+export interface Settings {
+  schema_version: number;
+  openvpn_path: string | null;
+  connect_wait_secs: number;
+  status_poll_interval_secs: number;
+  log_lines: number;
+  persist_tun: boolean;
+  kill_switch: boolean;
+  api_base_url: string | null;
+  api_timeout_secs: number;
+  api_connect_timeout_secs: number;
+  proxy: string | null;
+  sync_enabled: boolean;
+  sync_interval_minutes: number;
+  server_cache_ttl_secs: number;
+  probe_timeout_ms: number;
+  preferred_region: string | null;
+  expiry_warning_days: number;
+  tcp_fallback: boolean;
+  tcp_fallback_timeout_secs: number;
+  quality_monitor: boolean;
+  quality_interval_secs: number;
+  quality_max_rtt_ms: number;
+  quality_max_loss_percent: number;
+  auto_switch_server: boolean;
+  auto_switch_after_secs: number;
+}
+
+export interface ExpiringProfile {
+  name: string;
+  expires_at: string;
+  days_left: number;
+  expired: boolean;
+}
+
+export interface PolicyStatus {
+  managed: boolean;
+  locked_settings: (keyof Settings)[];
+  allowed_servers: string[] | null;
+  allow_imported_profiles: boolean;
+  warnings: string[];
+  error: string | null;
+}
+
+export interface PendingOperation {
+  id: string;
+  profile: string;
+  action: { kind: "revoke"; target: Record<string, string> };
+  created_at: string;
+  attempts: number;
+  last_error: string | null;
+}
+
+export interface DeleteReport {
+  removed: boolean;
+  revoked: boolean;
+  pending: PendingOperation | null;
+  error: string | null;
+}
+
+export type Severity = "info" | "warning" | "error";
+
+export interface Problem {
+  severity: Severity;
+  block: string;
+  message: string;
+}
+
+export interface CertificateInfo {
+  subject: string;
+  issuer: string;
+  subject_alt_names: string[];
+  serial: string;
+  not_before: string;
+  not_after: string;
+  key_type: string;
+  key_bits: number | null;
+  signature_algorithm: string;
+  fingerprint_sha256: string;
+  is_ca: boolean;
+}
+
+export interface ProfileInspection {
+  ca: CertificateInfo[];
+  cert: CertificateInfo | null;
+  key: { format: string; encrypted: boolean } | null;
+  tls_key: { kind: string; valid: boolean } | null;
+  problems: Problem[];
+}
+
+export interface Finding {
+  id: string;
+  severity: Severity;
+  message: string;
+  directive: string | null;
+  fixable: boolean;
+}
+
+export interface AuditReport {
+  findings: Finding[];
+  fixed: Finding[];
+}
+
+export interface NmExport {
+  keyfile: string;
+  files: string[];
+  skipped: string[];
+}
+
+export interface Remote {
+  host: string;
+  port: number | null;
+  proto: string | null;
+}
+
+export interface Directive {
+  name: string;
+  args: string[];
+}
+
+export interface ProfileSettings {
+  proto: string;
+  remotes: Remote[];
+  verb: number | null;
+  directives: Directive[];
+}
+
+export interface ProfileEdit {
+  proto?: string;
+  remotes?: Remote[];
+  verb?: number;
+  set?: Directive[];
+  remove?: string[];
+}
+
+export type ChangeReason =
+  | "generated"
+  | "imported"
+  | "edited"
+  | "fixed"
+  | "renewed"
+  | "synced"
+  | "restored"
+  | "duplicated";
+
+export interface ProfileVersion {
+  id: number;
+  replaced_at: string;
+  reason: ChangeReason;
+  hash: string;
+  size: number;
+}
+
+export interface DiffLine {
+  kind: "same" | "added" | "removed";
+  text: string;
+}
+
+export interface RemoteHealth {
+  successes: number;
+  failures: number;
+  last_success: string | null;
+  last_failure: string | null;
+}
+
+export interface RemoteStatus {
+  host: string;
+  port: number;
+  proto: string;
+  index: number;
+  health: RemoteHealth;
+  in_use: boolean;
+}
+
+export interface ImportReport {
+  source: string;
+  name: string | null;
+  imported: boolean;
+  inlined: string[];
+  unresolved: string[];
+  skipped: boolean;
+  error: string | null;
+}
+
+export interface BundleExport {
+  path: string;
+  profiles: string[];
+  credentials_included: boolean;
+}
+
+export interface BundlePreview {
+  created_at: string;
+  encrypted: boolean;
+  profiles: { name: string; has_credentials: boolean; exists: boolean }[];
+}
+
+export interface BackupReport {
+  path: string;
+  files: number;
+  skipped: string[];
+}
+
+export interface RestoreReport {
+  files: number;
+  created_at: string;
+  app_version: string;
+}
+
+export type Collision =
+  | { action: "skip" }
+  | { action: "overwrite" }
+  | { action: "rename"; name: string };
+
+export interface ConnectionQuality {
+  rtt_ms: number | null;
+  jitter_ms: number | null;
+  loss_percent: number;
+  measurable: boolean;
+  measured_at: string;
+  degraded_since: string | null;
+}
+
+export interface ServerSwitch {
+  from: string;
+  to: string;
+}
+
+export interface SubnetConflict {
+  vpn_network: string;
+  source: "profile" | "pushed" | "tunnel";
+  local_network: string;
+  interface: string;
+  guidance: string;
+}
+
 export interface VpnConnection {
   config_name: string;
   server: string;
+  remote: string | null;
   connected_at: string;
+  quality: ConnectionQuality | null;
+  subnet_conflicts: SubnetConflict[];
   bytes_sent: number;
   bytes_received: number;
 }
@@ -33,7 +278,8 @@ export const api = {
   verifyApiKey: (apiKey: string): Promise<VerifyResponse> =>
     invoke("verify_api_key", { apiKey }),
 
-  listServers: (): Promise<Server[]> => invoke("list_servers"),
+  listServers: (): Promise<Server[]> =>
+    invoke<ServerList>("list_servers").then((list) => list.servers),
 
   generateConfig: (params: {
     username: string;
@@ -46,15 +292,109 @@ export const api = {
   importConfig: (name: string, content: string): Promise<VpnConfig> =>
     invoke("import_config", { name, content }),
 
+  importConfigFromPath: (path: string, name?: string): Promise<ImportReport[]> =>
+    invoke("import_config_from_path", { path, name }),
+
+  importNetworkManager: (path?: string): Promise<ImportReport[]> =>
+    invoke("import_networkmanager", { path }),
+
+  importOpenVpnGui: (dir?: string): Promise<ImportReport[]> =>
+    invoke("import_openvpn_gui", { dir }),
+
+  exportNetworkManager: (name: string, dir: string, includeCredentials?: boolean): Promise<NmExport> =>
+    invoke("export_networkmanager", { name, dir, includeCredentials }),
+
+  exportBundle: (path: string, names?: string[], passphrase?: string): Promise<BundleExport> =>
+    invoke("export_bundle", { names, path, passphrase }),
+
+  previewBundle: (path: string): Promise<BundlePreview> =>
+    invoke("preview_bundle", { path }),
+
+  importBundle: (
+    path: string,
+    passphrase?: string,
+    collisions?: Record<string, Collision>
+  ): Promise<ImportReport[]> =>
+    invoke("import_bundle", { path, passphrase, collisions }),
+
+  backupAppData: (path: string, passphrase: string): Promise<BackupReport> =>
+    invoke("backup_app_data", { path, passphrase }),
+
+  restoreAppData: (path: string, passphrase: string): Promise<RestoreReport> =>
+    invoke("restore_app_data", { path, passphrase }),
+
   listConfigs: (): Promise<VpnConfig[]> => invoke("list_configs"),
 
-  deleteConfig: (name: string): Promise<void> =>
-    invoke("delete_config", { name }),
+  getProfileSettings: (name: string): Promise<ProfileSettings> =>
+    invoke("get_profile_settings", { name }),
+
+  editProfile: (name: string, edit: ProfileEdit): Promise<ProfileSettings> =>
+    invoke("edit_profile", { name, edit }),
+
+  renameConfig: (name: string, newName: string): Promise<void> =>
+    invoke("rename_config", { name, newName }),
+
+  duplicateConfig: (name: string, newName: string): Promise<void> =>
+    invoke("duplicate_config", { name, newName }),
+
+  listProfileVersions: (name: string): Promise<ProfileVersion[]> =>
+    invoke("list_profile_versions", { name }),
+
+  diffProfileVersions: (name: string, from?: number, to?: number): Promise<DiffLine[]> =>
+    invoke("diff_profile_versions", { name, from, to }),
+
+  restoreProfileVersion: (name: string, id: number): Promise<void> =>
+    invoke("restore_profile_version", { name, id }),
+
+  checkSubnetConflicts: (name: string): Promise<SubnetConflict[]> =>
+    invoke("check_subnet_conflicts", { name }),
+
+  getProfileRemotes: (name: string): Promise<RemoteStatus[]> =>
+    invoke("get_profile_remotes", { name }),
+
+  inspectProfile: (name: string): Promise<ProfileInspection> =>
+    invoke("inspect_profile", { name }),
+
+  auditProfile: (name: string): Promise<AuditReport> =>
+    invoke("audit_profile", { name }),
 
-  connectVpn: (configName: string): Promise<void> =>
-    invoke("connect_vpn", { configName }),
+  fixProfile: (name: string): Promise<AuditReport> =>
+    invoke("fix_profile", { name }),
+
+  expiringProfiles: (withinDays?: number): Promise<ExpiringProfile[]> =>
+    invoke("expiring_profiles", { withinDays }),
+
+  renewConfig: (params: {
+    name: string;
+    username?: string;
+    password?: string;
+    expiryDays?: number;
+  }): Promise<VpnConfig> => invoke("renew_config", params),
+
+  deleteConfig: (name: string, revoke = false): Promise<DeleteReport> =>
+    invoke("delete_config", { name, revoke }),
+
+  listPendingOperations: (): Promise<PendingOperation[]> =>
+    invoke("list_pending_operations"),
+
+  retryPendingOperations: (): Promise<PendingOperation[]> =>
+    invoke("retry_pending_operations"),
+
+  discardPendingOperation: (id: string): Promise<void> =>
+    invoke("discard_pending_operation", { id }),
+
+  connectVpn: (configName: string, tcpFallback?: boolean): Promise<void> =>
+    invoke("connect_vpn", { configName, tcpFallback }),
 
   disconnectVpn: (): Promise<void> => invoke("disconnect_vpn"),
 
   getVpnStatus: (): Promise<VpnConnection | null> => invoke("get_vpn_status"),
+
+  getSettings: (): Promise<Settings> => invoke("get_settings"),
+
+  updateSettings: (
+    patch: Partial<Omit<Settings, "schema_version">>
+  ): Promise<Settings> => invoke("update_settings", { patch }),
+
+  getPolicyStatus: (): Promise<PolicyStatus> => invoke("get_policy_status"),
 };